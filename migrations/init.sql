@@ -1,6 +1,41 @@
 CREATE DATABASE connect_management;
 
 \c connect_management
+
+-- 维护窗口日历，任务和group通过calendar_id引用
+CREATE TABLE IF NOT EXISTS calendars
+(
+    id          serial
+        primary key,
+    name        varchar(100)                                                NOT NULL
+        unique,
+    description text,
+    timezone    varchar(64)              DEFAULT 'UTC'                      NOT NULL, -- 窗口的星期和时刻按该时区计算
+    created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP,
+    updated_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP
+);
+
+-- 日历中的窗口：allow 只能在窗口内执行，blackout 窗口内禁止执行；给出的条件同时满足时命中
+CREATE TABLE IF NOT EXISTS calendar_windows
+(
+    id          serial
+        primary key,
+    calendar_id integer                                                     NOT NULL
+        CONSTRAINT fk_calendar
+            REFERENCES calendars(id)
+            ON UPDATE CASCADE ON DELETE CASCADE,
+    kind        varchar(20)                                                 NOT NULL, -- allow / blackout
+    weekdays    integer[], -- 1-7 对应周一到周日，为空表示每天
+    start_time  time, -- 每天的时间段，end_time 小于 start_time 表示跨零点
+    end_time    time,
+    starts_at   timestamp with time zone, -- 绝对时间段，例如节假日封网
+    ends_at     timestamp with time zone,
+    description text,
+    created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_calendar_windows_calendar_id ON calendar_windows(calendar_id);
+
 CREATE SEQUENCE IF NOT EXISTS groups_id_seq;
 
 CREATE TABLE groups
@@ -10,10 +45,21 @@ CREATE TABLE groups
     name        varchar(100)                                                        not null
         unique,
     description text,
+    calendar_id integer
+        CONSTRAINT fk_group_calendar
+            REFERENCES calendars(id)
+            ON UPDATE CASCADE ON DELETE SET NULL, -- 组内的定时任务和批量执行都受这个日历限制
+    max_concurrency integer, -- 组内同时执行的定时任务数上限，为空不限制
+    archived_at timestamp with time zone, -- 删除只做归档，超过保留时间后清理
     created_at  timestamp with time zone default CURRENT_TIMESTAMP,
     updated_at  timestamp with time zone default CURRENT_TIMESTAMP
 );
 
+-- 已有的库：CREATE TABLE不会补上之后新增的列，这里补齐
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS calendar_id integer CONSTRAINT fk_group_calendar REFERENCES calendars(id) ON UPDATE CASCADE ON DELETE SET NULL;
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS max_concurrency integer;
+ALTER TABLE groups ADD COLUMN IF NOT EXISTS archived_at timestamp with time zone;
+
 create table servers
 (
     id            serial
@@ -27,12 +73,98 @@ create table servers
     ip            varchar(45)                                                not null,
     port          integer                  default 22                        not null,
     password_hash text                                                       not null,
+    labels        jsonb                    default '{}'::jsonb               not null, -- 主机标签，命令模板里用 {{label.key}} 或同名参数取值
+    become_method varchar(10), -- 登录后提权执行：sudo / su，为空时不提权
+    become_user   varchar(100), -- 提权的目标用户，默认root
+    become_password_hash text, -- 加密保存的提权密码，为空时使用登录密码，只在执行时通过提示符发送
+    archived_at   timestamp with time zone, -- 删除只做归档，归档的server不再执行任务，超过保留时间后清理
     created_at    timestamp with time zone default CURRENT_TIMESTAMP,
     updated_at    timestamp with time zone default CURRENT_TIMESTAMP,
     constraint unique_ip_port
         unique (ip, port)
 );
 
+-- 已有的库：CREATE TABLE不会补上之后新增的列，这里补齐
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS labels jsonb DEFAULT '{}'::jsonb NOT NULL;
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS become_method varchar(10);
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS become_user varchar(100);
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS become_password_hash text;
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS archived_at timestamp with time zone;
+
+-- 命令模板，例如 systemctl restart {{service}}，参数执行时填入并做shell转义
+CREATE TABLE IF NOT EXISTS command_templates
+(
+    id          serial
+        primary key,
+    name        varchar(100)                                        NOT NULL
+        unique,
+    description text,
+    template    text                                                NOT NULL,
+    params      jsonb                    DEFAULT '[]'::jsonb        NOT NULL, -- [{name, type: string/integer/boolean/choice, required, default, choices}]
+    env         jsonb                    DEFAULT '{}'::jsonb        NOT NULL, -- 执行时的环境变量，值里可以使用占位符
+    workdir     text,                                                         -- 执行时的工作目录，可以使用占位符
+    created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+-- 已有的库：CREATE TABLE不会补上之后新增的列，这里补齐
+ALTER TABLE command_templates ADD COLUMN IF NOT EXISTS env jsonb DEFAULT '{}'::jsonb NOT NULL;
+ALTER TABLE command_templates ADD COLUMN IF NOT EXISTS workdir text;
+
+-- 常用命令库，日常排查用的命令或脚本，可以直接执行也可以作为任务的命令
+CREATE TABLE IF NOT EXISTS runbooks
+(
+    id          serial
+        primary key,
+    name        varchar(100)                                        NOT NULL
+        unique,
+    description text,
+    body        text                                                NOT NULL, -- 单条命令或多行脚本
+    tags        text[]                   DEFAULT '{}'               NOT NULL,
+    server_id   integer
+        CONSTRAINT fk_runbook_server
+            REFERENCES servers(id)
+            ON UPDATE CASCADE ON DELETE SET NULL, -- 默认执行目标，执行时可以覆盖
+    group_id    integer
+        CONSTRAINT fk_runbook_group
+            REFERENCES groups(group_id)
+            ON UPDATE CASCADE ON DELETE SET NULL,
+    selector    jsonb                    DEFAULT '{}'::jsonb        NOT NULL, -- 默认的标签选择器，labels包含这些键值的server
+    created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_runbooks_tags ON runbooks USING gin (tags);
+
+-- 剧本：每台server一个连接，按顺序执行的多个步骤(命令、上传文件、条件检查)
+CREATE TABLE IF NOT EXISTS playbooks
+(
+    id          serial
+        primary key,
+    name        varchar(100)                                        NOT NULL
+        unique,
+    description text,
+    steps       jsonb                    DEFAULT '[]'::jsonb        NOT NULL, -- [{name, type: command/upload/check, continue_on_error, timeout_secs, capture}]
+    created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+-- 保存在服务端的脚本，执行时通过stdin或临时文件传到server上，用指定的解释器执行
+CREATE TABLE IF NOT EXISTS scripts
+(
+    id          serial
+        primary key,
+    name        varchar(100)                                        NOT NULL
+        unique,
+    description text,
+    body        text                                                NOT NULL,
+    interpreter varchar(20)              DEFAULT 'bash'             NOT NULL, -- bash / sh / python3
+    args        text[]                   DEFAULT '{}'               NOT NULL, -- 默认参数
+    delivery    varchar(20)              DEFAULT 'stdin'            NOT NULL, -- stdin / temp_file
+    created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    updated_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
 -- 创建序列
 CREATE SEQUENCE IF NOT EXISTS cron_jobs_id_seq;
 
@@ -43,6 +175,9 @@ CREATE TABLE IF NOT EXISTS cronjobs
         CONSTRAINT cronjobs_pkey PRIMARY KEY,
     name            varchar(255),
     cron_expression varchar(100)                                                           NOT NULL,
+    timezone        varchar(64)              DEFAULT 'UTC'                                 NOT NULL, -- IANA 时区名，cron 表达式按该时区的墙上时间计算
+    schedule_type   varchar(20)              DEFAULT 'cron'                                NOT NULL, -- cron / dependency / once / interval
+    interval_secs   integer, -- interval任务两次执行的间隔秒数，从上一次执行结束开始计算
     server_id       integer
         CONSTRAINT fk_server
             REFERENCES servers(id)
@@ -58,14 +193,112 @@ CREATE TABLE IF NOT EXISTS cronjobs
     description     text,
     last_executed_at timestamp with time zone,
     next_execute_at timestamp with time zone,                                              NOT NULL,
+    misfire_policy  varchar(20)              DEFAULT 'skip'                                NOT NULL, -- skip / run_once / run_all
+    misfire_grace_secs integer               DEFAULT 60                                    NOT NULL,
+    misfire_limit   integer                  DEFAULT 10                                    NOT NULL, -- run_all 最多补跑次数
+    catchup_remaining integer                DEFAULT 0                                     NOT NULL, -- 剩余补跑次数
+    overlap_policy  varchar(20)              DEFAULT 'allow'                               NOT NULL, -- allow / skip / queue / replace
+    retry_backoff   varchar(20)              DEFAULT 'fixed'                               NOT NULL, -- fixed / exponential / jitter
+    retry_delay_ms  integer                  DEFAULT 200                                   NOT NULL,
+    retry_max_delay_ms integer               DEFAULT 60000                                 NOT NULL,
+    retry_on        text[]                   DEFAULT '{connect,auth,timeout,non_zero,output}' NOT NULL, -- 需要重试的失败分类
+    retry_exit_codes integer[], -- 非0退出码中只重试这些，为空重试所有
+    disable_on_failure boolean               DEFAULT false                                 NOT NULL, -- 重试耗尽后是否停用任务
+    success_exit_codes integer[]             DEFAULT '{0}'                                 NOT NULL, -- 视为成功的退出码
+    stdout_must_match text, -- 正则，stdout必须匹配
+    stdout_must_not_match text, -- 正则，stdout不能匹配
+    stderr_must_match text,
+    stderr_must_not_match text,
+    min_success_ratio double precision       DEFAULT 1                                     NOT NULL, -- group任务至少多少比例的server成功
+    archived_at     timestamp with time zone, -- once任务执行后或删除时归档，归档的任务不再调度
+    deleted_at      timestamp with time zone, -- 通过删除接口归档的时间，只有删除的任务超过保留时间后清理
+    calendar_id     integer
+        CONSTRAINT fk_calendar
+            REFERENCES calendars(id)
+            ON UPDATE CASCADE ON DELETE SET NULL,
+    calendar_policy varchar(20)              DEFAULT 'skip'                                NOT NULL, -- 不在日历允许的时间内: skip / delay
+    jitter_secs     integer                  DEFAULT 0                                     NOT NULL, -- 每台server随机延迟的上限，同一server每次相同
+    spread_secs     integer                  DEFAULT 0                                     NOT NULL, -- group内server按任务id和ip散列到这个窗口内启动
+    priority        integer                  DEFAULT 0                                     NOT NULL, -- 多个任务同时到期时优先执行数值大的
+    version         integer                  DEFAULT 1                                     NOT NULL, -- 每次创建/修改/回滚后的版本号，对应cronjob_versions
+    template_id     integer
+        CONSTRAINT fk_command_template
+            REFERENCES command_templates(id)
+            ON UPDATE CASCADE ON DELETE RESTRICT, -- 使用命令模板时command不生效
+    template_params jsonb                    DEFAULT '{}'::jsonb                           NOT NULL, -- 任务定义里的模板参数，运行请求里的同名参数优先
+    runbook_id      integer
+        CONSTRAINT fk_runbook
+            REFERENCES runbooks(id)
+            ON UPDATE CASCADE ON DELETE RESTRICT, -- 使用命令库里的命令，与template_id、playbook_id、script_id只能有一个
+    playbook_id     integer
+        CONSTRAINT fk_playbook
+            REFERENCES playbooks(id)
+            ON UPDATE CASCADE ON DELETE RESTRICT, -- 执行剧本，结果按步骤记录到日志
+    script_id       integer
+        CONSTRAINT fk_script
+            REFERENCES scripts(id)
+            ON UPDATE CASCADE ON DELETE RESTRICT, -- 执行保存的脚本
+    script_args     text[], -- 脚本参数，为空时使用脚本的默认参数
+    become_method   varchar(10), -- 覆盖server上的提权方式：sudo / su，密码使用server上保存的
+    become_user     varchar(100),
+    env             jsonb DEFAULT '{}'::jsonb NOT NULL, -- 执行时的环境变量，覆盖模板里的同名变量
+    secret_env      jsonb DEFAULT '{}'::jsonb NOT NULL, -- 加密保存的环境变量，输出里的值会被遮盖
+    workdir         text, -- 执行时的工作目录
     created_at      timestamp with time zone DEFAULT CURRENT_TIMESTAMP,
     updated_at      timestamp with time zone DEFAULT CURRENT_TIMESTAMP,
     CONSTRAINT check_server_or_group
         CHECK ((server_id IS NOT NULL AND group_id IS NULL) OR
                (server_id IS NULL AND group_id IS NOT NULL) OR
-               (server_id IS NOT NULL AND group_id IS NOT NULL))
+               (server_id IS NOT NULL AND group_id IS NOT NULL)),
+    CONSTRAINT check_interval_secs
+        CHECK (schedule_type <> 'interval' OR interval_secs > 0)
 );
 
+-- 已有的库：CREATE TABLE不会补上之后新增的列，这里补齐
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS timezone varchar(64) DEFAULT 'UTC' NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS schedule_type varchar(20) DEFAULT 'cron' NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS interval_secs integer;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS misfire_policy varchar(20) DEFAULT 'skip' NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS misfire_grace_secs integer DEFAULT 60 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS misfire_limit integer DEFAULT 10 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS catchup_remaining integer DEFAULT 0 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS overlap_policy varchar(20) DEFAULT 'allow' NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS retry_backoff varchar(20) DEFAULT 'fixed' NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS retry_delay_ms integer DEFAULT 200 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS retry_max_delay_ms integer DEFAULT 60000 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS retry_on text[] DEFAULT '{connect,auth,timeout,non_zero,output}' NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS retry_exit_codes integer[];
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS disable_on_failure boolean DEFAULT false NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS success_exit_codes integer[] DEFAULT '{0}' NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS stdout_must_match text;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS stdout_must_not_match text;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS stderr_must_match text;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS stderr_must_not_match text;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS min_success_ratio double precision DEFAULT 1 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS archived_at timestamp with time zone;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS deleted_at timestamp with time zone;
+-- 只有once任务会自动归档，其余已归档的任务都是删除的
+UPDATE cronjobs SET deleted_at = archived_at WHERE deleted_at IS NULL AND archived_at IS NOT NULL AND schedule_type <> 'once';
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS calendar_id integer CONSTRAINT fk_calendar REFERENCES calendars(id) ON UPDATE CASCADE ON DELETE SET NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS calendar_policy varchar(20) DEFAULT 'skip' NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS jitter_secs integer DEFAULT 0 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS spread_secs integer DEFAULT 0 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS priority integer DEFAULT 0 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS version integer DEFAULT 1 NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS template_id integer CONSTRAINT fk_command_template REFERENCES command_templates(id) ON UPDATE CASCADE ON DELETE RESTRICT;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS template_params jsonb DEFAULT '{}'::jsonb NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS runbook_id integer CONSTRAINT fk_runbook REFERENCES runbooks(id) ON UPDATE CASCADE ON DELETE RESTRICT;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS playbook_id integer CONSTRAINT fk_playbook REFERENCES playbooks(id) ON UPDATE CASCADE ON DELETE RESTRICT;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS script_id integer CONSTRAINT fk_script REFERENCES scripts(id) ON UPDATE CASCADE ON DELETE RESTRICT;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS script_args text[];
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS become_method varchar(10);
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS become_user varchar(100);
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS env jsonb DEFAULT '{}'::jsonb NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS secret_env jsonb DEFAULT '{}'::jsonb NOT NULL;
+ALTER TABLE cronjobs ADD COLUMN IF NOT EXISTS workdir text;
+ALTER TABLE cronjobs DROP CONSTRAINT IF EXISTS check_interval_secs;
+ALTER TABLE cronjobs ADD CONSTRAINT check_interval_secs CHECK (schedule_type <> 'interval' OR interval_secs > 0);
+
 -- 创建索引
 CREATE INDEX IF NOT EXISTS idx_cronjobs_server_id ON cronjobs(server_id);
 CREATE INDEX IF NOT EXISTS idx_cronjobs_group_id ON cronjobs(group_id);
@@ -88,6 +321,105 @@ CREATE INDEX IF NOT EXISTS idx_cronjobs_next_execute ON cronjobs(next_execute_at
 -- EXECUTE FUNCTION update_updated_at_column();
 
 
+-- 任务的每一次运行(定时触发或手动触发)，执行日志通过run_id关联到具体某次运行
+CREATE TABLE IF NOT EXISTS cronjob_runs
+(
+    run_id       serial
+        primary key,
+    job_id       integer                                             NOT NULL
+        CONSTRAINT fk_cronjob
+            REFERENCES cronjobs(id)
+            ON UPDATE CASCADE ON DELETE CASCADE,
+    trigger_type varchar(20)                                         NOT NULL, -- schedule / manual / dependency
+    status       varchar(20)                                         NOT NULL, -- queued / running / success / failed / skipped / cancelled
+    command      text, -- 手动运行时覆盖的命令，为空使用任务自身的命令
+    dry_run      boolean                  DEFAULT false              NOT NULL,
+    error        text,
+    workflow_run_id integer, -- 依赖触发的运行，指向工作流起点的run_id
+    upstream_run_id integer, -- 触发本次运行的上游run_id
+    scheduled_at timestamp with time zone, -- 定时运行本该执行的时间，与started_at的差为队列延迟
+    job_version  integer, -- 开始执行时任务的版本号
+    template_params jsonb, -- 手动运行时传入的模板参数，优先于任务定义里的
+    created_at   timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    started_at   timestamp with time zone,
+    finished_at  timestamp with time zone
+);
+
+-- 已有的库：CREATE TABLE不会补上之后新增的列，这里补齐
+ALTER TABLE cronjob_runs ADD COLUMN IF NOT EXISTS workflow_run_id integer;
+ALTER TABLE cronjob_runs ADD COLUMN IF NOT EXISTS upstream_run_id integer;
+ALTER TABLE cronjob_runs ADD COLUMN IF NOT EXISTS scheduled_at timestamp with time zone;
+ALTER TABLE cronjob_runs ADD COLUMN IF NOT EXISTS job_version integer;
+ALTER TABLE cronjob_runs ADD COLUMN IF NOT EXISTS template_params jsonb;
+
+CREATE INDEX IF NOT EXISTS idx_cronjob_runs_job_id ON cronjob_runs(job_id);
+CREATE INDEX IF NOT EXISTS idx_cronjob_runs_status ON cronjob_runs(status);
+CREATE INDEX IF NOT EXISTS idx_cronjob_runs_started_at ON cronjob_runs(started_at);
+-- 同一个工作流里每个任务只触发一次，多个上游同时结束时由它去重
+CREATE UNIQUE INDEX IF NOT EXISTS idx_cronjob_runs_workflow_job ON cronjob_runs(workflow_run_id, job_id)
+    WHERE workflow_run_id IS NOT NULL;
+
+-- 任务依赖，job_id 在 upstream_id 的运行结束后按 trigger_on 触发
+CREATE TABLE IF NOT EXISTS cronjob_dependencies
+(
+    job_id      integer                                             NOT NULL
+        CONSTRAINT fk_cronjob
+            REFERENCES cronjobs(id)
+            ON UPDATE CASCADE ON DELETE CASCADE,
+    upstream_id integer                                             NOT NULL
+        CONSTRAINT fk_upstream
+            REFERENCES cronjobs(id)
+            ON UPDATE CASCADE ON DELETE CASCADE,
+    trigger_on  varchar(20)              DEFAULT 'success'          NOT NULL, -- success / failure / always
+    created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (job_id, upstream_id),
+    CONSTRAINT check_not_self CHECK (job_id <> upstream_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_cronjob_dependencies_upstream ON cronjob_dependencies(upstream_id);
+
+-- 死信：重试耗尽后仍然失败的运行，保留失败原因和最后的输出，可重放或丢弃
+CREATE TABLE IF NOT EXISTS cronjob_dead_letters
+(
+    id            serial
+        primary key,
+    job_id        integer                                             NOT NULL
+        CONSTRAINT fk_cronjob
+            REFERENCES cronjobs(id)
+            ON UPDATE CASCADE ON DELETE CASCADE,
+    run_id        integer
+        CONSTRAINT fk_cronjob_run
+            REFERENCES cronjob_runs(run_id)
+            ON DELETE SET NULL,
+    reason        text                                                NOT NULL,
+    output        text, -- 失败server最后一次执行的输出
+    job_disabled  boolean                  DEFAULT false              NOT NULL, -- 任务是否因这次失败被disable_on_failure停用
+    status        varchar(20)              DEFAULT 'open'             NOT NULL, -- open / replayed / discarded
+    replay_run_id integer, -- 重放时创建的运行
+    created_at    timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    resolved_at   timestamp with time zone
+);
+
+CREATE INDEX IF NOT EXISTS idx_cronjob_dead_letters_job_id ON cronjob_dead_letters(job_id);
+CREATE INDEX IF NOT EXISTS idx_cronjob_dead_letters_status ON cronjob_dead_letters(status);
+
+-- 任务的版本历史，每次创建、修改、回滚保存一份完整的行快照
+CREATE TABLE IF NOT EXISTS cronjob_versions
+(
+    id          serial
+        primary key,
+    job_id      integer                                             NOT NULL
+        CONSTRAINT fk_cronjob
+            REFERENCES cronjobs(id)
+            ON UPDATE CASCADE ON DELETE CASCADE,
+    version     integer                                             NOT NULL,
+    snapshot    jsonb                                               NOT NULL, -- to_jsonb(cronjobs行)
+    changed_by  varchar(100), -- 请求头 X-Changed-By
+    change_note text, -- 例如 restored from version N
+    created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    CONSTRAINT uq_cronjob_version UNIQUE (job_id, version)
+);
+
 CREATE TABLE IF NOT EXISTS cronjob_logs
 (
     log_id      serial
@@ -96,13 +428,78 @@ CREATE TABLE IF NOT EXISTS cronjob_logs
         CONSTRAINT fk_cronjob
             REFERENCES cronjobs(id)
             ON UPDATE CASCADE ON DELETE CASCADE,
+    run_id      integer
+        CONSTRAINT fk_cronjob_run
+            REFERENCES cronjob_runs(run_id)
+            ON DELETE SET NULL,
     server_ip   varchar(45)                                         NOT NULL,
     status      varchar(20)                                         NOT NULL,
     output      text,
     created_at  timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
 );
 
+-- 已有的库：CREATE TABLE不会补上之后新增的列，这里补齐
+ALTER TABLE cronjob_logs ADD COLUMN IF NOT EXISTS run_id integer CONSTRAINT fk_cronjob_run REFERENCES cronjob_runs(run_id) ON DELETE SET NULL;
+
 CREATE INDEX IF NOT EXISTS idx_cronjob_logs_job_id ON cronjob_logs(job_id);
 CREATE INDEX IF NOT EXISTS idx_cronjob_logs_created_at ON cronjob_logs(created_at);
 CREATE INDEX IF NOT EXISTS idx_cronjob_logs_server_ip ON cronjob_logs(server_ip);
-CREATE INDEX IF NOT EXISTS idx_cronjob_logs_status ON cronjob_logs(status);
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS idx_cronjob_logs_status ON cronjob_logs(status);
+CREATE INDEX IF NOT EXISTS idx_cronjob_logs_run_id ON cronjob_logs(run_id);
+
+-- cronjobs 变更通知，worker 通过 LISTEN cronjob_changes 实时同步队列
+CREATE OR REPLACE FUNCTION notify_cronjob_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM pg_notify('cronjob_changes', json_build_object('op', TG_OP, 'id', OLD.id)::text);
+        RETURN OLD;
+    END IF;
+    PERFORM pg_notify('cronjob_changes', json_build_object('op', TG_OP, 'id', NEW.id)::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS cronjobs_notify_change ON cronjobs;
+CREATE TRIGGER cronjobs_notify_change
+AFTER INSERT OR UPDATE OR DELETE ON cronjobs
+FOR EACH ROW
+EXECUTE FUNCTION notify_cronjob_change();
+
+
+-- SCHEDULER_BACKEND=postgres 时使用的队列表，pending/processing 对应 Redis 的两个 zset
+CREATE TABLE IF NOT EXISTS scheduler_queue
+(
+    job_id  integer     NOT NULL,
+    state   varchar(20) NOT NULL, -- pending / processing
+    score   bigint      NOT NULL, -- pending 为执行时间，processing 为超时死线，毫秒时间戳
+    PRIMARY KEY (job_id, state)
+);
+
+CREATE INDEX IF NOT EXISTS idx_scheduler_queue_state_score ON scheduler_queue(state, score);
+
+
+-- SCHEDULER_BACKEND=postgres 时使用的任务锁，防止同一任务在多个 worker 上重叠执行
+CREATE TABLE IF NOT EXISTS scheduler_locks
+(
+    job_id      integer NOT NULL PRIMARY KEY,
+    token       text    NOT NULL,
+    expires_at  bigint  NOT NULL -- 毫秒时间戳
+);
+
+
+-- 手动运行入队通知，worker 通过 LISTEN cronjob_runs 立即认领执行
+CREATE OR REPLACE FUNCTION notify_cronjob_run()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('cronjob_runs', NEW.run_id::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS cronjob_runs_notify_queued ON cronjob_runs;
+CREATE TRIGGER cronjob_runs_notify_queued
+AFTER INSERT ON cronjob_runs
+FOR EACH ROW
+WHEN (NEW.status = 'queued')
+EXECUTE FUNCTION notify_cronjob_run();
@@ -11,7 +11,18 @@ use connect_ok::handler::server::*;
 use connect_ok::handler::cron_log::*;
 use connect_ok::handler::servergroup::*;
 use actix_cors::Cors;
-use connect_ok::handler::cron_job::{create_cronjob, get_all_cronjobs, get_cronjob_by_id, update_cronjob};
+use connect_ok::handler::cron_job::{create_cronjob, get_all_cronjobs, get_cronjob_by_id, update_cronjob, preview_cronjob, enable_cronjob, delete_cronjob, restore_cronjob};
+use connect_ok::handler::archive::purge_archived;
+use connect_ok::handler::command_template::*;
+use connect_ok::handler::runbook::*;
+use connect_ok::handler::playbook::*;
+use connect_ok::handler::script::*;
+use connect_ok::handler::cron_run::{run_cronjob, get_run_by_id, get_runs_by_job_id};
+use connect_ok::handler::workflow::{get_dependencies, add_dependency, delete_dependency, get_workflow_runs};
+use connect_ok::handler::calendar::*;
+use connect_ok::handler::queue::{get_queue, requeue_job, drop_job, resync_queue, get_queue_lag};
+use connect_ok::handler::cron_job_version::{get_cronjob_versions, get_cronjob_version, diff_cronjob_versions, restore_cronjob_version};
+use connect_ok::handler::dead_letter::{get_dead_letters, get_dead_letter_by_id, replay_dead_letter, discard_dead_letter};
 
 #[tokio::main]
 async fn main()  -> std::io::Result<()> {
@@ -38,19 +49,86 @@ async fn main()  -> std::io::Result<()> {
                 .app_data(share_data.clone())
                 .service(
                     web::scope("/group")
-                        .route("", web::get().to(get_all_groups))// 查找所有的group
+                        .route("", web::get().to(get_all_groups))// 查找所有的group，?archived=true 查已归档的
                         .route("",web::post().to(create_group))// 创建group
-                        .route("/{id}",web::delete().to(delete_group_by_id))// 删除group根据group的id
+                        .route("/{id}",web::delete().to(delete_group_by_id))// 归档group，仍有任务引用时返回409，?force=true 一起归档
+                        .route("/{id}/restore",web::post().to(restore_group))// 恢复归档的group
                         .route("/{id}", web::get().to(get_group_by_id))// 查找group根据group的id
                         .route("/{id}", web::put().to(update_group_by_id))// 更新group信息 根据group的id
                 )
+                .service(
+                    web::scope("/template")
+                        .route("",web::get().to(get_all_command_templates))// 查所有命令模板
+                        .route("",web::post().to(create_command_template))// 创建命令模板
+                        .route("/{id}",web::get().to(get_command_template_by_id))
+                        .route("/{id}",web::put().to(update_command_template))// 修改模板，引用它的任务下次运行生效
+                        .route("/{id}",web::delete().to(delete_command_template))// 仍有任务引用时返回409
+                        .route("/{id}/render",web::post().to(render_command_template))// 预览渲染结果，可带server_id
+                )
+                .service(
+                    web::scope("/runbook")
+                        .route("",web::get().to(get_all_runbooks))// 查命令库，?tag=disk 按标签过滤
+                        .route("",web::post().to(create_runbook))
+                        .route("/{id}",web::get().to(get_runbook_by_id))
+                        .route("/{id}",web::put().to(update_runbook))
+                        .route("/{id}",web::delete().to(delete_runbook))// 仍有任务使用时返回409
+                        .route("/{id}/run",web::post().to(run_runbook))// 在server、group或selector选中的server上执行，默认使用保存的目标
+                )
+                .service(
+                    web::scope("/playbook")
+                        .route("",web::get().to(get_all_playbooks))// 查所有剧本
+                        .route("",web::post().to(create_playbook))// 创建剧本，steps按顺序在同一个连接上执行
+                        .route("/{id}",web::get().to(get_playbook_by_id))
+                        .route("/{id}",web::put().to(update_playbook))
+                        .route("/{id}",web::delete().to(delete_playbook))// 仍有任务使用时返回409
+                        .route("/{id}/run",web::post().to(run_playbook))// 在server、group或selector选中的server上执行，按server和步骤返回结果
+                )
+                .service(
+                    web::scope("/script")
+                        .route("",web::get().to(get_all_scripts))// 查所有脚本
+                        .route("",web::post().to(create_script))// 创建脚本，选择解释器和stdin或临时文件方式
+                        .route("/{id}",web::get().to(get_script_by_id))
+                        .route("/{id}",web::put().to(update_script))
+                        .route("/{id}",web::delete().to(delete_script))// 仍有任务使用时返回409
+                )
+                .service(
+                    web::scope("/archive")
+                        .route("/purge",web::post().to(purge_archived)) // ?older_than_days=30 彻底删除归档超过N天的任务、server、group
+                )
+                .service(
+                    web::scope("/scheduler/queue")
+                        .route("",web::get().to(get_queue)) // pending和processing中的任务，到期时间和租约死线
+                        .route("/lag",web::get().to(get_queue_lag)) // 队列延迟，?minutes=60
+                        .route("/resync",web::post().to(resync_queue)) // 以SQL为准全量同步pending
+                        .route("/{job_id}/requeue",web::post().to(requeue_job)) // 放回pending，默认立即执行
+                        .route("/{job_id}",web::delete().to(drop_job)) // 从队列删除，?state=pending|processing
+                )
+                .service(
+                    web::scope("/deadletter")
+                        .route("",web::get().to(get_dead_letters)) // 重试耗尽的失败运行，?status=open&job_id=1
+                        .route("/{id}",web::get().to(get_dead_letter_by_id)) // 失败原因、最后的输出和运行日志
+                        .route("/{id}/replay",web::post().to(replay_dead_letter)) // 以手动运行重新执行，可同时重新启用任务
+                        .route("/{id}/discard",web::post().to(discard_dead_letter)) // 标记为不再处理
+                )
+                .service(
+                    web::scope("/calendar")
+                        .route("",web::get().to(get_all_calendars)) // 所有维护窗口日历
+                        .route("",web::post().to(create_calendar)) // 创建日历
+                        .route("/{id}",web::get().to(get_calendar_by_id)) // 日历和它的所有窗口
+                        .route("/{id}",web::delete().to(delete_calendar)) // 删除日历，引用它的任务和group不再受限制
+                        .route("/{id}/windows",web::post().to(create_calendar_window)) // 添加允许/禁止窗口
+                        .route("/{id}/windows/{window_id}",web::delete().to(delete_calendar_window)) // 删除窗口
+                )
                 .service(
                     web::scope("/server")
-                        .route("",web::get().to(get_all_servers))// 获取所有server
+                        .route("",web::get().to(get_all_servers))// 获取所有server，?archived=true 查已归档的
                         .route("",web::post().to(create_single_server))// 创建单个server
                         .route("/group",web::post().to(create_group_server))// 批量创建server
                         .route("/{id}", web::get().to(get_server_by_id))// 根据server的id查找server
-                        .route("/{id}",web::delete().to(delete_single_server_by_id))// 删除单个server根据server的id
+                        .route("/{id}",web::delete().to(delete_single_server_by_id))// 归档单个server，仍有任务引用时返回409，?force=true 一起归档
+                        .route("/{id}/restore",web::post().to(restore_server))// 恢复归档的server
+                        .route("/{id}/labels",web::put().to(update_server_labels))// 设置server的labels，模板里用{{label.key}}引用
+                        .route("/{id}/become",web::put().to(update_server_become))// 设置sudo/su提权，{"method":null}取消
                         .route("/group/{id}", web::get().to(get_server_by_group_id))// 根据group的id查找server
                 )
                 .service(
@@ -67,8 +145,23 @@ async fn main()  -> std::io::Result<()> {
                 .service(
                     web::scope("/cronjob")
                         .route("",web::post().to(create_cronjob))// 创建cronjob
-                        .route("",web::get().to(get_all_cronjobs)) // 查所有
+                        .route("",web::get().to(get_all_cronjobs)) // 查所有，?archived=true 查已归档的once任务
+                        .route("/preview",web::post().to(preview_cronjob)) // 预览cron表达式的下几次执行时间
+                        .route("/runs/{run_id}",web::get().to(get_run_by_id)) // 查询一次运行的状态和日志
+                        .route("/runs/{run_id}/workflow",web::get().to(get_workflow_runs)) // 工作流起点触发的所有运行
+                        .route("/{id}/run",web::post().to(run_cronjob)) // 立即手动运行一次，返回run_id
+                        .route("/{id}/runs",web::get().to(get_runs_by_job_id)) // 任务的运行记录
+                        .route("/{id}/enable",web::post().to(enable_cronjob)) // 重新启用任务，下次执行时间从现在开始计算
+                        .route("/{id}/restore",web::post().to(restore_cronjob)) // 恢复归档的任务
+                        .route("/{id}/versions",web::get().to(get_cronjob_versions)) // 版本历史，修改人来自请求头 X-Changed-By
+                        .route("/{id}/versions/diff",web::get().to(diff_cronjob_versions)) // ?from=1&to=3 比较两个版本，to省略时与当前比较
+                        .route("/{id}/versions/{version}",web::get().to(get_cronjob_version)) // 某个版本的完整快照
+                        .route("/{id}/versions/{version}/restore",web::post().to(restore_cronjob_version)) // 回滚到某个版本，生成新版本
+                        .route("/{id}/dependencies",web::get().to(get_dependencies)) // 任务的上游
+                        .route("/{id}/dependencies",web::post().to(add_dependency)) // 添加上游，成环返回422
+                        .route("/{id}/dependencies/{upstream_id}",web::delete().to(delete_dependency)) // 删除上游
                         .route("/{id}",web::get().to(get_cronjob_by_id)) // 根据id查
+                        .route("/{id}",web::delete().to(delete_cronjob)) // 归档任务，日志和运行记录保留
                         .route("{id}",web::put().to(update_cronjob)) // 更新cronjob，注意，下次执行时间根据最新的cron表达式更新
                 )
                 .default_service(web::route().to(not_found_handler))
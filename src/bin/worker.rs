@@ -1,78 +1,19 @@
+use std::sync::Arc;
 use dotenvy::dotenv;
 use log::warn;
 use sqlx::PgPool;
-use connect_ok::domain::scheduler::JobScheduler;
+use connect_ok::domain::scheduler::{JobQueue, JobScheduler, PgJobQueue, Scheduler};
 use tracing::{info, debug, error};
-use tokio::signal;
-use connect_ok::repository::cron_job::*;
+use tokio::sync::Semaphore;
 use connect_ok::scheduler::prepare::*;
-use anyhow::Result;
-
-// 业务逻辑抽离出来
-async fn process_job(pool: &PgPool, heap: &JobScheduler, job_id: i32) -> Result<(),anyhow::Error> {
-    // heap.del_job(job_id).await?;
-    info!("job {} start execute", job_id);
-    // let job_log = CreateCronLog::new(job_id, status, output);
-    let msg = get_cronjob_by_id_db(pool, job_id).await?;
-    match msg.group_id {
-        Some(_) => {
-            batch_job_execute(Some(job_id),pool, msg.clone()).await?;
-            heap.del_job(msg.id).await?;// 任务完成 从processing移除
-        },
-        None => {
-            single_job_execute(Some(job_id),pool, msg.clone()).await?;
-            heap.del_job(msg.id).await?;
-            // let (code,output) = single_job_execute(pool, msg.clone()).await?;
-            // let job_log = match single_job_execute(pool, msg.clone()).await{
-            //     Ok((code,output)) => {
-            //         let job_log = CreateCronLog::new(job_id, code.to_string(), Some(output));
-            //         heap.del_job(msg.id).await?;// 任务完成 从processing移除
-            //         job_log
-            //     }
-            //     _ => {
-            //         let job_log = CreateCronLog::new(job_id, "Error".into(),None);
-            //         job_log
-            //     }
-            // };
-            // let _  = create_cron_log_db(pool, job_log).await?;
-        }
-    }
-    reload_single_job(pool, msg.id, heap.clone()).await?;
-    Ok(())
-}
-
-async fn retry_process_job(pool: &PgPool,heap: &JobScheduler, job_id: i32) -> Result<()>{
-    let retry_count = sqlx::query!("select retry_count  from cronjobs where id = $1",job_id)
-        .fetch_one(pool).await    
-        .map(|row| row.retry_count)  // 提取字段
-        .unwrap_or(Some(1));  // 失败时默认值
-    if let Some(retry_count) = retry_count{
-        info!("job{} start retry",job_id);
-        let mut i = 0;
-        while i < retry_count {
-            i += 1;
-            match process_job(pool, heap, job_id).await{
-                Ok(_) => {
-                    info!("job {} retry {} times success",job_id,i); 
-                    return Ok(())
-                }
-                _ => {
-                    error!("job {} retry {} times failed",job_id,i);
-                }
-            }
-            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await; // 间隔200ms，这块的时间可能影响大时间的任务
-            // 任务在重试机制后，如果失败，就再也不会执行了
-        }
-        let _ = sqlx::query!("UPDATE cronjobs SET enabled = $1 WHERE id=$2",false,job_id).execute(pool).await;
-        error!("job {} all retry failed The job has been actively closed by the program",job_id)
-    }
-    else {
-        warn!("job {} Failed & retry count is None",job_id);
-        return Ok(())
-    }
-    Ok(())
-}
+use connect_ok::scheduler::runner::{dispatch_queued_runs, process_job};
+use connect_ok::scheduler::listener::listen_job_changes;
+use connect_ok::scheduler::shutdown::{shutdown_signal, Shutdown};
+use connect_ok::domain::archive::purge_cutoff;
+use connect_ok::repository::archive::purge_archived_db;
 
+// drain超时后留给中断的运行写库、放回队列的时间
+const ABORT_GRACE_SECS: u64 = 10;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
@@ -82,20 +23,68 @@ async fn main() -> Result<(), anyhow::Error> {
     let db_url = std::env::var("DATABASE_URL").expect("notfound env var DATABASE_URL");
     info!("Using DATABASE_URL: {}", &db_url);
     let pool = PgPool::connect(&db_url).await?;
-    let heap = JobScheduler::new().await?;
-    heap.clear_all_jobs().await?; // 清空所有队列
     let reload_sec: u64 = std::env::var("RELOAD_SECS")
         .unwrap_or("100".to_string()).parse().expect("RELOAD_SECS must be number");
     let save_sec: u64 = std::env::var("SAVE_SECS")
         .unwrap_or("300".to_string()).parse().expect("SAVE_SECS must be number");
+    // 同时执行的定时任务数上限，满了之后不再从队列取任务，到期的任务按优先级排队；0为不限制
+    let max_jobs: usize = std::env::var("MAX_CONCURRENT_JOBS")
+        .unwrap_or("0".to_string()).parse().expect("MAX_CONCURRENT_JOBS must be number");
+    // 停机时等待执行中的运行结束的最长时间，超时后中断并放回队列
+    let drain_sec: u64 = std::env::var("DRAIN_TIMEOUT_SECS")
+        .unwrap_or("60".to_string()).parse().expect("DRAIN_TIMEOUT_SECS must be number");
+    // 归档的任务、server、group保留的天数，超过后在reload时彻底删除；0为不自动清理
+    let retention_days: i64 = std::env::var("ARCHIVE_RETENTION_DAYS")
+        .unwrap_or("0".to_string()).parse().expect("ARCHIVE_RETENTION_DAYS must be number");
     info!("Worker reloads every {} secs,Redis save {} secs", reload_sec,save_sec);
+    // 队列后端：redis(默认) / memory(单节点、测试) / postgres(不依赖Redis)
+    let backend = std::env::var("SCHEDULER_BACKEND").unwrap_or("redis".to_string());
+    info!("Using scheduler backend: {}", backend);
+    match backend.as_str() {
+        "redis" => run_worker(pool, JobScheduler::new().await?, reload_sec, save_sec, max_jobs, drain_sec, retention_days).await,
+        "memory" => {
+            warn!("memory backend is not shared between workers, run only one worker");
+            run_worker(pool, Scheduler::new(), reload_sec, save_sec, max_jobs, drain_sec, retention_days).await
+        }
+        "postgres" => {
+            let heap = PgJobQueue::new(pool.clone());
+            run_worker(pool, heap, reload_sec, save_sec, max_jobs, drain_sec, retention_days).await
+        }
+        other => Err(anyhow::anyhow!("Unknown SCHEDULER_BACKEND: {}", other)),
+    }
+}
+
+
+async fn run_worker<Q: JobQueue>(pool: PgPool, heap: Q, reload_sec: u64, save_sec: u64, max_jobs: usize, drain_sec: u64, retention_days: i64) -> Result<(), anyhow::Error> {
+    let shutdown = Shutdown::new();
+    // 队列由所有worker共用，滚动发布时旧worker还在执行，不能清空；已经退出的worker留下的processing超过死线后放回pending
+    let requeued = heap.del_timeout_jobs().await?;
+    if !requeued.is_empty() {
+        warn!("Requeued timed out jobs {:?}", requeued);
+    }
     // 初始化加载
     let pool1 = pool.clone();
     let heap1 = heap.clone();
     // 首次运行 先reload next execute at,如果不这么做，在执行时候，worker会有任务补偿，将所有任务都执行一遍
     let _ = init_job_from_sql(&pool, heap.clone()).await?;
+    // worker不在线期间API入队的手动运行
+    dispatch_queued_runs(&pool, &heap, &shutdown).await?;
 
-    // 定时轮询数据库
+    // 监听cronjobs变更和手动运行，API的增删改、手动运行通过NOTIFY实时同步到worker
+    let listen_pool = pool.clone();
+    let listen_heap = heap.clone();
+    let listen_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = listen_job_changes(&listen_pool, listen_heap.clone(), save_sec, listen_shutdown.clone()).await {
+                error!("Cronjob listener stopped: {:?}", e);
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        }
+    });
+
+    // 定时轮询数据库，作为通知之外的一致性检查
+    let reload_shutdown = shutdown.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(reload_sec));
         interval.tick().await; 
@@ -105,28 +94,61 @@ async fn main() -> Result<(), anyhow::Error> {
                 Ok(_) => info!("Reload job from sql success"),
                 Err(_) => error!("Failed to reload job from sql!!"),
             };
+            // 已经退出的worker留在processing中的任务，超过死线后放回pending
+            match heap1.del_timeout_jobs().await {
+                Ok(jobs) if !jobs.is_empty() => warn!("Requeued timed out jobs {:?}", jobs),
+                Ok(_) => {}
+                Err(e) => error!("Failed to requeue timed out jobs: {:?}", e),
+            }
+            if let Err(e) = dispatch_queued_runs(&pool1, &heap1, &reload_shutdown).await {
+                error!("Failed to dispatch queued runs: {:?}", e);
+            }
+            if retention_days > 0 {
+                let purged = match purge_cutoff(chrono::Utc::now(), retention_days) {
+                    Ok(before) => purge_archived_db(&pool1, before).await,
+                    Err(e) => Err(e),
+                };
+                match purged {
+                    Ok(result) if result.total() > 0 => info!("Purged archived older than {} days: {:?}", retention_days, result),
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to purge archived: {:?}", e),
+                }
+            }
         }
     });
     // worker启动
     let worker_pool = pool.clone();
     let worker_heap = heap.clone();
+    let permits = (max_jobs > 0).then(|| Arc::new(Semaphore::new(max_jobs)));
+    let worker_shutdown = shutdown.clone();
     tokio::spawn(async move {
         loop {
             let worker_pool2 = worker_pool.clone();
             let worker_heap2 =worker_heap.clone();
-            
+            // 先拿到执行名额再取任务，名额用完时任务留在队列里，空出名额后优先级高的先被取走
+            let permit = match &permits {
+                Some(permits) => permits.clone().acquire_owned().await.ok(),
+                None => None,
+            };
+            // 停机中不再从队列取任务，已到期的任务留给其他worker
+            if worker_shutdown.is_draining() {
+                info!("Worker is draining, stop acquiring jobs");
+                break;
+            }
+
             match worker_heap.get_job().await {
                 Ok(Some(job_id)) => {
                     info!("job {} shouled run", job_id);
+                    let shutdown = worker_shutdown.clone();
+                    let guard = shutdown.track();
                     tokio::spawn(async move{
-                        if let Err(e) = process_job(&worker_pool2, &worker_heap2, job_id).await {
+                        if let Err(e) = process_job(&worker_pool2, &worker_heap2, job_id, &shutdown).await {
+                            // server上的失败、执行前的错误在process_job内部记为失败的运行，这里只剩数据库、队列等基础设施错误
+                            // process_job已经尽量推进到下一次执行时间
                             error!("Failed to process job {}: {:?}", job_id, e);
-                            let _ = retry_process_job(&worker_pool2,&worker_heap2, job_id).await;
-                            // 这个retry，最后有关闭enabled的逻辑，小时间的任务失败了不会自回归，关闭enable不影响，大时间的任务关闭了enable下次reload就不会带着他了
-                            // 错误后将任务继续加回。。不需要加回，小时间的重试成功自动加回，重试失败不加回，大时间的重试后等待reload加回，失败后关闭enable避免reload到
-                            // let _ = reload_single_job(&worker_pool2, job_id, worker_heap2.clone()).await;
-                            
                         }
+                        drop(guard);
+                        drop(permit);
                     });
                 }
                 Ok(None) => {
@@ -142,10 +164,20 @@ async fn main() -> Result<(), anyhow::Error> {
         }
     });
 
-    match signal::ctrl_c().await {
-        Ok(()) => info!("Received Ctrl-C, shutting down..."),
+    match shutdown_signal().await {
+        Ok(signal) => info!("Received {}, draining {} running jobs...", signal, shutdown.in_flight()),
         Err(err) => error!("Unable to listen for shutdown signal: {}", err),
     }
-
+    shutdown.drain();
+    if shutdown.wait_idle(tokio::time::Duration::from_secs(drain_sec)).await {
+        info!("All running jobs finished, worker exit");
+        return Ok(());
+    }
+    // drain超时：中断剩余运行，定时任务放回pending，手动运行放回queued
+    warn!("Drain timeout after {} secs, interrupting {} running jobs", drain_sec, shutdown.in_flight());
+    shutdown.abort();
+    if !shutdown.wait_idle(tokio::time::Duration::from_secs(ABORT_GRACE_SECS)).await {
+        error!("{} running jobs not requeued before exit, they will be recovered by lock timeout and reload", shutdown.in_flight());
+    }
     Ok(())
 }
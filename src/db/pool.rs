@@ -1,11 +1,18 @@
+use log::warn;
 use sqlx::PgPool;
+use crate::domain::scheduler::QueueBackend;
 #[derive(Clone,Debug)]
 pub struct AppState {
     pub db_pool : PgPool,
+    pub queue: Option<QueueBackend>, // 和worker共用的调度队列，用于队列管理接口；memory后端或连接失败时为None
 }
 
 impl AppState {
     pub async fn new(db_pool: PgPool) -> Self {
-        Self{db_pool}
+        let queue = QueueBackend::from_env(&db_pool).await.unwrap_or_else(|e| {
+            warn!("Scheduler queue unavailable, queue admin api disabled: {:?}", e);
+            None
+        });
+        Self{db_pool, queue}
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,75 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+// 归档保留天数的上限，防止传入过大的值溢出
+const MAX_RETENTION_DAYS: i64 = 36_500;
+
+// server、group列表的查询参数
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ArchiveQuery {
+    pub archived: Option<bool>, // 查看已归档的
+}
+
+// 删除(归档)server、group的查询参数
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeleteQuery {
+    pub force: Option<bool>, // 仍有任务引用时同时归档这些任务
+}
+
+// 删除仍被任务引用的server、group时返回409，列出引用的任务，确认后带?force=true重新删除
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferencedBy {
+    pub message: String,
+    pub cronjob_ids: Vec<i32>,
+}
+
+// 归档的结果，列出一起归档的任务
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveResult {
+    pub archived_at: DateTime<Utc>,
+    pub archived_cronjob_ids: Vec<i32>,
+}
+
+// 彻底删除归档超过older_than_days天的任务、server、group
+#[derive(Debug, Clone, Deserialize)]
+pub struct PurgeQuery {
+    pub older_than_days: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PurgeResult {
+    pub cronjobs: u64,
+    pub servers: u64,
+    pub groups: u64,
+}
+
+impl PurgeResult {
+    pub fn total(&self) -> u64 {
+        self.cronjobs + self.servers + self.groups
+    }
+}
+
+// 归档时间早于返回值的才会被清理
+pub fn purge_cutoff(now: DateTime<Utc>, older_than_days: i64) -> Result<DateTime<Utc>, anyhow::Error> {
+    if !(1..=MAX_RETENTION_DAYS).contains(&older_than_days) {
+        return Err(anyhow!("older_than_days must be between 1 and {}", MAX_RETENTION_DAYS));
+    }
+    Ok(now - Duration::days(older_than_days))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_purge_cutoff() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 31, 12, 0, 0).unwrap();
+        assert_eq!(purge_cutoff(now, 30).unwrap(), Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap());
+        assert!(purge_cutoff(now, 0).is_err());
+        assert!(purge_cutoff(now, -1).is_err());
+        assert!(purge_cutoff(now, MAX_RETENTION_DAYS + 1).is_err());
+    }
+}
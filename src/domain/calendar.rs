@@ -0,0 +1,317 @@
+use std::str::FromStr;
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::domain::cron_job::parse_timezone;
+
+// 查找下一个允许执行的时间时最多往后找多久，找不到按skip处理
+const MAX_DELAY_DAYS: i64 = 31;
+
+// 任务或group引用的日历，由若干允许窗口和禁止窗口组成
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Calendar {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub timezone: String, // 窗口的星期和时刻按这个时区的墙上时间计算
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCalendar {
+    pub name: String,
+    pub description: Option<String>,
+    pub timezone: Option<String>,
+}
+
+// 一个窗口：所有给出的条件同时满足时命中，都不给表示任何时间都命中
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct CalendarWindow {
+    pub id: i32,
+    pub calendar_id: i32,
+    pub kind: String,
+    pub weekdays: Option<Vec<i32>>,     // 1-7 对应周一到周日
+    pub start_time: Option<NaiveTime>,  // 每天的时间段，end_time小于start_time表示跨过零点
+    pub end_time: Option<NaiveTime>,
+    pub starts_at: Option<DateTime<Utc>>, // 绝对时间段，例如节假日封网
+    pub ends_at: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCalendarWindow {
+    pub kind: WindowKind,
+    pub weekdays: Option<Vec<i32>>,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+}
+
+// 查询日历和它的所有窗口
+#[derive(Debug, Clone, Serialize)]
+pub struct CalendarDetail {
+    #[serde(flatten)]
+    pub calendar: Calendar,
+    pub windows: Vec<CalendarWindow>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowKind {
+    Allow,     // 有允许窗口时，只能在允许窗口内执行
+    Blackout,  // 禁止窗口内不执行，优先于允许窗口
+}
+
+impl WindowKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WindowKind::Allow => "allow",
+            WindowKind::Blackout => "blackout",
+        }
+    }
+}
+
+impl FromStr for WindowKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(WindowKind::Allow),
+            "blackout" => Ok(WindowKind::Blackout),
+            _ => Err(anyhow!("Invalid window kind: {}", s)),
+        }
+    }
+}
+
+// 定时执行的时间不被日历允许时如何处理
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarPolicy {
+    #[default]
+    Skip,   // 跳过这一次，按计划等下一次；once任务没有下一次，总是延后
+    Delay,  // 延后到下一个允许执行的时间
+}
+
+impl CalendarPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CalendarPolicy::Skip => "skip",
+            CalendarPolicy::Delay => "delay",
+        }
+    }
+}
+
+impl FromStr for CalendarPolicy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(CalendarPolicy::Skip),
+            "delay" => Ok(CalendarPolicy::Delay),
+            _ => Err(anyhow!("Invalid calendar policy: {}", s)),
+        }
+    }
+}
+
+impl CreateCalendarWindow {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if let Some(day) = self.weekdays.iter().flatten().find(|day| !(1..=7).contains(*day)) {
+            return Err(anyhow!("Invalid weekday {}, must be 1-7", day));
+        }
+        if self.start_time.is_some() != self.end_time.is_some() {
+            return Err(anyhow!("start_time and end_time must be given together"));
+        }
+        if let (Some(starts_at), Some(ends_at)) = (self.starts_at, self.ends_at)
+            && starts_at >= ends_at
+        {
+            return Err(anyhow!("starts_at must be earlier than ends_at"));
+        }
+        Ok(())
+    }
+}
+
+impl CalendarWindow {
+    fn kind(&self) -> WindowKind {
+        // 入库前已校验，读到未知的值按禁止处理，宁可不执行
+        self.kind.parse().unwrap_or(WindowKind::Blackout)
+    }
+
+    pub fn matches(&self, calendar_tz: &Tz, time: DateTime<Utc>) -> bool {
+        if self.starts_at.is_some_and(|starts_at| time < starts_at) || self.ends_at.is_some_and(|ends_at| time >= ends_at) {
+            return false;
+        }
+        let local = time.with_timezone(calendar_tz);
+        let clock = local.time();
+        let weekday = local.weekday().number_from_monday() as i32;
+        match (self.start_time, self.end_time) {
+            (Some(start), Some(end)) if start <= end => {
+                clock >= start && clock < end && self.on_weekday(weekday)
+            }
+            (Some(start), Some(end)) => {
+                // 跨零点的时间段，零点之后的部分属于前一天的窗口
+                let yesterday = if weekday == 1 { 7 } else { weekday - 1 };
+                (clock >= start && self.on_weekday(weekday)) || (clock < end && self.on_weekday(yesterday))
+            }
+            _ => self.on_weekday(weekday),
+        }
+    }
+
+    fn on_weekday(&self, weekday: i32) -> bool {
+        self.weekdays.as_ref().is_none_or(|days| days.contains(&weekday))
+    }
+
+    // time之后命中与否可能变化的最早时刻：绝对时间段的起止、每天时间段的起止、当地零点(星期变化)
+    fn next_boundary(&self, calendar_tz: &Tz, time: DateTime<Utc>) -> DateTime<Utc> {
+        let mut boundaries = [self.starts_at, self.ends_at].into_iter().flatten().filter(|at| *at > time).collect::<Vec<_>>();
+        let clocks = [Some(NaiveTime::MIN), self.start_time, self.end_time];
+        let today = time.with_timezone(calendar_tz).date_naive();
+        for date in [today, today + Duration::days(1)] {
+            for clock in clocks.iter().flatten() {
+                // 夏令时跳过的时刻不存在，忽略
+                if let Some(at) = calendar_tz.from_local_datetime(&date.and_time(*clock)).earliest()
+                    && at > time
+                {
+                    boundaries.push(at.with_timezone(&Utc));
+                }
+            }
+        }
+        boundaries.into_iter().min().unwrap_or(time + Duration::days(1))
+    }
+}
+
+// 一个日历在某个时间是否允许执行
+#[derive(Debug, Clone)]
+pub struct CalendarRules {
+    pub name: String,
+    pub timezone: String,
+    pub windows: Vec<CalendarWindow>,
+}
+
+impl CalendarRules {
+    // 不允许执行时返回原因
+    pub fn blocked_reason(&self, time: DateTime<Utc>) -> Result<Option<String>, anyhow::Error> {
+        let tz = parse_timezone(&self.timezone)?;
+        if let Some(window) = self.windows.iter().find(|w| w.kind() == WindowKind::Blackout && w.matches(&tz, time)) {
+            let description = window.description.as_deref().unwrap_or("");
+            return Ok(Some(format!("calendar {} blackout window {} {}", self.name, window.id, description).trim_end().to_string()));
+        }
+        let mut allows = self.windows.iter().filter(|w| w.kind() == WindowKind::Allow).peekable();
+        if allows.peek().is_some() && !allows.any(|w| w.matches(&tz, time)) {
+            return Ok(Some(format!("calendar {} outside allowed windows", self.name)));
+        }
+        Ok(None)
+    }
+
+    // time之后下一个窗口边界，允许与否只会在边界上变化
+    fn next_boundary(&self, time: DateTime<Utc>) -> Result<DateTime<Utc>, anyhow::Error> {
+        let tz = parse_timezone(&self.timezone)?;
+        Ok(self.windows.iter().map(|w| w.next_boundary(&tz, time)).min().unwrap_or(time + Duration::days(1)))
+    }
+}
+
+// 所有日历都允许时返回None，否则返回第一个不允许的原因
+pub fn blocked_reason(calendars: &[CalendarRules], time: DateTime<Utc>) -> Result<Option<String>, anyhow::Error> {
+    for calendar in calendars {
+        if let Some(reason) = calendar.blocked_reason(time)? {
+            return Ok(Some(reason));
+        }
+    }
+    Ok(None)
+}
+
+// after之后第一个所有日历都允许的时间，在窗口边界之间跳着找，MAX_DELAY_DAYS内找不到返回None
+pub fn next_allowed_time(calendars: &[CalendarRules], after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    // 窗口的边界精确到分钟，从after之后的整分钟开始找
+    let mut time = after.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(after) + Duration::minutes(1);
+    let deadline = after + Duration::days(MAX_DELAY_DAYS);
+    while time <= deadline {
+        if blocked_reason(calendars, time)?.is_none() {
+            return Ok(Some(time));
+        }
+        let mut next = time + Duration::days(1);
+        for calendar in calendars {
+            next = next.min(calendar.next_boundary(time)?);
+        }
+        time = next;
+    }
+    Ok(None)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn window(kind: WindowKind, weekdays: Option<Vec<i32>>, start: Option<(u32, u32)>, end: Option<(u32, u32)>) -> CalendarWindow {
+        CalendarWindow {
+            id: 1,
+            calendar_id: 1,
+            kind: kind.as_str().to_string(),
+            weekdays,
+            start_time: start.map(|(h, m)| NaiveTime::from_hms_opt(h, m, 0).unwrap()),
+            end_time: end.map(|(h, m)| NaiveTime::from_hms_opt(h, m, 0).unwrap()),
+            starts_at: None,
+            ends_at: None,
+            description: None,
+            created_at: None,
+        }
+    }
+
+    fn rules(windows: Vec<CalendarWindow>) -> CalendarRules {
+        CalendarRules { name: "ops".to_string(), timezone: "UTC".to_string(), windows }
+    }
+
+    #[test]
+    fn test_allow_window() {
+        // 工作日 01:00-05:00，2026-01-05 是周一
+        let calendar = rules(vec![window(WindowKind::Allow, Some(vec![1, 2, 3, 4, 5]), Some((1, 0)), Some((5, 0)))]);
+        assert!(calendar.blocked_reason(Utc.with_ymd_and_hms(2026, 1, 5, 2, 0, 0).unwrap()).unwrap().is_none());
+        assert!(calendar.blocked_reason(Utc.with_ymd_and_hms(2026, 1, 5, 5, 0, 0).unwrap()).unwrap().is_some());
+        assert!(calendar.blocked_reason(Utc.with_ymd_and_hms(2026, 1, 4, 2, 0, 0).unwrap()).unwrap().is_some());
+        assert_eq!(
+            next_allowed_time(&[calendar], Utc.with_ymd_and_hms(2026, 1, 3, 12, 30, 15).unwrap()).unwrap(),
+            Some(Utc.with_ymd_and_hms(2026, 1, 5, 1, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_blackout_window() {
+        // 周日 22:00 到周一 02:00 封网，跨零点的部分属于周日的窗口
+        let mut freeze = window(WindowKind::Blackout, Some(vec![7]), Some((22, 0)), Some((2, 0)));
+        freeze.description = Some("weekly freeze".to_string());
+        let calendar = rules(vec![freeze]);
+        assert!(calendar.blocked_reason(Utc.with_ymd_and_hms(2026, 1, 4, 23, 0, 0).unwrap()).unwrap().is_some());
+        assert!(calendar.blocked_reason(Utc.with_ymd_and_hms(2026, 1, 5, 1, 59, 0).unwrap()).unwrap().is_some());
+        assert!(calendar.blocked_reason(Utc.with_ymd_and_hms(2026, 1, 5, 2, 0, 0).unwrap()).unwrap().is_none());
+        assert!(calendar.blocked_reason(Utc.with_ymd_and_hms(2026, 1, 6, 1, 0, 0).unwrap()).unwrap().is_none());
+
+        let mut holiday = window(WindowKind::Blackout, None, None, None);
+        holiday.starts_at = Some(Utc.with_ymd_and_hms(2026, 12, 24, 0, 0, 0).unwrap());
+        holiday.ends_at = Some(Utc.with_ymd_and_hms(2026, 12, 27, 0, 0, 0).unwrap());
+        let calendar = rules(vec![holiday]);
+        assert!(calendar.blocked_reason(Utc.with_ymd_and_hms(2026, 12, 25, 3, 0, 0).unwrap()).unwrap().is_some());
+        assert_eq!(
+            next_allowed_time(&[calendar], Utc.with_ymd_and_hms(2026, 12, 25, 3, 0, 0).unwrap()).unwrap(),
+            Some(Utc.with_ymd_and_hms(2026, 12, 27, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_next_allowed_time_boundaries() {
+        // 上海时区每周三 23:30 到次日 00:15 允许，2026-01-05 是周一
+        let mut calendar = rules(vec![window(WindowKind::Allow, Some(vec![3]), Some((23, 30)), Some((0, 15)))]);
+        calendar.timezone = "Asia/Shanghai".to_string();
+        assert_eq!(
+            next_allowed_time(std::slice::from_ref(&calendar), Utc.with_ymd_and_hms(2026, 1, 5, 0, 0, 0).unwrap()).unwrap(),
+            Some(Utc.with_ymd_and_hms(2026, 1, 7, 15, 30, 0).unwrap())
+        );
+        // 一直禁止时找到MAX_DELAY_DAYS为止
+        let calendar = rules(vec![window(WindowKind::Blackout, None, None, None)]);
+        assert_eq!(next_allowed_time(&[calendar], Utc.with_ymd_and_hms(2026, 1, 5, 0, 0, 0).unwrap()).unwrap(), None);
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::types::Json;
+use crate::domain::environment::{validate_env, Environment};
+use crate::domain::server::ServiceTerminal;
+
+// 占位符里的主机变量前缀：{{host.ip}}、{{label.env}}
+const HOST_PREFIX: &str = "host.";
+const LABEL_PREFIX: &str = "label.";
+const HOST_FIELDS: [&str; 5] = ["id", "name", "ip", "port", "user"];
+// 不需要引号的字符，其余的值一律用单引号包起来
+const SHELL_SAFE_CHARS: &str = "_-./:=@%+,";
+
+// 保存的命令模板，例如 systemctl restart {{service}}，参数在执行时填入并做shell转义
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandTemplate {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub template: String,
+    pub params: Json<Vec<TemplateParam>>,
+    pub env: Json<HashMap<String, String>>, // 执行时的环境变量，值里可以使用占位符
+    pub workdir: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateParam {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub kind: ParamType,
+    #[serde(default = "default_required")]
+    pub required: bool,           // 没有取到值也没有默认值时报错，默认必填
+    pub default: Option<Value>,
+    pub choices: Option<Vec<String>>, // choice类型的可选值
+    pub description: Option<String>,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamType {
+    #[default]
+    String,
+    Integer,
+    Boolean,
+    Choice,
+}
+
+impl ParamType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Integer => "integer",
+            ParamType::Boolean => "boolean",
+            ParamType::Choice => "choice",
+        }
+    }
+}
+
+impl FromStr for ParamType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(ParamType::String),
+            "integer" => Ok(ParamType::Integer),
+            "boolean" => Ok(ParamType::Boolean),
+            "choice" => Ok(ParamType::Choice),
+            other => Err(anyhow!("unknown param type: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateCommandTemplate {
+    pub name: String,
+    pub description: Option<String>,
+    pub template: String,
+    #[serde(default)]
+    pub params: Vec<TemplateParam>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub workdir: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateCommandTemplate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub template: Option<String>,
+    pub params: Option<Vec<TemplateParam>>,
+    pub env: Option<HashMap<String, String>>,
+    pub workdir: Option<String>,  // 空字符串表示清除
+}
+
+// 预览渲染结果，给出server_id时可以使用主机变量和标签
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RenderTemplate {
+    #[serde(default)]
+    pub params: HashMap<String, Value>,
+    pub server_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedCommand {
+    pub template_id: i32,
+    pub server_id: Option<i32>,
+    pub command: String,
+    pub env: BTreeMap<String, String>,
+    pub workdir: Option<String>,
+}
+
+// 按优先级排列的参数来源：运行请求、任务定义，之后是主机标签和默认值
+pub type ParamValues<'a> = [&'a Map<String, Value>];
+
+impl CommandTemplate {
+    /// 在一台主机上渲染，values靠前的优先；所有替换进去的值都经过shell转义
+    pub fn render(&self, values: &ParamValues, host: Option<&ServiceTerminal>) -> Result<String, anyhow::Error> {
+        let labels = host.map(host_labels).unwrap_or_default();
+        let resolved = self.resolve(values, &labels)?;
+        fill(&self.template, &resolved, host, &labels, true)
+    }
+
+    // 环境变量和工作目录按同样的规则填入参数，但不做shell转义，执行时再整体转义
+    pub fn render_env(&self, values: &ParamValues, host: Option<&ServiceTerminal>) -> Result<Environment, anyhow::Error> {
+        let labels = host.map(host_labels).unwrap_or_default();
+        let resolved = self.resolve(values, &labels)?;
+        let vars = self.env.iter()
+            .map(|(name, value)| Ok((name.clone(), fill(value, &resolved, host, &labels, false)?)))
+            .collect::<Result<HashMap<_, _>, anyhow::Error>>()?;
+        let workdir = self.workdir.as_deref().map(|workdir| fill(workdir, &resolved, host, &labels, false)).transpose()?;
+        Ok(Environment::new(vars, workdir))
+    }
+
+    fn resolve(&self, values: &ParamValues, labels: &HashMap<String, String>) -> Result<HashMap<&str, Option<String>>, anyhow::Error> {
+        let mut resolved = HashMap::new();
+        for param in self.params.iter() {
+            let value = values.iter().find_map(|layer| layer.get(&param.name).filter(|v| !v.is_null()).cloned())
+                .or_else(|| labels.get(&param.name).map(|v| Value::String(v.clone())))
+                .or_else(|| param.default.clone());
+            match value {
+                Some(value) => { resolved.insert(param.name.as_str(), Some(coerce(param, &value)?)); }
+                None if param.required => return Err(anyhow!("missing value for param {}", param.name)),
+                None => { resolved.insert(param.name.as_str(), None); }
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+fn fill(text: &str, resolved: &HashMap<&str, Option<String>>, host: Option<&ServiceTerminal>, labels: &HashMap<String, String>, quote: bool) -> Result<String, anyhow::Error> {
+    let mut filled = String::with_capacity(text.len());
+    for segment in parse_template(text)? {
+        match segment {
+            Segment::Text(text) => filled.push_str(text),
+            Segment::Var(name) => {
+                let value = match resolved.get(name) {
+                    Some(value) => value.clone(),
+                    None => Some(host_var(name, host, labels)?),
+                };
+                // 可选参数没有取到值时替换为空，而不是''
+                match value {
+                    Some(value) if quote => filled.push_str(&shell_quote(&value)),
+                    Some(value) => filled.push_str(&value),
+                    None => {}
+                }
+            }
+        }
+    }
+    Ok(filled)
+}
+
+// 创建、修改模板时检查参数声明和模板里的占位符
+pub fn validate_template(template: &str, params: &[TemplateParam]) -> Result<(), anyhow::Error> {
+    if template.trim().is_empty() {
+        return Err(anyhow!("template must not be empty"));
+    }
+    let mut names = HashSet::new();
+    for param in params {
+        if !valid_name(&param.name) || param.name.contains('.') {
+            return Err(anyhow!("invalid param name: {}", param.name));
+        }
+        if !names.insert(param.name.as_str()) {
+            return Err(anyhow!("duplicate param: {}", param.name));
+        }
+        if param.kind == ParamType::Choice && param.choices.as_ref().is_none_or(|c| c.is_empty()) {
+            return Err(anyhow!("param {} of type choice requires choices", param.name));
+        }
+        if let Some(default) = &param.default {
+            coerce(param, default)?;
+        }
+    }
+    check_placeholders(template, &names)
+}
+
+// 模板上的环境变量和工作目录：变量名合法，里面的占位符都已声明
+pub fn validate_template_env(env: &HashMap<String, String>, workdir: Option<&str>, params: &[TemplateParam]) -> Result<(), anyhow::Error> {
+    validate_env(env)?;
+    let names = params.iter().map(|p| p.name.as_str()).collect::<HashSet<_>>();
+    for value in env.values().map(String::as_str).chain(workdir) {
+        check_placeholders(value, &names)?;
+    }
+    Ok(())
+}
+
+fn check_placeholders(text: &str, names: &HashSet<&str>) -> Result<(), anyhow::Error> {
+    for segment in parse_template(text)? {
+        if let Segment::Var(name) = segment {
+            let declared = names.contains(name);
+            let host = name.strip_prefix(HOST_PREFIX).is_some_and(|field| HOST_FIELDS.contains(&field));
+            let label = name.strip_prefix(LABEL_PREFIX).is_some_and(|key| !key.is_empty());
+            if !declared && !host && !label {
+                return Err(anyhow!("undeclared param in template: {}", name));
+            }
+        }
+    }
+    Ok(())
+}
+
+// 任务或运行请求里给出的值：必须是声明过的参数，类型正确
+pub fn validate_values(params: &[TemplateParam], values: &Map<String, Value>) -> Result<(), anyhow::Error> {
+    for (name, value) in values {
+        let param = params.iter().find(|p| &p.name == name).ok_or_else(|| anyhow!("unknown param: {}", name))?;
+        if !value.is_null() {
+            coerce(param, value)?;
+        }
+    }
+    Ok(())
+}
+
+// 标签的key要能写进 {{label.key}} 占位符
+pub fn validate_labels(labels: &HashMap<String, String>) -> Result<(), anyhow::Error> {
+    match labels.keys().find(|key| !valid_name(key)) {
+        Some(key) => Err(anyhow!("invalid label key: {}", key)),
+        None => Ok(()),
+    }
+}
+
+// 单引号包起来，值里的单引号写成 '\''
+pub fn shell_quote(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || SHELL_SAFE_CHARS.contains(c)) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+// 按参数类型检查并转成字符串
+fn coerce(param: &TemplateParam, value: &Value) -> Result<String, anyhow::Error> {
+    let invalid = || anyhow!("param {} expects {}, got {}", param.name, param.kind.as_str(), value);
+    match (param.kind, value) {
+        (ParamType::Integer, Value::Number(n)) if n.is_i64() => Ok(n.to_string()),
+        (ParamType::Integer, Value::String(s)) => s.trim().parse::<i64>().map(|n| n.to_string()).map_err(|_| invalid()),
+        (ParamType::Boolean, Value::Bool(b)) => Ok(b.to_string()),
+        (ParamType::Boolean, Value::String(s)) => s.trim().parse::<bool>().map(|b| b.to_string()).map_err(|_| invalid()),
+        (ParamType::String, Value::String(s)) => Ok(s.clone()),
+        (ParamType::String, Value::Number(_) | Value::Bool(_)) => Ok(value.to_string()),
+        (ParamType::Choice, Value::String(s)) => {
+            if param.choices.as_ref().is_some_and(|choices| choices.contains(s)) {
+                Ok(s.clone())
+            } else {
+                Err(anyhow!("param {} must be one of {:?}, got {}", param.name, param.choices.as_deref().unwrap_or_default(), s))
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn host_labels(server: &ServiceTerminal) -> HashMap<String, String> {
+    server.labels.as_object().map(|labels| {
+        labels.iter().map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (key.clone(), value)
+        }).collect()
+    }).unwrap_or_default()
+}
+
+fn host_var(name: &str, host: Option<&ServiceTerminal>, labels: &HashMap<String, String>) -> Result<String, anyhow::Error> {
+    let host = host.ok_or_else(|| anyhow!("{} requires a target server", name))?;
+    if let Some(key) = name.strip_prefix(LABEL_PREFIX) {
+        return labels.get(key).cloned().ok_or_else(|| anyhow!("server {} has no label {}", host.ip, key));
+    }
+    match name.strip_prefix(HOST_PREFIX) {
+        Some("id") => Ok(host.id.to_string()),
+        Some("name") => Ok(host.name.clone().unwrap_or_default()),
+        Some("ip") => Ok(host.ip.clone()),
+        Some("port") => Ok(host.port.to_string()),
+        Some("user") => Ok(host.ssh_user.clone()),
+        _ => Err(anyhow!("undeclared param in template: {}", name)),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Var(&'a str),
+}
+
+// 拆成文本和 {{name}} 占位符，占位符两侧可以有空格
+pub fn parse_template(template: &str) -> Result<Vec<Segment<'_>>, anyhow::Error> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| anyhow!("unclosed {{{{ in template"))?;
+        let name = after[..end].trim();
+        if !valid_name(name) {
+            return Err(anyhow!("invalid placeholder: {{{{{}}}}}", &after[..end]));
+        }
+        segments.push(Segment::Var(name));
+        rest = &after[end + 2..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+pub fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn param(name: &str, kind: ParamType) -> TemplateParam {
+        TemplateParam { name: name.to_string(), kind, required: true, default: None, choices: None, description: None }
+    }
+
+    fn template(text: &str, params: Vec<TemplateParam>) -> CommandTemplate {
+        CommandTemplate {
+            id: 1,
+            name: "t".to_string(),
+            description: None,
+            template: text.to_string(),
+            params: Json(params),
+            env: Json(HashMap::new()),
+            workdir: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn server(labels: Value) -> ServiceTerminal {
+        ServiceTerminal {
+            id: 7,
+            name: Some("web 1".to_string()),
+            group_id: None,
+            ssh_user: "root".to_string(),
+            ip: "10.0.0.1".to_string(),
+            port: 22,
+            password: String::new(),
+            labels,
+            archived_at: None,
+            become_method: None,
+            become_user: None,
+            become_password: None,
+        }
+    }
+
+    fn values(value: Value) -> Map<String, Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("nginx"), "nginx");
+        assert_eq!(shell_quote("/var/log/app.log"), "/var/log/app.log");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("x; rm -rf /"), "'x; rm -rf /'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$(id)"), "'$(id)'");
+    }
+
+    #[test]
+    fn test_render_layers_and_escape() {
+        let t = template("systemctl restart {{ service }} && echo {{host.name}}", vec![param("service", ParamType::String)]);
+        let request = values(json!({"service": "nginx; reboot"}));
+        let job = values(json!({"service": "nginx"}));
+        let host = server(json!({}));
+        assert_eq!(t.render(&[&request, &job], Some(&host)).unwrap(), "systemctl restart 'nginx; reboot' && echo 'web 1'");
+        assert_eq!(t.render(&[&job], Some(&host)).unwrap(), "systemctl restart nginx && echo 'web 1'");
+    }
+
+    #[test]
+    fn test_render_host_labels_and_default() {
+        let mut port = param("port", ParamType::Integer);
+        port.default = Some(json!(8080));
+        let t = template("deploy {{service}} {{port}} {{label.env}}", vec![param("service", ParamType::String), port]);
+        let host = server(json!({"service": "api", "env": "prod"}));
+        assert_eq!(t.render(&[], Some(&host)).unwrap(), "deploy api 8080 prod");
+        assert!(t.render(&[], None).is_err());
+    }
+
+    #[test]
+    fn test_render_type_checks() {
+        let mut mode = param("mode", ParamType::Choice);
+        mode.choices = Some(vec!["fast".to_string(), "safe".to_string()]);
+        let t = template("run {{count}} {{mode}} {{dry}}", vec![param("count", ParamType::Integer), mode, param("dry", ParamType::Boolean)]);
+        let ok = values(json!({"count": "3", "mode": "safe", "dry": true}));
+        assert_eq!(t.render(&[&ok], None).unwrap(), "run 3 safe true");
+        assert!(t.render(&[&values(json!({"count": "3;id", "mode": "safe", "dry": true}))], None).is_err());
+        assert!(t.render(&[&values(json!({"count": 3, "mode": "rm", "dry": true}))], None).is_err());
+        assert!(t.render(&[&values(json!({"count": 3, "mode": "fast"}))], None).is_err());
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template("echo {{name}}", &[param("name", ParamType::String)]).is_ok());
+        assert!(validate_template("echo {{host.ip}} {{label.env}}", &[]).is_ok());
+        assert!(validate_template("echo {{name}}", &[]).is_err());
+        assert!(validate_template("echo {{name", &[param("name", ParamType::String)]).is_err());
+        assert!(validate_template("echo {{host.secret}}", &[]).is_err());
+        assert!(validate_template("echo", &[param("a", ParamType::String), param("a", ParamType::String)]).is_err());
+        assert!(validate_template("echo", &[param("mode", ParamType::Choice)]).is_err());
+    }
+
+    #[test]
+    fn test_render_env() {
+        let mut t = template("make", vec![param("release", ParamType::String)]);
+        t.env = Json(HashMap::from([("RELEASE".to_string(), "{{release}}-{{label.env}}".to_string())]));
+        t.workdir = Some("/srv/{{ release }}".to_string());
+        let host = server(json!({"env": "prod"}));
+        let env = t.render_env(&[&values(json!({"release": "v1 beta"}))], Some(&host)).unwrap();
+        // 不在这里转义，执行时整体转义
+        assert_eq!(env.vars.get("RELEASE").map(String::as_str), Some("v1 beta-prod"));
+        assert_eq!(env.workdir.as_deref(), Some("/srv/v1 beta"));
+        let params = [param("release", ParamType::String)];
+        assert!(validate_template_env(&t.env, t.workdir.as_deref(), &params).is_ok());
+        assert!(validate_template_env(&t.env, Some("/srv/{{other}}"), &params).is_err());
+        assert!(validate_template_env(&HashMap::from([("BAD-NAME".to_string(), String::new())]), None, &params).is_err());
+    }
+
+    #[test]
+    fn test_validate_labels() {
+        let ok = HashMap::from([("env".to_string(), "prod".to_string()), ("app-name".to_string(), "api".to_string())]);
+        assert!(validate_labels(&ok).is_ok());
+        let bad = HashMap::from([("bad key".to_string(), "x".to_string())]);
+        assert!(validate_labels(&bad).is_err());
+    }
+
+    #[test]
+    fn test_validate_values() {
+        let params = vec![param("count", ParamType::Integer)];
+        assert!(validate_values(&params, &values(json!({"count": 1}))).is_ok());
+        assert!(validate_values(&params, &values(json!({"count": "x"}))).is_err());
+        assert!(validate_values(&params, &values(json!({"other": 1}))).is_err());
+    }
+}
@@ -1,23 +1,79 @@
+use std::collections::HashMap;
+use std::str::FromStr;
 use actix_web::web;
 use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Local, Utc};
+use serde_json::Value;
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
 use sqlx::FromRow;
 use cron_parser::parse;
+use crate::domain::calendar::CalendarPolicy;
+use crate::domain::privilege::{BecomeMethod, BecomeSpec};
+use crate::domain::retry::RetryBackoff;
+use crate::domain::ssh_session::FailureClass;
+
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+pub const DEFAULT_MISFIRE_GRACE_SECS: i32 = 60;
+pub const DEFAULT_MISFIRE_LIMIT: i32 = 10;
+// 统计错过的轮次时的上限，防止每秒级任务停机很久后循环过多
+const MAX_MISSED_TICKS: usize = 10_000;
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct CronJob {
     pub id: i32,
     pub name: Option<String>,
     pub cron_expression: String,
+    pub timezone: String,
+    pub schedule_type: String,
+    pub interval_secs: Option<i32>,
     pub server_id: Option<i32>,
     pub group_id: Option<i32>,
-    pub command: String,
+    #[serde(default)]
+    pub command: String,                // 引用模板、命令库、剧本或脚本时可以省略
     pub enabled: bool,
     pub timeout: Option<i32>,
     pub retry_count: Option<i32>,
     pub description: Option<String>,
     pub last_executed_at: Option<DateTime<Utc>>,
     pub next_execute_at: DateTime<Utc>,
+    pub misfire_policy: String,
+    pub misfire_grace_secs: i32,
+    pub misfire_limit: i32,
+    pub catchup_remaining: i32,
+    pub overlap_policy: String,
+    pub retry_backoff: String,
+    pub retry_delay_ms: i32,
+    pub retry_max_delay_ms: i32,
+    pub retry_on: Vec<String>,
+    pub retry_exit_codes: Option<Vec<i32>>,
+    pub disable_on_failure: bool,
+    pub success_exit_codes: Vec<i32>,
+    pub stdout_must_match: Option<String>,
+    pub stdout_must_not_match: Option<String>,
+    pub stderr_must_match: Option<String>,
+    pub stderr_must_not_match: Option<String>,
+    pub min_success_ratio: f64,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>, // 删除接口归档的时间，once任务执行后的自动归档没有
+    pub calendar_id: Option<i32>,
+    pub calendar_policy: String,
+    pub jitter_secs: i32,
+    pub spread_secs: i32,
+    pub priority: i32,
+    pub template_id: Option<i32>,
+    pub template_params: Value,
+    pub runbook_id: Option<i32>,
+    pub playbook_id: Option<i32>,
+    pub script_id: Option<i32>,
+    pub script_args: Option<Vec<String>>,
+    pub become_method: Option<String>,
+    pub become_user: Option<String>,
+    pub env: Value,
+    #[serde(skip_serializing, default)]
+    pub secret_env: Value,          // 值加密保存，不返回给客户端
+    pub workdir: Option<String>,
+    pub version: i32,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
@@ -29,6 +85,9 @@ impl TryFrom<web::Json<CronJob>> for CronJob {
             id : json.id,
             name: json.name.clone(),
             cron_expression: json.cron_expression.clone(),
+            timezone: json.timezone.clone(),
+            schedule_type: json.schedule_type.clone(),
+            interval_secs: json.interval_secs,
             server_id: json.server_id,
             group_id: json.group_id,
             command: json.command.clone(),
@@ -38,6 +97,42 @@ impl TryFrom<web::Json<CronJob>> for CronJob {
             description: json.description.clone(),
             last_executed_at: json.last_executed_at.clone(),
             next_execute_at: json.next_execute_at.clone(),
+            misfire_policy: json.misfire_policy.clone(),
+            misfire_grace_secs: json.misfire_grace_secs,
+            misfire_limit: json.misfire_limit,
+            catchup_remaining: json.catchup_remaining,
+            overlap_policy: json.overlap_policy.clone(),
+            retry_backoff: json.retry_backoff.clone(),
+            retry_delay_ms: json.retry_delay_ms,
+            retry_max_delay_ms: json.retry_max_delay_ms,
+            retry_on: json.retry_on.clone(),
+            retry_exit_codes: json.retry_exit_codes.clone(),
+            disable_on_failure: json.disable_on_failure,
+            success_exit_codes: json.success_exit_codes.clone(),
+            stdout_must_match: json.stdout_must_match.clone(),
+            stdout_must_not_match: json.stdout_must_not_match.clone(),
+            stderr_must_match: json.stderr_must_match.clone(),
+            stderr_must_not_match: json.stderr_must_not_match.clone(),
+            min_success_ratio: json.min_success_ratio,
+            archived_at: json.archived_at,
+            deleted_at: json.deleted_at,
+            calendar_id: json.calendar_id,
+            calendar_policy: json.calendar_policy.clone(),
+            jitter_secs: json.jitter_secs,
+            spread_secs: json.spread_secs,
+            priority: json.priority,
+            template_id: json.template_id,
+            template_params: json.template_params.clone(),
+            runbook_id: json.runbook_id,
+            playbook_id: json.playbook_id,
+            script_id: json.script_id,
+            script_args: json.script_args.clone(),
+            become_method: json.become_method.clone(),
+            become_user: json.become_user.clone(),
+            env: json.env.clone(),
+            secret_env: json.secret_env.clone(),
+            workdir: json.workdir.clone(),
+            version: json.version,
             created_at: json.created_at.clone(),
             updated_at: json.updated_at.clone()
         })
@@ -48,7 +143,12 @@ impl TryFrom<web::Json<CronJob>> for CronJob {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CreateCronJob {
     pub name: Option<String>,
-    pub cron_expression: String,
+    #[serde(default)]
+    pub cron_expression: String, // schedule_type不是cron时可以省略
+    pub timezone: Option<String>,
+    pub schedule_type: Option<ScheduleType>,
+    pub run_at: Option<DateTime<Utc>>,  // once任务的执行时间；interval任务的第一次执行时间，默认创建后interval_secs秒
+    pub interval_secs: Option<i32>,     // interval任务两次执行的间隔，从上一次执行结束开始计算
     pub server_id: Option<i32>,
     pub group_id: Option<i32>,
     pub command: String,
@@ -56,6 +156,39 @@ pub struct CreateCronJob {
     pub timeout: Option<i32>,
     pub retry_count: Option<i32>,
     pub description: Option<String>,
+    pub misfire_policy: Option<MisfirePolicy>,
+    pub misfire_grace_secs: Option<i32>,
+    pub misfire_limit: Option<i32>,
+    pub overlap_policy: Option<OverlapPolicy>,
+    pub retry_backoff: Option<RetryBackoff>,
+    pub retry_delay_ms: Option<i32>,
+    pub retry_max_delay_ms: Option<i32>,
+    pub retry_on: Option<Vec<FailureClass>>, // 哪些失败需要重试，默认全部
+    pub retry_exit_codes: Option<Vec<i32>>,  // 非0退出码中只重试这些
+    pub disable_on_failure: Option<bool>,    // 重试耗尽后停用任务，默认不停用
+    pub success_exit_codes: Option<Vec<i32>>, // 视为成功的退出码，默认[0]
+    pub stdout_must_match: Option<String>,
+    pub stdout_must_not_match: Option<String>,
+    pub stderr_must_match: Option<String>,
+    pub stderr_must_not_match: Option<String>,
+    pub min_success_ratio: Option<f64>,       // 至少多少比例的server成功，默认1
+    pub calendar_id: Option<i32>,             // 引用的维护窗口日历
+    pub calendar_policy: Option<CalendarPolicy>, // 不在日历允许的时间内时跳过还是延后，默认跳过
+    pub jitter_secs: Option<i32>,             // 定时运行时每台server随机延迟的上限，默认0
+    pub spread_secs: Option<i32>,             // 定时运行时server按任务id和ip散列到这个窗口内启动，默认0
+    pub priority: Option<i32>,                // 多个任务同时到期时优先执行数值大的，默认0
+    pub template_id: Option<i32>,             // 引用的命令模板，设置后执行渲染出来的命令
+    pub template_params: Option<HashMap<String, Value>>, // 任务定义里的模板参数
+    pub runbook_id: Option<i32>,              // 执行命令库里的命令，与template_id、playbook_id、script_id只能有一个
+    pub playbook_id: Option<i32>,             // 执行剧本
+    pub script_id: Option<i32>,               // 执行保存的脚本
+    pub script_args: Option<Vec<String>>,     // 脚本参数，默认使用脚本的默认参数
+    pub become_method: Option<BecomeMethod>,  // 覆盖server上的提权方式，密码使用server上保存的
+    pub become_user: Option<String>,          // 提权的目标用户，默认root
+    pub env: Option<HashMap<String, String>>, // 执行时的环境变量，覆盖模板里的同名变量
+    #[serde(skip_serializing)]
+    pub secret_env: Option<HashMap<String, String>>, // 加密保存的环境变量，值在输出和日志里遮盖
+    pub workdir: Option<String>,              // 执行时的工作目录，覆盖模板里的
     #[serde(skip_deserializing)]
     pub next_execute_at: DateTime<Utc>,
 }
@@ -65,6 +198,10 @@ impl TryFrom<web::Json<CreateCronJob>> for CreateCronJob {
         Ok(CreateCronJob{
             name: json.name.clone(),
             cron_expression: json.cron_expression.clone(),
+            timezone: json.timezone.clone(),
+            schedule_type: json.schedule_type,
+            run_at: json.run_at,
+            interval_secs: json.interval_secs,
             server_id: json.server_id,
             group_id: json.group_id,
             command: json.command.clone(),
@@ -72,6 +209,38 @@ impl TryFrom<web::Json<CreateCronJob>> for CreateCronJob {
             timeout: json.timeout,
             retry_count: json.retry_count,
             description: json.description.clone(),
+            misfire_policy: json.misfire_policy,
+            misfire_grace_secs: json.misfire_grace_secs,
+            misfire_limit: json.misfire_limit,
+            overlap_policy: json.overlap_policy,
+            retry_backoff: json.retry_backoff,
+            retry_delay_ms: json.retry_delay_ms,
+            retry_max_delay_ms: json.retry_max_delay_ms,
+            retry_on: json.retry_on.clone(),
+            retry_exit_codes: json.retry_exit_codes.clone(),
+            disable_on_failure: json.disable_on_failure,
+            success_exit_codes: json.success_exit_codes.clone(),
+            stdout_must_match: json.stdout_must_match.clone(),
+            stdout_must_not_match: json.stdout_must_not_match.clone(),
+            stderr_must_match: json.stderr_must_match.clone(),
+            stderr_must_not_match: json.stderr_must_not_match.clone(),
+            min_success_ratio: json.min_success_ratio,
+            calendar_id: json.calendar_id,
+            calendar_policy: json.calendar_policy,
+            jitter_secs: json.jitter_secs,
+            spread_secs: json.spread_secs,
+            priority: json.priority,
+            template_id: json.template_id,
+            template_params: json.template_params.clone(),
+            runbook_id: json.runbook_id,
+            playbook_id: json.playbook_id,
+            script_id: json.script_id,
+            script_args: json.script_args.clone(),
+            become_method: json.become_method,
+            become_user: json.become_user.clone(),
+            env: json.env.clone(),
+            secret_env: json.secret_env.clone(),
+            workdir: json.workdir.clone(),
             next_execute_at: json.next_execute_at.clone(),
         })
     }
@@ -82,6 +251,10 @@ impl TryFrom<web::Json<CreateCronJob>> for CreateCronJob {
 pub struct UpdateCronJob {
     pub name: Option<String>,
     pub cron_expression: Option<String>,
+    pub timezone: Option<String>,
+    pub schedule_type: Option<ScheduleType>,
+    pub run_at: Option<DateTime<Utc>>,
+    pub interval_secs: Option<i32>,
     pub server_id: Option<i32>,
     pub group_id: Option<i32>,
     pub command: Option<String>,
@@ -89,6 +262,39 @@ pub struct UpdateCronJob {
     pub timeout: Option<i32>,
     pub retry_count: Option<i32>,
     pub description: Option<String>,
+    pub misfire_policy: Option<MisfirePolicy>,
+    pub misfire_grace_secs: Option<i32>,
+    pub misfire_limit: Option<i32>,
+    pub overlap_policy: Option<OverlapPolicy>,
+    pub retry_backoff: Option<RetryBackoff>,
+    pub retry_delay_ms: Option<i32>,
+    pub retry_max_delay_ms: Option<i32>,
+    pub retry_on: Option<Vec<FailureClass>>, // 哪些失败需要重试，默认全部
+    pub retry_exit_codes: Option<Vec<i32>>,  // 非0退出码中只重试这些
+    pub disable_on_failure: Option<bool>,    // 重试耗尽后停用任务，默认不停用
+    pub success_exit_codes: Option<Vec<i32>>, // 视为成功的退出码，默认[0]
+    pub stdout_must_match: Option<String>,
+    pub stdout_must_not_match: Option<String>,
+    pub stderr_must_match: Option<String>,
+    pub stderr_must_not_match: Option<String>,
+    pub min_success_ratio: Option<f64>,       // 至少多少比例的server成功，默认1
+    pub calendar_id: Option<i32>,             // 引用的维护窗口日历
+    pub calendar_policy: Option<CalendarPolicy>, // 不在日历允许的时间内时跳过还是延后，默认跳过
+    pub jitter_secs: Option<i32>,             // 定时运行时每台server随机延迟的上限，默认0
+    pub spread_secs: Option<i32>,             // 定时运行时server按任务id和ip散列到这个窗口内启动，默认0
+    pub priority: Option<i32>,                // 多个任务同时到期时优先执行数值大的，默认0
+    pub template_id: Option<i32>,             // 引用的命令模板，传0取消引用
+    pub template_params: Option<HashMap<String, Value>>,
+    pub runbook_id: Option<i32>,              // 执行命令库里的命令，传0取消
+    pub playbook_id: Option<i32>,             // 执行剧本，传0取消
+    pub script_id: Option<i32>,               // 执行保存的脚本，传0取消
+    pub script_args: Option<Vec<String>>,
+    pub become_method: Option<String>,        // sudo或su，传空字符串取消，server上的设置继续生效
+    pub become_user: Option<String>,
+    pub env: Option<HashMap<String, String>>, // 整体替换
+    #[serde(skip_serializing)]
+    pub secret_env: Option<HashMap<String, String>>, // 整体替换，传空对象清除
+    pub workdir: Option<String>,              // 传空字符串取消
     #[serde(skip_deserializing)]
     pub next_execute_at: Option<DateTime<Utc>>,
 }
@@ -99,6 +305,10 @@ impl TryFrom<web::Json<UpdateCronJob>> for UpdateCronJob {
         Ok(UpdateCronJob{
             name: json.name.clone(),
             cron_expression: json.cron_expression.clone(),
+            timezone: json.timezone.clone(),
+            schedule_type: json.schedule_type,
+            run_at: json.run_at,
+            interval_secs: json.interval_secs,
             server_id: json.server_id,
             group_id: json.group_id,
             command: json.command.clone(),
@@ -106,6 +316,38 @@ impl TryFrom<web::Json<UpdateCronJob>> for UpdateCronJob {
             timeout: json.timeout,
             retry_count: json.retry_count,
             description: json.description.clone(),
+            misfire_policy: json.misfire_policy,
+            misfire_grace_secs: json.misfire_grace_secs,
+            misfire_limit: json.misfire_limit,
+            overlap_policy: json.overlap_policy,
+            retry_backoff: json.retry_backoff,
+            retry_delay_ms: json.retry_delay_ms,
+            retry_max_delay_ms: json.retry_max_delay_ms,
+            retry_on: json.retry_on.clone(),
+            retry_exit_codes: json.retry_exit_codes.clone(),
+            disable_on_failure: json.disable_on_failure,
+            success_exit_codes: json.success_exit_codes.clone(),
+            stdout_must_match: json.stdout_must_match.clone(),
+            stdout_must_not_match: json.stdout_must_not_match.clone(),
+            stderr_must_match: json.stderr_must_match.clone(),
+            stderr_must_not_match: json.stderr_must_not_match.clone(),
+            min_success_ratio: json.min_success_ratio,
+            calendar_id: json.calendar_id,
+            calendar_policy: json.calendar_policy,
+            jitter_secs: json.jitter_secs,
+            spread_secs: json.spread_secs,
+            priority: json.priority,
+            template_id: json.template_id,
+            template_params: json.template_params.clone(),
+            runbook_id: json.runbook_id,
+            playbook_id: json.playbook_id,
+            script_id: json.script_id,
+            script_args: json.script_args.clone(),
+            become_method: json.become_method.clone(),
+            become_user: json.become_user.clone(),
+            env: json.env.clone(),
+            secret_env: json.secret_env.clone(),
+            workdir: json.workdir.clone(),
             next_execute_at: json.next_execute_at.clone(),
 
         })
@@ -114,13 +356,267 @@ impl TryFrom<web::Json<UpdateCronJob>> for UpdateCronJob {
 
 
 
+// 任务列表的查询参数
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CronJobQuery {
+    pub archived: Option<bool>, // 查看已归档的once任务
+}
+
+
+// worker停机期间错过的轮次如何处理
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MisfirePolicy {
+    #[default]
+    Skip,     // 全部跳过，从现在开始的下一次执行
+    RunOnce,  // 立即补跑一次
+    RunAll,   // 每个错过的轮次都补跑，最多 misfire_limit 次
+}
+
+impl MisfirePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MisfirePolicy::Skip => "skip",
+            MisfirePolicy::RunOnce => "run_once",
+            MisfirePolicy::RunAll => "run_all",
+        }
+    }
+
+    // 错过 missed 个轮次时需要补跑的次数
+    pub fn runs(&self, missed: usize, limit: i32) -> usize {
+        match self {
+            MisfirePolicy::Skip => 0,
+            MisfirePolicy::RunOnce => missed.min(1),
+            MisfirePolicy::RunAll => missed.min(limit.max(1) as usize),
+        }
+    }
+}
+
+impl FromStr for MisfirePolicy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(MisfirePolicy::Skip),
+            "run_once" => Ok(MisfirePolicy::RunOnce),
+            "run_all" => Ok(MisfirePolicy::RunAll),
+            _ => Err(anyhow!("Invalid misfire policy: {}", s)),
+        }
+    }
+}
+
+// 任务如何被触发
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleType {
+    #[default]
+    Cron,        // 按cron表达式定时执行
+    Dependency,  // 不进入调度队列，只由上游任务的运行结果触发
+    Once,        // 在run_at执行一次，执行后自动归档
+    Interval,    // 每次执行结束后interval_secs秒再执行
+}
+
+impl ScheduleType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleType::Cron => "cron",
+            ScheduleType::Dependency => "dependency",
+            ScheduleType::Once => "once",
+            ScheduleType::Interval => "interval",
+        }
+    }
+}
+
+impl FromStr for ScheduleType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cron" => Ok(ScheduleType::Cron),
+            "dependency" => Ok(ScheduleType::Dependency),
+            "once" => Ok(ScheduleType::Once),
+            "interval" => Ok(ScheduleType::Interval),
+            _ => Err(anyhow!("Invalid schedule type: {}", s)),
+        }
+    }
+}
+
+// 同一任务上一轮还在执行时，新一轮如何处理
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    #[default]
+    Allow,    // 允许同时执行
+    Skip,     // 跳过新一轮
+    Queue,    // 新一轮等上一轮结束后再执行
+    Replace,  // 取消上一轮，执行新一轮
+}
+
+impl OverlapPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverlapPolicy::Allow => "allow",
+            OverlapPolicy::Skip => "skip",
+            OverlapPolicy::Queue => "queue",
+            OverlapPolicy::Replace => "replace",
+        }
+    }
+}
+
+impl FromStr for OverlapPolicy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(OverlapPolicy::Allow),
+            "skip" => Ok(OverlapPolicy::Skip),
+            "queue" => Ok(OverlapPolicy::Queue),
+            "replace" => Ok(OverlapPolicy::Replace),
+            _ => Err(anyhow!("Invalid overlap policy: {}", s)),
+        }
+    }
+}
+
+pub fn parse_timezone(timezone: &str) -> Result<Tz, anyhow::Error> {
+    timezone.parse::<Tz>().map_err(|e| anyhow!("Invalid timezone {}: {}", timezone, e))
+}
+
+// after之后的下一次执行时间，cron表达式按timezone的墙上时间匹配
+// 夏令时开始时被跳过的时间，按切换前的偏移换算(落在跳变之后执行一次)
+// 夏令时结束时重复的时间，只在第一次出现时执行
+pub fn next_fire_time(cron_expression: &str, timezone: &str, after: DateTime<Utc>) -> Result<DateTime<Utc>, anyhow::Error> {
+    let tz = parse_timezone(timezone)?;
+    // 把本地墙上时间当作UTC交给cron_parser，避免它自己处理时区
+    let mut wall = after.with_timezone(&tz).naive_local();
+    loop {
+        wall = parse(cron_expression, &Utc.from_utc_datetime(&wall))
+            .map_err(|e| anyhow!("Invalid cron expression: {}", e))?
+            .naive_utc();
+        let fire = match tz.from_local_datetime(&wall) {
+            LocalResult::Single(time) => time.with_timezone(&Utc),
+            LocalResult::Ambiguous(earliest, _) if earliest > after => earliest.with_timezone(&Utc),
+            // after已经处在重复的第二个小时里，剩下的时间第一次出现都已经过去，从重复时段结束处继续找
+            LocalResult::Ambiguous(earliest, latest) => {
+                wall = repeated_end(&tz, wall, latest - earliest) - Duration::seconds(1);
+                continue;
+            }
+            LocalResult::None => {
+                let offset = tz.offset_from_utc_datetime(&(wall - Duration::days(1))).fix();
+                Utc.from_utc_datetime(&(wall - Duration::seconds(offset.local_minus_utc() as i64)))
+            }
+        };
+        return Ok(fire);
+    }
+}
+
+// 包含wall的重复时段结束后的第一个墙上时间，精确到秒；重复时段不超过gap(两次出现的间隔)
+fn repeated_end(tz: &Tz, wall: NaiveDateTime, gap: Duration) -> NaiveDateTime {
+    let (mut low, mut high) = (wall, wall + gap);
+    while high - low > Duration::seconds(1) {
+        let mid = low + (high - low) / 2;
+        if matches!(tz.from_local_datetime(&mid), LocalResult::Ambiguous(..)) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    high
+}
+
+// 从 first(本该执行但没执行的时间) 开始到 now 为止错过的所有轮次
+pub fn missed_ticks(cron_expression: &str, timezone: &str, first: DateTime<Utc>, now: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, anyhow::Error> {
+    let mut ticks = Vec::new();
+    let mut tick = first;
+    while tick <= now && ticks.len() < MAX_MISSED_TICKS {
+        ticks.push(tick);
+        tick = next_fire_time(cron_expression, timezone, tick)?;
+    }
+    Ok(ticks)
+}
+
+// 按调度方式计算after之后的下一次执行时间，once和dependency没有下一次
+pub fn next_schedule_time(
+    schedule_type: ScheduleType,
+    cron_expression: &str,
+    timezone: &str,
+    interval_secs: Option<i32>,
+    after: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    match schedule_type {
+        ScheduleType::Cron => Ok(Some(next_fire_time(cron_expression, timezone, after)?)),
+        ScheduleType::Interval => match interval_secs {
+            Some(secs) if secs > 0 => Ok(Some(after + Duration::seconds(secs as i64))),
+            _ => Err(anyhow!("interval_secs must be greater than 0")),
+        },
+        ScheduleType::Once | ScheduleType::Dependency => Ok(None),
+    }
+}
+
+// once任务必须给出run_at，interval任务必须给出大于0的interval_secs
+pub fn validate_schedule(schedule_type: ScheduleType, run_at: Option<DateTime<Utc>>, interval_secs: Option<i32>) -> Result<(), anyhow::Error> {
+    match schedule_type {
+        ScheduleType::Once if run_at.is_none() => Err(anyhow!("run_at is required for once job")),
+        ScheduleType::Interval if interval_secs.is_none_or(|secs| secs <= 0) => {
+            Err(anyhow!("interval_secs must be greater than 0 for interval job"))
+        }
+        _ => Ok(()),
+    }
+}
+
+
+// cronjobs表触发器通过 NOTIFY 发出的变更事件，payload为 {"op":"INSERT","id":1}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CronJobOp {
+    Insert,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CronJobChange {
+    pub op: CronJobOp,
+    pub id: i32,
+}
+
 
 pub trait CronJobExecutor {
     fn get_cron_expression(&self) -> &str;
+    fn get_timezone(&self) -> &str;
     fn next_tick(&self) ->Result<DateTime<Utc>,anyhow::Error> {
-        let time = parse(self.get_cron_expression(), &Local::now())
-        .map_err(|e| anyhow!("Invalid cron expression: {}", e))?;
-        Ok(time.with_timezone(&Utc))
+        next_fire_time(self.get_cron_expression(), self.get_timezone(), Utc::now())
+    }
+}
+
+impl CronJob {
+    // 是否由调度队列按时间触发，dependency任务只由上游触发，已归档的once任务不再触发
+    pub fn is_scheduled(&self) -> bool {
+        self.schedule_type != ScheduleType::Dependency.as_str() && self.archived_at.is_none()
+    }
+
+    // 任务上设置的提权方式，没有时使用server上的
+    pub fn become_spec(&self) -> Result<Option<BecomeSpec>, anyhow::Error> {
+        BecomeSpec::from_columns(self.become_method.as_deref(), self.become_user.as_deref())
+    }
+
+    // 定时运行重试耗尽后是否停用任务，once任务不会再执行，失败后同样归档
+    pub fn disables_on_failure(&self) -> bool {
+        self.disable_on_failure && self.schedule_type != ScheduleType::Once.as_str()
+    }
+
+    pub fn next_schedule_time(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+        next_schedule_time(self.schedule_type.parse()?, &self.cron_expression, &self.timezone, self.interval_secs, after)
+    }
+
+    // 从next_execute_at开始到now为止错过的轮次，once任务最多一次
+    pub fn missed_ticks(&self, now: DateTime<Utc>) -> Result<Vec<DateTime<Utc>>, anyhow::Error> {
+        let mut ticks = Vec::new();
+        let mut tick = Some(self.next_execute_at);
+        while let Some(current) = tick {
+            if current > now || ticks.len() >= MAX_MISSED_TICKS {
+                break;
+            }
+            ticks.push(current);
+            tick = self.next_schedule_time(current)?;
+        }
+        Ok(ticks)
     }
 }
 
@@ -128,10 +624,95 @@ impl CronJobExecutor for CronJob {
     fn get_cron_expression(&self) -> &str {
         &self.cron_expression
     }
+    fn get_timezone(&self) -> &str {
+        &self.timezone
+    }
 }
 
 impl CronJobExecutor for CreateCronJob {
     fn get_cron_expression(&self) -> &str {
         &self.cron_expression
     }
-}
\ No newline at end of file
+    fn get_timezone(&self) -> &str {
+        self.timezone.as_deref().unwrap_or(DEFAULT_TIMEZONE)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_misfire_policy_runs() {
+        assert_eq!(MisfirePolicy::Skip.runs(5, 10), 0);
+        assert_eq!(MisfirePolicy::RunOnce.runs(5, 10), 1);
+        assert_eq!(MisfirePolicy::RunOnce.runs(0, 10), 0);
+        assert_eq!(MisfirePolicy::RunAll.runs(5, 3), 3);
+        assert_eq!(MisfirePolicy::RunAll.runs(2, 3), 2);
+        assert_eq!("run_all".parse::<MisfirePolicy>().unwrap(), MisfirePolicy::RunAll);
+        assert!("later".parse::<MisfirePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_missed_ticks() {
+        let first = Utc.with_ymd_and_hms(2026, 1, 1, 10, 0, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 12, 30, 0).unwrap();
+        let ticks = missed_ticks("0 * * * *", "UTC", first, now).unwrap();
+        assert_eq!(ticks, vec![
+            first,
+            Utc.with_ymd_and_hms(2026, 1, 1, 11, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap(),
+        ]);
+        assert!(missed_ticks("0 * * * *", "UTC", now, first).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_next_schedule_time() {
+        let after = Utc.with_ymd_and_hms(2026, 1, 1, 10, 0, 0).unwrap();
+        assert_eq!(next_schedule_time(ScheduleType::Interval, "", "UTC", Some(90), after).unwrap(), Some(after + Duration::seconds(90)));
+        assert!(next_schedule_time(ScheduleType::Interval, "", "UTC", Some(0), after).is_err());
+        assert_eq!(next_schedule_time(ScheduleType::Once, "", "UTC", None, after).unwrap(), None);
+        assert_eq!(next_schedule_time(ScheduleType::Cron, "0 * * * *", "UTC", None, after).unwrap(), Some(after + Duration::hours(1)));
+        assert!(validate_schedule(ScheduleType::Once, None, None).is_err());
+        assert!(validate_schedule(ScheduleType::Interval, None, Some(-1)).is_err());
+        assert!(validate_schedule(ScheduleType::Interval, None, Some(60)).is_ok());
+    }
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_next_fire_time_in_timezone() {
+        // 纽约 09:00，冬令时为 UTC-5，2026-03-08 起夏令时为 UTC-4
+        assert_eq!(next_fire_time("0 9 * * *", "America/New_York", utc(2026, 3, 6, 15, 0)).unwrap(), utc(2026, 3, 7, 14, 0));
+        assert_eq!(next_fire_time("0 9 * * *", "America/New_York", utc(2026, 3, 7, 15, 0)).unwrap(), utc(2026, 3, 8, 13, 0));
+        assert!(next_fire_time("0 9 * * *", "Mars/Olympus", utc(2026, 3, 6, 15, 0)).is_err());
+    }
+
+    #[test]
+    fn test_next_fire_time_dst_start() {
+        // 柏林 2026-03-29 02:00 跳到 03:00，02:30 不存在，在跳变后(03:30 CEST)执行
+        assert_eq!(next_fire_time("30 2 * * *", "Europe/Berlin", utc(2026, 3, 28, 12, 0)).unwrap(), utc(2026, 3, 29, 1, 30));
+        assert_eq!(next_fire_time("30 2 * * *", "Europe/Berlin", utc(2026, 3, 29, 1, 30)).unwrap(), utc(2026, 3, 30, 0, 30));
+        // 每小时的任务跨过跳变不会重复执行
+        assert_eq!(next_fire_time("0 * * * *", "Europe/Berlin", utc(2026, 3, 29, 0, 30)).unwrap(), utc(2026, 3, 29, 1, 0));
+        assert_eq!(next_fire_time("0 * * * *", "Europe/Berlin", utc(2026, 3, 29, 1, 0)).unwrap(), utc(2026, 3, 29, 2, 0));
+    }
+
+    #[test]
+    fn test_next_fire_time_dst_end() {
+        // 柏林 2026-10-25 03:00 回拨到 02:00，02:30 出现两次，只在第一次(CEST)执行
+        assert_eq!(next_fire_time("30 2 * * *", "Europe/Berlin", utc(2026, 10, 24, 12, 0)).unwrap(), utc(2026, 10, 25, 0, 30));
+        assert_eq!(next_fire_time("30 2 * * *", "Europe/Berlin", utc(2026, 10, 25, 0, 30)).unwrap(), utc(2026, 10, 26, 1, 30));
+        // 处在重复的第二个小时里时，下一次是 03:00 CET
+        assert_eq!(next_fire_time("0 * * * *", "Europe/Berlin", utc(2026, 10, 25, 1, 10)).unwrap(), utc(2026, 10, 25, 2, 0));
+        // 重复的小时里有很多轮次的表达式同样跳到 03:00 CET，不会出错
+        assert_eq!(next_fire_time("* * * * *", "Europe/Berlin", utc(2026, 10, 25, 1, 10)).unwrap(), utc(2026, 10, 25, 2, 0));
+        assert_eq!(next_fire_time("*/5 * * * *", "Europe/Berlin", utc(2026, 10, 25, 1, 10)).unwrap(), utc(2026, 10, 25, 2, 0));
+        // 第一次出现(CEST)里照常每分钟执行
+        assert_eq!(next_fire_time("* * * * *", "Europe/Berlin", utc(2026, 10, 25, 0, 10)).unwrap(), utc(2026, 10, 25, 0, 11));
+        assert_eq!(next_fire_time("*/5 * * * *", "Europe/Berlin", utc(2026, 10, 25, 0, 58)).unwrap(), utc(2026, 10, 25, 2, 0));
+    }
+}
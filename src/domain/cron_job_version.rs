@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use crate::domain::environment::MASKED;
+
+// 记录修改人的请求头，没有登录体系，由调用方自行填写
+pub const CHANGED_BY_HEADER: &str = "X-Changed-By";
+const MAX_CHANGED_BY_LEN: usize = 100;
+
+// 运行状态而不是任务定义的字段，比较版本和回滚时忽略
+pub const RUNTIME_FIELDS: [&str; 10] = [
+    "id",
+    "enabled",
+    "last_executed_at",
+    "next_execute_at",
+    "catchup_remaining",
+    "archived_at",
+    "deleted_at",
+    "version",
+    "created_at",
+    "updated_at",
+];
+
+// 加密保存的字段，返回快照和比较结果前把值遮盖，只保留变量名
+const SECRET_FIELDS: [&str; 1] = ["secret_env"];
+
+// 任务的一个历史版本，snapshot是当时cronjobs整行的json
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct CronJobVersion {
+    pub id: i32,
+    pub job_id: i32,
+    pub version: i32,
+    pub snapshot: Value,
+    pub changed_by: Option<String>,
+    pub change_note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CronJobVersion {
+    // 返回给调用方的版本，secret_env只显示变量名
+    pub fn masked(mut self) -> Self {
+        if let Some(snapshot) = self.snapshot.as_object_mut() {
+            for field in SECRET_FIELDS {
+                if let Some(value) = snapshot.get_mut(field) {
+                    *value = mask_secret(value);
+                }
+            }
+        }
+        self
+    }
+}
+
+fn mask_secret(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        Value::Object(vars) => Value::Object(vars.keys().map(|name| (name.clone(), Value::from(MASKED))).collect()),
+        _ => Value::from(MASKED),
+    }
+}
+
+// 比较两个版本，to省略时与当前版本比较
+#[derive(Debug, Clone, Deserialize)]
+pub struct VersionDiffQuery {
+    pub from: i32,
+    pub to: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionDiff {
+    pub job_id: i32,
+    pub from: i32,
+    pub to: i32,
+    pub changes: Vec<FieldChange>,
+}
+
+// 逐字段比较两个快照，按字段名排序；某一边没有的字段(之后新增的列)视为null，加密字段比较密文、返回遮盖后的值
+pub fn diff_snapshots(from: &Value, to: &Value) -> Vec<FieldChange> {
+    let empty = serde_json::Map::new();
+    let from = from.as_object().unwrap_or(&empty);
+    let to = to.as_object().unwrap_or(&empty);
+    let mut fields: Vec<&String> = from.keys().chain(to.keys())
+        .filter(|field| !RUNTIME_FIELDS.contains(&field.as_str()))
+        .collect();
+    fields.sort();
+    fields.dedup();
+    fields.into_iter().filter_map(|field| {
+        let old = from.get(field).cloned().unwrap_or(Value::Null);
+        let new = to.get(field).cloned().unwrap_or(Value::Null);
+        if old == new {
+            return None;
+        }
+        if SECRET_FIELDS.contains(&field.as_str()) {
+            return Some(FieldChange { field: field.clone(), from: mask_secret(&old), to: mask_secret(&new) });
+        }
+        Some(FieldChange { field: field.clone(), from: old, to: new })
+    }).collect()
+}
+
+// 去掉首尾空白，空值和过长的值不记录
+pub fn normalize_changed_by(value: Option<&str>) -> Option<String> {
+    value.map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.chars().take(MAX_CHANGED_BY_LEN).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_snapshots_ignores_runtime_fields() {
+        let from = json!({"id": 1, "command": "echo 1", "cron_expression": "0 * * * * *", "next_execute_at": "2024-01-01T00:00:00Z", "version": 1});
+        let to = json!({"id": 1, "command": "echo 2", "cron_expression": "0 * * * * *", "next_execute_at": "2024-01-01T01:00:00Z", "version": 2});
+        let changes = diff_snapshots(&from, &to);
+        assert_eq!(changes, vec![FieldChange { field: "command".to_string(), from: json!("echo 1"), to: json!("echo 2") }]);
+    }
+
+    #[test]
+    fn test_diff_snapshots_missing_field_is_null() {
+        let from = json!({"command": "uptime"});
+        let to = json!({"command": "uptime", "priority": 5});
+        let changes = diff_snapshots(&from, &to);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "priority");
+        assert_eq!(changes[0].from, Value::Null);
+        assert_eq!(changes[0].to, json!(5));
+    }
+
+    #[test]
+    fn test_secret_env_masked() {
+        let from = json!({"secret_env": {"TOKEN": "cipher-1"}});
+        let to = json!({"secret_env": {"TOKEN": "cipher-2", "KEY": "cipher-3"}});
+        let changes = diff_snapshots(&from, &to);
+        assert_eq!(changes[0].from, json!({"TOKEN": MASKED}));
+        assert_eq!(changes[0].to, json!({"KEY": MASKED, "TOKEN": MASKED}));
+        let version = CronJobVersion { id: 1, job_id: 1, version: 1, snapshot: to, changed_by: None, change_note: None, created_at: Utc::now() };
+        assert!(!version.masked().snapshot.to_string().contains("cipher"));
+    }
+
+    #[test]
+    fn test_normalize_changed_by() {
+        assert_eq!(normalize_changed_by(Some("  alice ")), Some("alice".to_string()));
+        assert_eq!(normalize_changed_by(Some("   ")), None);
+        assert_eq!(normalize_changed_by(None), None);
+        assert_eq!(normalize_changed_by(Some(&"a".repeat(200))).map(|v| v.len()), Some(100));
+    }
+}
@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 pub struct CronLog{
     pub log_id :i32,
     pub job_id :i32,
+    pub run_id :Option<i32>,
     pub server_ip :String,
     pub status :String,
     pub output :Option<String>,
@@ -14,13 +15,14 @@ pub struct CronLog{
 #[derive(Clone,Debug,Serialize,Deserialize)]
 pub struct CreateCronLog{
     pub job_id :i32,
+    pub run_id :Option<i32>,
     pub server_ip :String,
     pub status :String,
     pub output :Option<String>
 }
 
 impl CreateCronLog{
-    pub fn new(job_id: i32,server_ip :String,status: String,output: Option<String>) -> Self{
-        Self { job_id,server_ip, status, output }
+    pub fn new(job_id: i32,run_id: Option<i32>,server_ip :String,status: String,output: Option<String>) -> Self{
+        Self { job_id,run_id,server_ip, status, output }
     }
 }
\ No newline at end of file
@@ -0,0 +1,164 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::cron_job::{next_fire_time, parse_timezone, DEFAULT_TIMEZONE};
+
+pub const DEFAULT_PREVIEW_COUNT: usize = 5;
+pub const MAX_PREVIEW_COUNT: usize = 50;
+
+const WEEKDAYS: [&str; 8] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CronPreviewRequest {
+    pub cron_expression: String,
+    pub timezone: Option<String>,
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CronPreviewResponse {
+    pub valid: bool,
+    pub description: Option<String>,
+    pub next_runs: Vec<DateTime<FixedOffset>>, // 带时区偏移的本地时间
+    pub error: Option<String>,
+}
+
+// 创建、更新、预览共用的校验，返回的错误信息直接给到前端
+pub fn validate_cron_expression(cron_expression: &str, timezone: &str) -> Result<(), anyhow::Error> {
+    next_fire_time(cron_expression, timezone, Utc::now())?;
+    Ok(())
+}
+
+pub fn preview_cron(req: &CronPreviewRequest) -> CronPreviewResponse {
+    let timezone = req.timezone.as_deref().unwrap_or(DEFAULT_TIMEZONE);
+    let count = req.count.unwrap_or(DEFAULT_PREVIEW_COUNT).clamp(1, MAX_PREVIEW_COUNT);
+    let invalid = |e: anyhow::Error| CronPreviewResponse {
+        valid: false,
+        description: None,
+        next_runs: Vec::new(),
+        error: Some(e.to_string()),
+    };
+    let tz = match parse_timezone(timezone) {
+        Ok(tz) => tz,
+        Err(e) => return invalid(e),
+    };
+    let mut next_runs = Vec::with_capacity(count);
+    let mut after = Utc::now();
+    for _ in 0..count {
+        match next_fire_time(&req.cron_expression, timezone, after) {
+            Ok(time) => {
+                next_runs.push(time.with_timezone(&tz).fixed_offset());
+                after = time;
+            }
+            Err(e) => return invalid(e),
+        }
+    }
+    CronPreviewResponse {
+        valid: true,
+        description: Some(describe_cron(&req.cron_expression)),
+        next_runs,
+        error: None,
+    }
+}
+
+// 把 5 段式 cron 表达式翻译成可读的描述，复杂的写法保留原样
+pub fn describe_cron(cron_expression: &str) -> String {
+    let fields: Vec<&str> = cron_expression.split_whitespace().collect();
+    if fields.len() != 5 {
+        return cron_expression.to_string();
+    }
+    let (minute, hour, day, month, weekday) = (fields[0], fields[1], fields[2], fields[3], fields[4]);
+
+    let mut parts = vec![describe_time(minute, hour)];
+    if day != "*" {
+        parts.push(format!("on day {} of the month", day));
+    }
+    if month != "*" {
+        parts.push(format!("in {}", describe_list(month, |n| MONTHS.get(n.wrapping_sub(1)).copied())));
+    }
+    if weekday != "*" {
+        parts.push(format!("on {}", describe_list(weekday, |n| WEEKDAYS.get(n).copied())));
+    }
+    parts.join(", ")
+}
+
+fn describe_time(minute: &str, hour: &str) -> String {
+    let minute_num = minute.parse::<u32>().ok();
+    let hour_num = hour.parse::<u32>().ok();
+    match (minute_num, hour_num) {
+        (Some(m), Some(h)) => format!("At {:02}:{:02}", h, m),
+        (Some(m), None) if hour == "*" => format!("At minute {} of every hour", m),
+        (Some(m), None) => format!("At minute {} past hour {}", m, describe_step(hour, "hour")),
+        (None, _) if minute == "*" && hour == "*" => "Every minute".to_string(),
+        (None, Some(h)) => format!("{} during hour {}", capitalize(&describe_step(minute, "minute")), h),
+        (None, None) if hour == "*" => capitalize(&describe_step(minute, "minute")),
+        (None, None) => format!("{}, {}", capitalize(&describe_step(minute, "minute")), describe_step(hour, "hour")),
+    }
+}
+
+// */5 => every 5 minutes，其他写法原样保留
+fn describe_step(field: &str, unit: &str) -> String {
+    match field.strip_prefix("*/") {
+        Some(step) => format!("every {} {}s", step, unit),
+        None if field == "*" => format!("every {}", unit),
+        None => format!("{} {}", unit, field),
+    }
+}
+
+// 1,3-5 这类列表/区间，把单个数字换成名称
+fn describe_list(field: &str, name: impl Fn(usize) -> Option<&'static str>) -> String {
+    let to_name = |s: &str| s.parse::<usize>().ok().and_then(&name).map(str::to_string).unwrap_or(s.to_string());
+    field
+        .split(',')
+        .map(|item| match item.split_once('-') {
+            Some((start, end)) => format!("{} through {}", to_name(start), to_name(end)),
+            None => to_name(item),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_cron() {
+        assert_eq!(describe_cron("30 2 * * *"), "At 02:30");
+        assert_eq!(describe_cron("0 9 * * 1-5"), "At 09:00, on Monday through Friday");
+        assert_eq!(describe_cron("*/5 * * * *"), "Every 5 minutes");
+        assert_eq!(describe_cron("0 * * * *"), "At minute 0 of every hour");
+        assert_eq!(describe_cron("0 0 1 1,7 *"), "At 00:00, on day 1 of the month, in January, July");
+    }
+
+    #[test]
+    fn test_preview_cron() {
+        let req = CronPreviewRequest {
+            cron_expression: "0 * * * *".to_string(),
+            timezone: Some("Asia/Shanghai".to_string()),
+            count: Some(3),
+        };
+        let resp = preview_cron(&req);
+        assert!(resp.valid);
+        assert_eq!(resp.next_runs.len(), 3);
+        assert_eq!(resp.next_runs[0].offset().local_minus_utc(), 8 * 3600);
+
+        let req = CronPreviewRequest {
+            cron_expression: "61 * * * *".to_string(),
+            timezone: None,
+            count: None,
+        };
+        let resp = preview_cron(&req);
+        assert!(!resp.valid);
+        assert!(resp.error.is_some());
+    }
+}
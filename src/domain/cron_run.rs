@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use crate::domain::cron_log::CronLog;
+
+// 任务的一次运行，定时触发和手动触发都会生成，执行日志通过run_id关联
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct CronRun {
+    pub run_id: i32,
+    pub job_id: i32,
+    pub trigger_type: String,
+    pub status: String,
+    pub command: Option<String>,
+    pub template_params: Option<Value>, // 手动运行传入的模板参数
+    pub dry_run: bool,
+    pub error: Option<String>,
+    pub workflow_run_id: Option<i32>, // 依赖触发的运行指向工作流起点的运行
+    pub upstream_run_id: Option<i32>, // 触发本次运行的上游运行
+    pub scheduled_at: Option<DateTime<Utc>>, // 定时运行本该执行的时间
+    pub job_version: Option<i32>,            // 开始执行时任务的版本号
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+// 手动运行的请求体，所有字段都可以省略
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunCronJob {
+    pub command: Option<String>, // 覆盖本次运行的命令，不修改任务本身
+    pub params: Option<HashMap<String, Value>>, // 本次运行的模板参数，优先于任务定义里的
+    pub dry_run: Option<bool>,   // 只记录将要在哪些server上执行什么，不真正连接
+}
+
+// 查询一次运行的状态和它产生的日志
+#[derive(Debug, Clone, Serialize)]
+pub struct CronRunDetail {
+    #[serde(flatten)]
+    pub run: CronRun,
+    pub logs: Vec<CronLog>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunTrigger {
+    Schedule,
+    Manual,
+    Dependency,
+}
+
+impl RunTrigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunTrigger::Schedule => "schedule",
+            RunTrigger::Manual => "manual",
+            RunTrigger::Dependency => "dependency",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Queued,     // 手动运行已入队，等待worker认领
+    Running,
+    Success,
+    Failed,
+    Skipped,    // 重叠策略跳过
+    Cancelled,  // 被新一轮(replace)取消
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Queued => "queued",
+            RunStatus::Running => "running",
+            RunStatus::Success => "success",
+            RunStatus::Failed => "failed",
+            RunStatus::Skipped => "skipped",
+            RunStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+// 一次运行的结束方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunEnd {
+    Succeeded,
+    Failed,     // 重试耗尽后仍有server失败，或执行前就出错(没有server、模板渲染失败等)
+    Cancelled,  // 被新一轮(replace)取消
+}
+
+// 定时运行结束后任务在队列里的去向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunFollowUp {
+    Reschedule, // 推进到下一次执行时间
+    Disable,    // 按disable_on_failure停用
+    Handover,   // 新一轮负责后续的调度
+}
+
+impl RunEnd {
+    pub fn follow_up(&self, disables_on_failure: bool) -> RunFollowUp {
+        match self {
+            RunEnd::Cancelled => RunFollowUp::Handover,
+            RunEnd::Failed if disables_on_failure => RunFollowUp::Disable,
+            _ => RunFollowUp::Reschedule,
+        }
+    }
+}
+
+// 执行日志归属到哪个任务的哪一次运行，ad-hoc执行不写日志
+#[derive(Debug, Clone, Copy)]
+pub struct LogTarget {
+    pub job_id: i32,
+    pub run_id: Option<i32>,
+}
+
+impl LogTarget {
+    pub fn new(job_id: i32, run_id: Option<i32>) -> Self {
+        Self { job_id, run_id }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::server::require_servers;
+
+    #[test]
+    fn test_follow_up() {
+        // 没有server的任务执行前就出错，和重试耗尽一样算作失败，不会停留在当前执行时间
+        let end = match require_servers(7, Vec::new()) {
+            Ok(_) => RunEnd::Succeeded,
+            Err(e) => {
+                assert_eq!(e.to_string(), "job 7 has no server to execute");
+                RunEnd::Failed
+            }
+        };
+        assert_eq!(end.follow_up(false), RunFollowUp::Reschedule);
+        assert_eq!(end.follow_up(true), RunFollowUp::Disable);
+        assert_eq!(RunEnd::Succeeded.follow_up(true), RunFollowUp::Reschedule);
+        assert_eq!(RunEnd::Cancelled.follow_up(true), RunFollowUp::Handover);
+    }
+}
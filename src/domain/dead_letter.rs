@@ -0,0 +1,78 @@
+use std::str::FromStr;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::domain::cron_log::CronLog;
+use crate::domain::cron_run::CronRun;
+
+// 重试耗尽后仍然失败的运行
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: i32,
+    pub job_id: i32,
+    pub run_id: Option<i32>,
+    pub reason: String,
+    pub output: Option<String>,    // 失败server最后一次执行的输出
+    pub job_disabled: bool,        // 任务是否因这次失败被停用
+    pub status: String,
+    pub replay_run_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+// 查看死信和对应运行的全部日志
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetterDetail {
+    #[serde(flatten)]
+    pub dead_letter: DeadLetter,
+    pub logs: Vec<CronLog>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeadLetterQuery {
+    pub status: Option<DeadLetterStatus>,
+    pub job_id: Option<i32>,
+}
+
+// 重放的请求体，可以省略
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReplayDeadLetter {
+    pub enable_job: Option<bool>, // 是否同时重新启用任务，默认在任务因这次失败被停用时启用
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayResult {
+    pub dead_letter: DeadLetter,
+    pub run: CronRun,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterStatus {
+    Open,
+    Replayed,   // 已重新入队执行
+    Discarded,  // 确认不需要处理
+}
+
+impl DeadLetterStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeadLetterStatus::Open => "open",
+            DeadLetterStatus::Replayed => "replayed",
+            DeadLetterStatus::Discarded => "discarded",
+        }
+    }
+}
+
+impl FromStr for DeadLetterStatus {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(DeadLetterStatus::Open),
+            "replayed" => Ok(DeadLetterStatus::Replayed),
+            "discarded" => Ok(DeadLetterStatus::Discarded),
+            _ => Err(anyhow!("Invalid dead letter status: {}", s)),
+        }
+    }
+}
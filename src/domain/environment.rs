@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use anyhow::anyhow;
+use crate::domain::command_template::shell_quote;
+
+pub const MASKED: &str = "********";
+
+// 执行时的环境变量和工作目录，来自命令模板、任务定义或临时执行请求
+// 优先通过ssh的env请求设置，server不接受(sshd的AcceptEnv没有放行)或提权执行时普通变量改为在命令前export
+// secret的值不写进命令(远端ps、sudo的参数里都能看到)：不提权时只通过env请求设置，提权时从stdin读入
+#[derive(Clone, Default, PartialEq)]
+pub struct Environment {
+    pub vars: BTreeMap<String, String>,
+    pub secrets: BTreeSet<String>, // 值需要在输出里遮盖的变量名
+    pub workdir: Option<String>,
+}
+
+// Debug不输出secret的值
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vars = self.vars.iter()
+            .map(|(name, value)| (name, if self.secrets.contains(name) { MASKED } else { value.as_str() }))
+            .collect::<BTreeMap<_, _>>();
+        f.debug_struct("Environment").field("vars", &vars).field("workdir", &self.workdir).finish()
+    }
+}
+
+impl Environment {
+    pub fn new(vars: HashMap<String, String>, workdir: Option<String>) -> Self {
+        Environment { vars: vars.into_iter().collect(), secrets: BTreeSet::new(), workdir: workdir.filter(|w| !w.is_empty()) }
+    }
+
+    // other覆盖self：同名变量取other的值，other有工作目录时使用other的
+    pub fn merge(mut self, other: Environment) -> Self {
+        for (name, value) in other.vars {
+            self.secrets.remove(&name);
+            self.vars.insert(name, value);
+        }
+        self.secrets.extend(other.secrets);
+        if other.workdir.is_some() {
+            self.workdir = other.workdir;
+        }
+        self
+    }
+
+    pub fn add_secrets(&mut self, secrets: HashMap<String, String>) {
+        for (name, value) in secrets {
+            self.secrets.insert(name.clone());
+            self.vars.insert(name, value);
+        }
+    }
+
+    pub fn has_secrets(&self) -> bool {
+        self.secrets.iter().any(|name| self.vars.contains_key(name))
+    }
+
+    // 在命令前加上cd，exported为true时也export普通变量，secret不会写进命令；cd失败时不执行命令
+    pub fn apply(&self, command: &str, exported: bool) -> String {
+        let mut prefix = String::new();
+        let vars = self.vars.iter()
+            .filter(|(name, _)| exported && !self.secrets.contains(*name))
+            .map(|(name, value)| format!("{}={}", name, shell_quote(value)))
+            .collect::<Vec<_>>();
+        if !vars.is_empty() {
+            prefix.push_str(&format!("export {}; ", vars.join(" ")));
+        }
+        if let Some(workdir) = &self.workdir {
+            prefix.push_str(&format!("cd {} || exit 1; ", shell_quote(workdir)));
+        }
+        format!("{}{}", prefix, command)
+    }
+
+    // 提权执行时使用：普通变量export，secret在命令开始前按secret_input的顺序从stdin逐行读入
+    pub fn apply_with_stdin(&self, command: &str) -> String {
+        let names = self.secret_names();
+        if names.is_empty() {
+            return self.apply(command, true);
+        }
+        let reads = names.iter().map(|name| format!("IFS= read -r {} || exit 1; ", name)).collect::<String>();
+        format!("{}export {}; {}", reads, names.join(" "), self.apply(command, true))
+    }
+
+    // 提权成功后写入stdin的secret值，每行一个，值里不能有换行
+    pub fn secret_input(&self) -> Result<Vec<u8>, anyhow::Error> {
+        let mut input = Vec::new();
+        for name in self.secret_names() {
+            let value = &self.vars[name];
+            if value.contains('\n') {
+                return Err(anyhow!("secret environment variable {} contains a newline and can not be passed to a privileged command", name));
+            }
+            input.extend_from_slice(value.as_bytes());
+            input.push(b'\n');
+        }
+        Ok(input)
+    }
+
+    fn secret_names(&self) -> Vec<&str> {
+        self.secrets.iter().filter(|name| self.vars.contains_key(*name)).map(String::as_str).collect()
+    }
+
+    // 输出里出现的secret值替换掉再返回和记录日志，长的先替换，避免只替换掉一部分
+    pub fn mask(&self, text: String) -> String {
+        let mut values = self.secrets.iter()
+            .filter_map(|name| self.vars.get(name))
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>();
+        values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        values.into_iter().fold(text, |text, value| {
+            if text.contains(value.as_str()) { text.replace(value.as_str(), MASKED) } else { text }
+        })
+    }
+}
+
+// 变量名只能是字母、数字和下划线，不能以数字开头
+pub fn validate_env(vars: &HashMap<String, String>) -> Result<(), anyhow::Error> {
+    for name in vars.keys() {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(anyhow!("invalid environment variable name: {}", name));
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let env = Environment::new(vars(&[("APP_ENV", "prod"), ("GREETING", "it's me")]), Some("/srv/my app".to_string()));
+        assert_eq!(env.apply("make", false), "cd '/srv/my app' || exit 1; make");
+        assert_eq!(
+            env.apply("make", true),
+            r"export APP_ENV=prod GREETING='it'\''s me'; cd '/srv/my app' || exit 1; make"
+        );
+        assert_eq!(Environment::default().apply("make", true), "make");
+        assert!(Environment::new(HashMap::new(), Some(String::new())).workdir.is_none());
+    }
+
+    #[test]
+    fn test_secrets_not_inline() {
+        let mut env = Environment::new(vars(&[("APP_ENV", "prod")]), None);
+        env.add_secrets(vars(&[("TOKEN", "abc"), ("API_KEY", "x y")]));
+        assert!(env.has_secrets());
+        assert_eq!(env.apply("make", true), "export APP_ENV=prod; make");
+        assert_eq!(
+            env.apply_with_stdin("make"),
+            "IFS= read -r API_KEY || exit 1; IFS= read -r TOKEN || exit 1; export API_KEY TOKEN; export APP_ENV=prod; make"
+        );
+        assert_eq!(env.secret_input().unwrap(), b"x y\nabc\n");
+        env.add_secrets(vars(&[("TOKEN", "a\nb")]));
+        assert!(env.secret_input().is_err());
+        let plain = Environment::new(vars(&[("A", "1")]), None);
+        assert!(!plain.has_secrets());
+        assert_eq!(plain.apply_with_stdin("make"), plain.apply("make", true));
+    }
+
+    #[test]
+    fn test_merge_and_mask() {
+        let template = Environment::new(vars(&[("A", "1"), ("TOKEN", "t")]), Some("/opt".to_string()));
+        let mut job = Environment::new(vars(&[("A", "2")]), None);
+        job.add_secrets(vars(&[("TOKEN", "abc"), ("TOKEN_LONG", "abcdef")]));
+        let env = template.merge(job);
+        assert_eq!(env.vars.get("A").map(String::as_str), Some("2"));
+        assert_eq!(env.workdir.as_deref(), Some("/opt"));
+        assert_eq!(env.mask("token=abcdef a=abc x=2".to_string()), "token=******** a=******** x=2");
+        assert!(!format!("{:?}", env).contains("abc"));
+        // 被普通变量覆盖后不再是secret
+        let plain = env.clone().merge(Environment::new(vars(&[("TOKEN", "abc")]), None));
+        assert_eq!(plain.mask("abc".to_string()), "abc");
+    }
+
+    #[test]
+    fn test_validate_env() {
+        assert!(validate_env(&vars(&[("PATH_EXTRA", "x"), ("_a1", "y")])).is_ok());
+        assert!(validate_env(&vars(&[("1A", "x")])).is_err());
+        assert!(validate_env(&vars(&[("A-B", "x")])).is_err());
+        assert!(validate_env(&vars(&[("A=B", "x")])).is_err());
+    }
+}
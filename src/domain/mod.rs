@@ -4,4 +4,22 @@ pub mod server;
 pub mod servergroup;
 pub mod cron_job;
 pub mod scheduler;
-pub mod cron_log;
\ No newline at end of file
+pub mod cron_log;
+pub mod cron_preview;
+pub mod cron_run;
+pub mod retry;
+pub mod success;
+pub mod workflow;
+
+pub mod calendar;
+pub mod queue;
+pub mod dead_letter;
+pub mod spread;
+pub mod cron_job_version;
+pub mod archive;
+pub mod command_template;
+pub mod runbook;
+pub mod playbook;
+pub mod script;
+pub mod privilege;
+pub mod environment;
\ No newline at end of file
@@ -0,0 +1,398 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use crate::domain::command_template::{parse_template, shell_quote, valid_name, Segment};
+use crate::domain::runbook::{pick_target, RunbookTarget};
+use crate::domain::ssh_session::{CommandOutput, FailureClass, HostResult, SshFailure};
+
+pub const DEFAULT_STEP_TIMEOUT_SECS: u64 = 15;
+const MAX_STEP_TIMEOUT_SECS: u64 = 3600;
+const MAX_STEPS: usize = 100;
+
+// 多步骤的剧本：每台server建立一次连接，按顺序执行命令、上传文件和条件检查
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playbook {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: Json<Vec<PlaybookStep>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaybookStep {
+    pub name: String,
+    #[serde(flatten)]
+    pub action: StepAction,
+    #[serde(default)]
+    pub continue_on_error: bool,   // 失败后继续执行后面的步骤，默认停止
+    pub timeout_secs: Option<u64>, // 默认15秒
+    pub capture: Option<String>,   // 成功时把stdout(去掉首尾空白)保存为变量，后面的步骤用 {{name}} 引用
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StepAction {
+    Command { command: String },
+    // 写入文件，内容通过stdin传给 cat，已存在的文件会被覆盖
+    Upload { path: String, content: String, mode: Option<String> },
+    // 条件检查：退出码为0时继续，否则跳过这台server剩下的步骤，不算失败
+    Check { command: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatePlaybook {
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: Vec<PlaybookStep>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdatePlaybook {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub steps: Option<Vec<PlaybookStep>>,
+}
+
+// 临时执行的目标，三种只能给一个
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RunPlaybook {
+    pub server_id: Option<i32>,
+    pub group_id: Option<i32>,
+    pub selector: Option<HashMap<String, String>>,
+}
+
+impl RunPlaybook {
+    pub fn target(&self) -> Result<RunbookTarget, anyhow::Error> {
+        pick_target(self.server_id, self.group_id, self.selector.clone())?
+            .ok_or_else(|| anyhow!("server_id, group_id or selector is required"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Success,
+    Failed,
+    Ignored,  // 失败但设置了continue_on_error
+    Unmet,    // 条件检查不满足，后面的步骤跳过
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StepResult {
+    pub step: String,
+    pub status: StepStatus,
+    pub exit_code: Option<u32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,    // 超时、通道错误
+    pub duration_ms: u64,
+    #[serde(skip)]
+    pub failure_class: Option<FailureClass>, // error的分类，交给任务的重试策略
+}
+
+impl StepResult {
+    pub fn skipped(step: &PlaybookStep) -> Self {
+        StepResult { step: step.name.clone(), status: StepStatus::Skipped, exit_code: None, stdout: String::new(), stderr: String::new(), error: None, duration_ms: 0, failure_class: None }
+    }
+
+    // Failed和Unmet之后的步骤不再执行
+    pub fn stops(&self) -> bool {
+        matches!(self.status, StepStatus::Failed | StepStatus::Unmet)
+    }
+}
+
+// 一台server上的执行结果
+#[derive(Debug, Clone, Serialize)]
+pub struct HostPlaybookResult {
+    pub server: String,
+    pub success: bool,
+    pub error: Option<String>, // 连接或认证失败，没有执行任何步骤
+    pub steps: Vec<StepResult>,
+    pub variables: HashMap<String, String>,
+    #[serde(skip)]
+    pub failure: Option<SshFailure>,
+}
+
+impl HostPlaybookResult {
+    pub fn connect_failed(server: String, failure: SshFailure) -> Self {
+        HostPlaybookResult { server, success: false, error: Some(failure.message.clone()), steps: Vec::new(), variables: HashMap::new(), failure: Some(failure) }
+    }
+
+    // 转成任务执行的结果，交给任务的成功规则判断；stdout按步骤拼接，退出码取第一个失败的步骤
+    pub fn into_host_result(self) -> HostResult {
+        if let Some(failure) = self.failure {
+            return HostResult { server: self.server, result: Err(failure) };
+        }
+        let failed = self.steps.iter().find(|s| s.status == StepStatus::Failed);
+        if let Some(step) = failed.filter(|s| s.exit_code.is_none()) {
+            let message = format!("step {}: {}", step.step, step.error.as_deref().unwrap_or("failed"));
+            let class = step.failure_class.unwrap_or(FailureClass::Connect);
+            return HostResult { server: self.server, result: Err(SshFailure::new(class, message)) };
+        }
+        let exit_code = failed.and_then(|s| s.exit_code).unwrap_or(0);
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        for step in self.steps.iter().filter(|s| s.status != StepStatus::Skipped) {
+            stdout.push_str(&format!("[{}]\n{}", step.step, step.stdout));
+            if !step.stderr.is_empty() {
+                stderr.push_str(&format!("[{}]\n{}", step.step, step.stderr));
+            }
+        }
+        HostResult { server: self.server, result: Ok(CommandOutput { exit_code, stdout, stderr }) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaybookRunResult {
+    pub playbook_id: i32,
+    pub results: Vec<HostPlaybookResult>,
+}
+
+// 一个步骤实际要执行的命令和stdin
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepCommand {
+    pub command: String,
+    pub input: Option<String>,
+}
+
+impl PlaybookStep {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.timeout_secs.unwrap_or(DEFAULT_STEP_TIMEOUT_SECS))
+    }
+
+    // 替换前面步骤捕获的变量，命令里的值做shell转义
+    pub fn command(&self, vars: &HashMap<String, String>) -> Result<StepCommand, anyhow::Error> {
+        match &self.action {
+            StepAction::Command { command } | StepAction::Check { command } => {
+                Ok(StepCommand { command: render_vars(command, vars, true)?, input: None })
+            }
+            StepAction::Upload { path, content, mode } => {
+                let path = shell_quote(&render_vars(path, vars, false)?);
+                let command = match mode {
+                    Some(mode) => format!("cat > {} && chmod {} {}", path, mode, path),
+                    None => format!("cat > {}", path),
+                };
+                Ok(StepCommand { command, input: Some(content.clone()) })
+            }
+        }
+    }
+
+    // 根据执行结果判断步骤的状态，成功时保存捕获的变量
+    pub fn finish(&self, outcome: Result<CommandOutput, SshFailure>, duration_ms: u64, vars: &mut HashMap<String, String>) -> StepResult {
+        let mut result = StepResult { step: self.name.clone(), status: StepStatus::Success, exit_code: None, stdout: String::new(), stderr: String::new(), error: None, duration_ms, failure_class: None };
+        let succeeded = match outcome {
+            Ok(output) => {
+                result.exit_code = Some(output.exit_code);
+                result.stdout = output.stdout;
+                result.stderr = output.stderr;
+                output.exit_code == 0
+            }
+            Err(failure) => {
+                result.error = Some(failure.message);
+                result.failure_class = Some(failure.class);
+                false
+            }
+        };
+        result.status = match (succeeded, &self.action) {
+            (true, _) => {
+                if let Some(name) = &self.capture {
+                    vars.insert(name.clone(), result.stdout.trim().to_string());
+                }
+                StepStatus::Success
+            }
+            // 检查命令本身超时或出错仍按失败处理
+            (false, StepAction::Check { .. }) if result.error.is_none() => StepStatus::Unmet,
+            (false, _) if self.continue_on_error => StepStatus::Ignored,
+            (false, _) => StepStatus::Failed,
+        };
+        result
+    }
+}
+
+// 创建、修改时检查：步骤名唯一，引用的变量必须由前面的步骤捕获
+pub fn validate_steps(steps: &[PlaybookStep]) -> Result<(), anyhow::Error> {
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return Err(anyhow!("a playbook must have 1 to {} steps", MAX_STEPS));
+    }
+    let mut names = HashSet::new();
+    let mut captured = HashSet::new();
+    for step in steps {
+        if step.name.trim().is_empty() {
+            return Err(anyhow!("step name must not be empty"));
+        }
+        if !names.insert(step.name.as_str()) {
+            return Err(anyhow!("duplicate step name: {}", step.name));
+        }
+        if step.timeout_secs.is_some_and(|secs| secs == 0 || secs > MAX_STEP_TIMEOUT_SECS) {
+            return Err(anyhow!("step {}: timeout_secs must be between 1 and {}", step.name, MAX_STEP_TIMEOUT_SECS));
+        }
+        let texts = match &step.action {
+            StepAction::Command { command } | StepAction::Check { command } => {
+                if command.trim().is_empty() {
+                    return Err(anyhow!("step {}: command must not be empty", step.name));
+                }
+                vec![command]
+            }
+            StepAction::Upload { path, mode, .. } => {
+                if path.trim().is_empty() {
+                    return Err(anyhow!("step {}: path must not be empty", step.name));
+                }
+                if let Some(mode) = mode
+                    && (!(3..=4).contains(&mode.len()) || !mode.chars().all(|c| ('0'..='7').contains(&c)))
+                {
+                    return Err(anyhow!("step {}: mode must be octal like 644 or 0755", step.name));
+                }
+                vec![path]
+            }
+        };
+        for text in texts {
+            for segment in parse_template(text).map_err(|e| anyhow!("step {}: {}", step.name, e))? {
+                if let Segment::Var(name) = segment
+                    && !captured.contains(name)
+                {
+                    return Err(anyhow!("step {}: variable {} is not captured by an earlier step", step.name, name));
+                }
+            }
+        }
+        if let Some(name) = &step.capture {
+            if !valid_name(name) {
+                return Err(anyhow!("step {}: invalid capture name: {}", step.name, name));
+            }
+            captured.insert(name.as_str());
+        }
+    }
+    Ok(())
+}
+
+// 步骤的简要说明，dry run时记录
+pub fn describe_steps(steps: &[PlaybookStep]) -> String {
+    steps.iter().map(|step| match &step.action {
+        StepAction::Command { command } => format!("{}: `{}`", step.name, command),
+        StepAction::Upload { path, .. } => format!("{}: upload {}", step.name, path),
+        StepAction::Check { command } => format!("{}: check `{}`", step.name, command),
+    }).collect::<Vec<_>>().join("; ")
+}
+
+fn render_vars(text: &str, vars: &HashMap<String, String>, quote: bool) -> Result<String, anyhow::Error> {
+    let mut rendered = String::with_capacity(text.len());
+    for segment in parse_template(text)? {
+        match segment {
+            Segment::Text(text) => rendered.push_str(text),
+            Segment::Var(name) => {
+                let value = vars.get(name).ok_or_else(|| anyhow!("variable {} is not captured", name))?;
+                if quote {
+                    rendered.push_str(&shell_quote(value));
+                } else {
+                    rendered.push_str(value);
+                }
+            }
+        }
+    }
+    Ok(rendered)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(name: &str, action: StepAction) -> PlaybookStep {
+        PlaybookStep { name: name.to_string(), action, continue_on_error: false, timeout_secs: None, capture: None }
+    }
+
+    fn command(command: &str) -> StepAction {
+        StepAction::Command { command: command.to_string() }
+    }
+
+    fn output(exit_code: u32, stdout: &str) -> Result<CommandOutput, SshFailure> {
+        Ok(CommandOutput { exit_code, stdout: stdout.to_string(), stderr: String::new() })
+    }
+
+    #[test]
+    fn test_deserialize_steps() {
+        let steps: Vec<PlaybookStep> = serde_json::from_value(serde_json::json!([
+            {"name": "version", "type": "command", "command": "uname -r", "capture": "kernel"},
+            {"name": "config", "type": "upload", "path": "/tmp/a.conf", "content": "x=1", "mode": "644", "timeout_secs": 30},
+            {"name": "running", "type": "check", "command": "systemctl is-active nginx", "continue_on_error": true}
+        ])).unwrap();
+        assert_eq!(steps[0].capture.as_deref(), Some("kernel"));
+        assert_eq!(steps[1].action, StepAction::Upload { path: "/tmp/a.conf".to_string(), content: "x=1".to_string(), mode: Some("644".to_string()) });
+        assert_eq!(steps[1].timeout(), std::time::Duration::from_secs(30));
+        assert!(steps[2].continue_on_error);
+        assert!(validate_steps(&steps).is_ok());
+    }
+
+    #[test]
+    fn test_validate_steps() {
+        assert!(validate_steps(&[]).is_err());
+        assert!(validate_steps(&[step("a", command("ls")), step("a", command("ls"))]).is_err());
+        assert!(validate_steps(&[step("a", command("echo {{kernel}}"))]).is_err());
+        let mut capture = step("a", command("uname -r"));
+        capture.capture = Some("kernel".to_string());
+        assert!(validate_steps(&[capture.clone(), step("b", command("echo {{kernel}}"))]).is_ok());
+        let upload = step("c", StepAction::Upload { path: "/tmp/x".to_string(), content: String::new(), mode: Some("9x".to_string()) });
+        assert!(validate_steps(&[upload]).is_err());
+        let mut timeout = step("d", command("ls"));
+        timeout.timeout_secs = Some(0);
+        assert!(validate_steps(&[timeout]).is_err());
+    }
+
+    #[test]
+    fn test_step_command() {
+        let vars = HashMap::from([("dir".to_string(), "my dir".to_string())]);
+        let run = step("a", command("ls {{dir}}"));
+        assert_eq!(run.command(&vars).unwrap().command, "ls 'my dir'");
+        let upload = step("b", StepAction::Upload { path: "/tmp/{{dir}}/a".to_string(), content: "x".to_string(), mode: Some("600".to_string()) });
+        assert_eq!(upload.command(&vars).unwrap(), StepCommand {
+            command: "cat > '/tmp/my dir/a' && chmod 600 '/tmp/my dir/a'".to_string(),
+            input: Some("x".to_string()),
+        });
+    }
+
+    #[test]
+    fn test_step_finish() {
+        let mut vars = HashMap::new();
+        let mut capture = step("a", command("hostname"));
+        capture.capture = Some("host".to_string());
+        let result = capture.finish(output(0, "web-1\n"), 5, &mut vars);
+        assert_eq!(result.status, StepStatus::Success);
+        assert_eq!(vars["host"], "web-1");
+
+        let check = step("b", StepAction::Check { command: "test -f /x".to_string() });
+        let result = check.finish(output(1, ""), 1, &mut vars);
+        assert_eq!(result.status, StepStatus::Unmet);
+        assert!(result.stops());
+        assert_eq!(check.finish(Err(SshFailure::new(FailureClass::Timeout, "timeout".to_string())), 1, &mut vars).status, StepStatus::Failed);
+
+        let mut ignore = step("c", command("false"));
+        ignore.continue_on_error = true;
+        let result = ignore.finish(output(1, ""), 1, &mut vars);
+        assert_eq!(result.status, StepStatus::Ignored);
+        assert!(!result.stops());
+        assert_eq!(step("d", command("false")).finish(output(2, ""), 1, &mut vars).status, StepStatus::Failed);
+    }
+
+    #[test]
+    fn test_into_host_result() {
+        let mut vars = HashMap::new();
+        let ok = step("a", command("echo hi")).finish(output(0, "hi\n"), 1, &mut vars);
+        let failed = step("b", command("false")).finish(output(3, ""), 1, &mut vars);
+        let host = HostPlaybookResult { server: "10.0.0.1".to_string(), success: false, error: None, steps: vec![ok.clone(), failed], variables: vars.clone(), failure: None };
+        let output = host.into_host_result().result.unwrap();
+        assert_eq!(output.exit_code, 3);
+        assert_eq!(output.stdout, "[a]\nhi\n[b]\n");
+
+        let timeout = step("c", command("sleep 100")).finish(Err(SshFailure::new(FailureClass::Timeout, "timeout".to_string())), 1, &mut vars);
+        let host = HostPlaybookResult { server: "10.0.0.1".to_string(), success: false, error: None, steps: vec![ok.clone(), timeout], variables: vars.clone(), failure: None };
+        assert_eq!(host.into_host_result().result.unwrap_err().class, FailureClass::Timeout);
+        // 通道出错不是超时，按连接失败重试
+        let broken = step("d", command("uptime")).finish(Err(SshFailure::new(FailureClass::Connect, "channel closed".to_string())), 1, &mut vars);
+        let host = HostPlaybookResult { server: "10.0.0.1".to_string(), success: false, error: None, steps: vec![ok, broken], variables: vars, failure: None };
+        assert_eq!(host.into_host_result().result.unwrap_err().class, FailureClass::Connect);
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+use std::str::FromStr;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use crate::domain::command_template::shell_quote;
+
+// sudo的提示符，收到后才通过stdin发送密码，密码不会出现在命令里
+const SUDO_PROMPT: &str = "[cnok-become-password]";
+// su只能从终端读密码，使用pty，按C语言环境下的提示符识别
+const SU_PROMPT: &str = "Password: ";
+// 提权成功、命令开始执行前输出，之前的内容(提示符、sudo的提醒)从输出里去掉
+const READY_MARKER: &str = "[cnok-become-ready]";
+const REDACTED: &str = "********";
+pub const DEFAULT_BECOME_USER: &str = "root";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BecomeMethod {
+    Sudo,
+    Su,
+}
+
+impl BecomeMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BecomeMethod::Sudo => "sudo",
+            BecomeMethod::Su => "su",
+        }
+    }
+}
+
+impl FromStr for BecomeMethod {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sudo" => Ok(BecomeMethod::Sudo),
+            "su" => Ok(BecomeMethod::Su),
+            other => Err(anyhow!("unknown become method: {}", other)),
+        }
+    }
+}
+
+// 提权方式和目标用户：server上设置的对所有执行生效，任务上设置的覆盖server的
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BecomeSpec {
+    pub method: BecomeMethod,
+    pub user: String,
+}
+
+impl BecomeSpec {
+    // 数据库里的become_method、become_user两列，method为空时不提权，user为空时为root
+    pub fn from_columns(method: Option<&str>, user: Option<&str>) -> Result<Option<BecomeSpec>, anyhow::Error> {
+        let Some(method) = method.filter(|m| !m.is_empty()) else {
+            return Ok(None);
+        };
+        Ok(Some(BecomeSpec {
+            method: method.parse()?,
+            user: user.filter(|u| !u.is_empty()).unwrap_or(DEFAULT_BECOME_USER).to_string(),
+        }))
+    }
+}
+
+// 执行时才带上解密后的密码，Debug不输出密码
+#[derive(Clone)]
+pub struct Become {
+    pub spec: BecomeSpec,
+    pub password: String,
+}
+
+impl fmt::Debug for Become {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Become").field("spec", &self.spec).field("password", &REDACTED).finish()
+    }
+}
+
+impl Become {
+    // 包装成提权执行的命令，命令本身作为sh -c的参数，开始执行前先输出READY_MARKER
+    pub fn wrap(&self, command: &str) -> String {
+        let inner = format!("printf '%s' {} >&2; {}", shell_quote(READY_MARKER), command);
+        match self.spec.method {
+            // -k 忽略缓存的凭据，每次都从stdin读密码，避免密码被当成命令的输入
+            BecomeMethod::Sudo => format!(
+                "sudo -S -k -p {} -u {} -- sh -c {}",
+                shell_quote(SUDO_PROMPT), shell_quote(&self.spec.user), shell_quote(&inner)
+            ),
+            BecomeMethod::Su => format!("LC_ALL=C su {} -c {}", shell_quote(&self.spec.user), shell_quote(&inner)),
+        }
+    }
+
+    pub fn needs_pty(&self) -> bool {
+        self.spec.method == BecomeMethod::Su
+    }
+
+    // 输出里如果带出了密码，替换掉再返回和记录日志
+    pub fn redact(&self, text: String) -> String {
+        if self.password.is_empty() || !text.contains(&self.password) {
+            return text;
+        }
+        text.replace(&self.password, REDACTED)
+    }
+
+    pub fn scanner(&self) -> BecomeScanner {
+        let prompt = match self.spec.method {
+            BecomeMethod::Sudo => SUDO_PROMPT,
+            BecomeMethod::Su => SU_PROMPT,
+        };
+        BecomeScanner { prompt, prompted: false, ready: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BecomeEvent {
+    SendPassword, // 第一次出现提示符，发送密码
+    Abort,        // 密码错误后再次提示，发送EOF让sudo失败退出
+    Ready,        // 提权成功，可以发送命令的输入
+}
+
+// 在提权成功前的输出里找提示符和READY_MARKER，找到的从输出里去掉
+#[derive(Debug)]
+pub struct BecomeScanner {
+    prompt: &'static str,
+    prompted: bool,
+    ready: bool,
+}
+
+impl BecomeScanner {
+    // buffer为收到数据的那一路输出，sudo的提示在stderr，su在pty里都在stdout
+    pub fn scan(&mut self, buffer: &mut Vec<u8>) -> Vec<BecomeEvent> {
+        let mut events = Vec::new();
+        if self.ready {
+            return events;
+        }
+        while let Some(pos) = find(buffer, self.prompt.as_bytes()) {
+            buffer.drain(pos..pos + self.prompt.len());
+            events.push(if self.prompted { BecomeEvent::Abort } else { BecomeEvent::SendPassword });
+            self.prompted = true;
+        }
+        if let Some(pos) = find(buffer, READY_MARKER.as_bytes()) {
+            buffer.drain(..pos + READY_MARKER.len());
+            self.ready = true;
+            events.push(BecomeEvent::Ready);
+        }
+        events
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn become_as(method: BecomeMethod, user: &str) -> Become {
+        Become { spec: BecomeSpec { method, user: user.to_string() }, password: "s3cret".to_string() }
+    }
+
+    #[test]
+    fn test_wrap() {
+        let sudo = become_as(BecomeMethod::Sudo, "deploy");
+        let command = sudo.wrap("echo 'hi'");
+        assert!(command.starts_with("sudo -S -k -p '[cnok-become-password]' -u deploy -- sh -c '"));
+        assert!(command.contains(r#"echo '\''hi'\''"#));
+        assert!(!command.contains("s3cret"));
+        let su = become_as(BecomeMethod::Su, "root");
+        assert!(su.wrap("id").starts_with("LC_ALL=C su root -c '"));
+        assert!(su.needs_pty() && !sudo.needs_pty());
+        assert!(!format!("{:?}", su).contains("s3cret"));
+    }
+
+    #[test]
+    fn test_scan() {
+        let sudo = become_as(BecomeMethod::Sudo, "root");
+        let mut scanner = sudo.scanner();
+        let mut stderr = b"We trust you have received the usual lecture\n[cnok-become-pass".to_vec();
+        assert!(scanner.scan(&mut stderr).is_empty());
+        stderr.extend_from_slice(b"word]");
+        assert_eq!(scanner.scan(&mut stderr), vec![BecomeEvent::SendPassword]);
+        stderr.extend_from_slice(b"[cnok-become-ready]warning\n");
+        assert_eq!(scanner.scan(&mut stderr), vec![BecomeEvent::Ready]);
+        assert_eq!(stderr, b"warning\n");
+        // 提权后命令自己的输出不再处理
+        stderr.extend_from_slice(b"[cnok-become-password]");
+        assert!(scanner.scan(&mut stderr).is_empty());
+
+        let mut scanner = sudo.scanner();
+        let mut stderr = b"[cnok-become-password]Sorry, try again.\n[cnok-become-password]".to_vec();
+        assert_eq!(scanner.scan(&mut stderr), vec![BecomeEvent::SendPassword, BecomeEvent::Abort]);
+        assert_eq!(stderr, b"Sorry, try again.\n");
+
+        // NOPASSWD时没有提示符，直接开始执行
+        let mut scanner = sudo.scanner();
+        let mut stderr = b"[cnok-become-ready]".to_vec();
+        assert_eq!(scanner.scan(&mut stderr), vec![BecomeEvent::Ready]);
+    }
+
+    #[test]
+    fn test_spec_and_redact() {
+        assert_eq!(BecomeSpec::from_columns(None, Some("app")).unwrap(), None);
+        assert_eq!(BecomeSpec::from_columns(Some(""), None).unwrap(), None);
+        assert_eq!(
+            BecomeSpec::from_columns(Some("su"), None).unwrap(),
+            Some(BecomeSpec { method: BecomeMethod::Su, user: "root".to_string() })
+        );
+        assert!(BecomeSpec::from_columns(Some("doas"), None).is_err());
+        let sudo = become_as(BecomeMethod::Sudo, "root");
+        assert_eq!(sudo.redact("password is s3cret".to_string()), "password is ********");
+    }
+}
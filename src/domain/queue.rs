@@ -0,0 +1,152 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::cron_job::CronJob;
+
+// 队列里的一条记录，score为毫秒时间戳
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueEntry {
+    pub job_id: i32,
+    pub score: i64,
+}
+
+impl QueueEntry {
+    pub fn new(job_id: i32, score: i64) -> Self {
+        Self { job_id, score }
+    }
+
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        Utc.timestamp_millis_opt(self.score).single()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    pub pending: Vec<QueueEntry>,     // score为执行时间
+    pub processing: Vec<QueueEntry>,  // score为超时死线
+}
+
+impl QueueSnapshot {
+    pub fn new(mut pending: Vec<QueueEntry>, mut processing: Vec<QueueEntry>) -> Self {
+        pending.sort_by_key(|entry| entry.score);
+        processing.sort_by_key(|entry| entry.score);
+        Self { pending, processing }
+    }
+}
+
+// acquire时用到的任务属性：同时到期时先取优先级高的，所属group达到并发上限时跳过
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JobMeta {
+    pub priority: i32,
+    pub group_id: Option<i32>,
+}
+
+impl JobMeta {
+    pub fn from_job(job: &CronJob) -> Self {
+        Self { priority: job.priority, group_id: job.group_id }
+    }
+}
+
+// 等待执行的任务
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedJob {
+    pub job_id: i32,
+    pub name: Option<String>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub overdue_ms: i64, // 已经超过执行时间多久还没被取走，未到期为0
+}
+
+// 已被worker取走、正在执行的任务
+#[derive(Debug, Clone, Serialize)]
+pub struct InFlightJob {
+    pub job_id: i32,
+    pub name: Option<String>,
+    pub lease_deadline: Option<DateTime<Utc>>,
+    pub expired: bool, // 超过死线仍在processing中，worker可能已经挂掉
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueView {
+    pub pending: Vec<QueuedJob>,
+    pub processing: Vec<InFlightJob>,
+}
+
+// 把任务放回pending，默认立即执行
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RequeueJob {
+    pub execute_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueState {
+    Pending,
+    Processing,
+}
+
+// 删除队列记录时只删哪个队列，默认两个都删
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DropJobQuery {
+    pub state: Option<QueueState>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QueueLagQuery {
+    pub minutes: Option<i64>, // 统计最近多少分钟内的定时运行，默认60
+}
+
+// 从到期到被worker取走的延迟分布，单位毫秒
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LagStats {
+    pub count: usize,
+    pub avg_ms: i64,
+    pub p50_ms: i64,
+    pub p95_ms: i64,
+    pub max_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueLag {
+    pub window_minutes: i64,
+    pub picked_up: LagStats,   // 窗口内定时运行的实际延迟
+    pub overdue_count: usize,  // 当前已到期但还在pending中的任务数
+    pub max_overdue_ms: i64,
+}
+
+// 全量同步的结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResyncReport {
+    pub added: usize,    // 队列中缺失、从SQL补上的任务
+    pub removed: usize,  // pending中已经停用、删除或不再需要调度的任务
+}
+
+pub fn lag_stats(mut samples_ms: Vec<i64>) -> LagStats {
+    if samples_ms.is_empty() {
+        return LagStats::default();
+    }
+    samples_ms.sort_unstable();
+    let count = samples_ms.len();
+    // 最近秩法取百分位
+    let percentile = |p: usize| samples_ms[((count * p).div_ceil(100)).clamp(1, count) - 1];
+    LagStats {
+        count,
+        avg_ms: samples_ms.iter().sum::<i64>() / count as i64,
+        p50_ms: percentile(50),
+        p95_ms: percentile(95),
+        max_ms: samples_ms[count - 1],
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lag_stats() {
+        assert_eq!(lag_stats(Vec::new()), LagStats::default());
+        let stats = lag_stats((1..=100).rev().collect());
+        assert_eq!(stats, LagStats { count: 100, avg_ms: 50, p50_ms: 50, p95_ms: 95, max_ms: 100 });
+        let stats = lag_stats(vec![30, 10, 20]);
+        assert_eq!(stats, LagStats { count: 3, avg_ms: 20, p50_ms: 20, p95_ms: 30, max_ms: 30 });
+    }
+}
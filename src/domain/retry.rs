@@ -0,0 +1,134 @@
+use std::str::FromStr;
+use aes_gcm::aead::{OsRng, rand_core::RngCore};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use crate::domain::cron_job::CronJob;
+use crate::domain::ssh_session::{FailureClass, SshFailure};
+
+pub const DEFAULT_RETRY_DELAY_MS: i32 = 200;
+pub const DEFAULT_RETRY_MAX_DELAY_MS: i32 = 60_000;
+// exponential每次翻倍，超过这个次数后不再增长(已经远大于max_delay)
+const MAX_BACKOFF_SHIFT: u32 = 30;
+
+// 两次重试之间的等待方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryBackoff {
+    #[default]
+    Fixed,        // 每次等待delay
+    Exponential,  // delay * 2^(n-1)，不超过max_delay
+    Jitter,       // exponential的基础上随机取后一半，避免大量server同时重连
+}
+
+impl RetryBackoff {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetryBackoff::Fixed => "fixed",
+            RetryBackoff::Exponential => "exponential",
+            RetryBackoff::Jitter => "jitter",
+        }
+    }
+}
+
+impl FromStr for RetryBackoff {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(RetryBackoff::Fixed),
+            "exponential" => Ok(RetryBackoff::Exponential),
+            "jitter" => Ok(RetryBackoff::Jitter),
+            _ => Err(anyhow!("Invalid retry backoff: {}", s)),
+        }
+    }
+}
+
+// 默认所有失败都重试
+pub fn default_retry_on() -> Vec<String> {
+    [FailureClass::Connect, FailureClass::Auth, FailureClass::Timeout, FailureClass::NonZero, FailureClass::Output]
+        .iter()
+        .map(|c| c.as_str().to_string())
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub backoff: RetryBackoff,
+    pub delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub retry_on: Vec<FailureClass>,
+    pub exit_codes: Option<Vec<i32>>, // non_zero时只重试这些退出码，为空重试所有非0退出码
+}
+
+impl RetryPolicy {
+    pub fn from_job(job: &CronJob) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            max_retries: job.retry_count.unwrap_or(0).max(0) as u32,
+            backoff: job.retry_backoff.parse()?,
+            delay_ms: job.retry_delay_ms.max(0) as u64,
+            max_delay_ms: job.retry_max_delay_ms.max(0) as u64,
+            retry_on: job.retry_on.iter().map(|c| c.parse()).collect::<Result<_, _>>()?,
+            exit_codes: job.retry_exit_codes.clone(),
+        })
+    }
+
+    pub fn should_retry(&self, failure: &SshFailure) -> bool {
+        if !self.retry_on.contains(&failure.class) {
+            return false;
+        }
+        match (&self.exit_codes, failure.exit_code) {
+            (Some(codes), Some(code)) if failure.class == FailureClass::NonZero => codes.contains(&(code as i32)),
+            _ => true,
+        }
+    }
+
+    // 第attempt次重试(从1开始)前的等待时间
+    pub fn delay(&self, attempt: u32) -> std::time::Duration {
+        let fraction = (OsRng.next_u32() as f64) / (u32::MAX as f64);
+        std::time::Duration::from_millis(backoff_delay_ms(self.backoff, self.delay_ms, self.max_delay_ms, attempt, fraction))
+    }
+}
+
+// fraction为[0,1]的随机数，只有jitter使用
+pub fn backoff_delay_ms(backoff: RetryBackoff, delay_ms: u64, max_delay_ms: u64, attempt: u32, fraction: f64) -> u64 {
+    let shift = attempt.saturating_sub(1).min(MAX_BACKOFF_SHIFT);
+    let exponential = delay_ms.saturating_mul(1u64 << shift).min(max_delay_ms);
+    match backoff {
+        RetryBackoff::Fixed => delay_ms.min(max_delay_ms),
+        RetryBackoff::Exponential => exponential,
+        RetryBackoff::Jitter => exponential / 2 + (exponential as f64 / 2.0 * fraction.clamp(0.0, 1.0)) as u64,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay_ms(RetryBackoff::Fixed, 200, 60_000, 5, 0.5), 200);
+        assert_eq!(backoff_delay_ms(RetryBackoff::Exponential, 200, 60_000, 1, 0.5), 200);
+        assert_eq!(backoff_delay_ms(RetryBackoff::Exponential, 200, 60_000, 4, 0.5), 1600);
+        assert_eq!(backoff_delay_ms(RetryBackoff::Exponential, 200, 1000, 10, 0.5), 1000);
+        assert_eq!(backoff_delay_ms(RetryBackoff::Exponential, 200, 60_000, 100, 0.5), 60_000);
+        assert_eq!(backoff_delay_ms(RetryBackoff::Jitter, 200, 60_000, 3, 0.0), 400);
+        assert_eq!(backoff_delay_ms(RetryBackoff::Jitter, 200, 60_000, 3, 1.0), 800);
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            backoff: RetryBackoff::Fixed,
+            delay_ms: 200,
+            max_delay_ms: 60_000,
+            retry_on: vec![FailureClass::Connect, FailureClass::NonZero],
+            exit_codes: Some(vec![75]),
+        };
+        assert!(policy.should_retry(&SshFailure::new(FailureClass::Connect, "refused".to_string())));
+        assert!(!policy.should_retry(&SshFailure::new(FailureClass::Auth, "denied".to_string())));
+        assert!(policy.should_retry(&SshFailure::non_zero(75)));
+        assert!(!policy.should_retry(&SshFailure::non_zero(1)));
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::domain::ssh_session::HostResult;
+
+// 常用命令库：保存日常排查用的命令或脚本，可以直接在server、group或标签选中的server上执行，也可以作为任务的命令
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Runbook {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,              // 单条命令或多行脚本
+    pub tags: Vec<String>,
+    pub server_id: Option<i32>,    // 默认执行目标，执行时可以覆盖
+    pub group_id: Option<i32>,
+    pub selector: Value,           // 默认的标签选择器，labels包含这些键值的server
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateRunbook {
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub server_id: Option<i32>,
+    pub group_id: Option<i32>,
+    pub selector: Option<HashMap<String, String>>,
+}
+
+// 目标字段传0(selector传{})时清除默认目标
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateRunbook {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub body: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub server_id: Option<i32>,
+    pub group_id: Option<i32>,
+    pub selector: Option<HashMap<String, String>>,
+}
+
+// 列表的查询参数
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RunbookQuery {
+    pub tag: Option<String>, // 只查带这个标签的
+}
+
+// 执行请求，三种目标只能给一个，都不给时使用命令库里保存的默认目标
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RunRunbook {
+    pub server_id: Option<i32>,
+    pub group_id: Option<i32>,
+    pub selector: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunbookTarget {
+    Server(i32),
+    Group(i32),
+    Selector(HashMap<String, String>),
+}
+
+impl RunRunbook {
+    // 执行请求里给出的目标优先，否则使用默认目标
+    pub fn target(&self, runbook: &Runbook) -> Result<RunbookTarget, anyhow::Error> {
+        if let Some(target) = pick_target(self.server_id, self.group_id, self.selector.clone())? {
+            return Ok(target);
+        }
+        let selector = serde_json::from_value::<HashMap<String, String>>(runbook.selector.clone())
+            .map_err(|e| anyhow!("invalid selector of runbook {}: {}", runbook.id, e))?;
+        pick_target(runbook.server_id, runbook.group_id, Some(selector))?
+            .ok_or_else(|| anyhow!("runbook {} has no default target, server_id, group_id or selector is required", runbook.id))
+    }
+}
+
+// 创建、修改时检查默认目标最多只有一个
+pub fn validate_default_target(server_id: Option<i32>, group_id: Option<i32>, selector: Option<&HashMap<String, String>>) -> Result<(), anyhow::Error> {
+    pick_target(server_id, group_id, selector.cloned()).map(|_| ())
+}
+
+// 标签去掉首尾空白后不能为空，也不能重复
+pub fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, anyhow::Error> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_string();
+        if tag.is_empty() {
+            return Err(anyhow!("tag must not be empty"));
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+// 0和空的selector视为没有给出
+pub fn pick_target(server_id: Option<i32>, group_id: Option<i32>, selector: Option<HashMap<String, String>>) -> Result<Option<RunbookTarget>, anyhow::Error> {
+    let mut targets = Vec::new();
+    if let Some(server_id) = server_id.filter(|id| *id != 0) {
+        targets.push(RunbookTarget::Server(server_id));
+    }
+    if let Some(group_id) = group_id.filter(|id| *id != 0) {
+        targets.push(RunbookTarget::Group(group_id));
+    }
+    if let Some(selector) = selector.filter(|s| !s.is_empty()) {
+        targets.push(RunbookTarget::Selector(selector));
+    }
+    if targets.len() > 1 {
+        return Err(anyhow!("only one of server_id, group_id and selector can be given"));
+    }
+    Ok(targets.pop())
+}
+
+// 一台server上的执行结果
+#[derive(Debug, Clone, Serialize)]
+pub struct RunbookHostResult {
+    pub server: String,
+    pub exit_code: Option<u32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>, // 连接、认证失败或超时
+}
+
+impl From<HostResult> for RunbookHostResult {
+    fn from(host: HostResult) -> Self {
+        match host.result {
+            Ok(output) => RunbookHostResult {
+                server: host.server,
+                exit_code: Some(output.exit_code),
+                stdout: output.stdout,
+                stderr: output.stderr,
+                error: None,
+            },
+            Err(failure) => RunbookHostResult {
+                server: host.server,
+                exit_code: failure.exit_code,
+                stdout: String::new(),
+                stderr: String::new(),
+                error: Some(failure.message),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunbookRunResult {
+    pub runbook_id: i32,
+    pub results: Vec<RunbookHostResult>,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ssh_session::{CommandOutput, FailureClass, SshFailure};
+
+    fn runbook(server_id: Option<i32>, group_id: Option<i32>, selector: Value) -> Runbook {
+        Runbook {
+            id: 1,
+            name: "disk usage".to_string(),
+            description: None,
+            body: "df -h".to_string(),
+            tags: vec![],
+            server_id,
+            group_id,
+            selector,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_target_request_overrides_default() {
+        let book = runbook(None, Some(3), serde_json::json!({}));
+        assert_eq!(RunRunbook::default().target(&book).unwrap(), RunbookTarget::Group(3));
+        let request = RunRunbook { server_id: Some(7), ..Default::default() };
+        assert_eq!(request.target(&book).unwrap(), RunbookTarget::Server(7));
+        let selector = HashMap::from([("env".to_string(), "prod".to_string())]);
+        let request = RunRunbook { selector: Some(selector.clone()), ..Default::default() };
+        assert_eq!(request.target(&book).unwrap(), RunbookTarget::Selector(selector));
+    }
+
+    #[test]
+    fn test_target_errors() {
+        let book = runbook(None, None, serde_json::json!({}));
+        assert!(RunRunbook::default().target(&book).is_err());
+        let request = RunRunbook { server_id: Some(1), group_id: Some(2), selector: None };
+        assert!(request.target(&book).is_err());
+        let book = runbook(None, None, serde_json::json!({"env": "prod"}));
+        assert_eq!(
+            RunRunbook::default().target(&book).unwrap(),
+            RunbookTarget::Selector(HashMap::from([("env".to_string(), "prod".to_string())]))
+        );
+        assert!(validate_default_target(Some(1), Some(0), Some(&HashMap::new())).is_ok());
+        assert!(validate_default_target(Some(1), Some(2), None).is_err());
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = vec![" disk ".to_string(), "systemd".to_string(), "disk".to_string()];
+        assert_eq!(normalize_tags(tags).unwrap(), vec!["disk".to_string(), "systemd".to_string()]);
+        assert!(normalize_tags(vec!["  ".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_host_result() {
+        let ok = RunbookHostResult::from(HostResult {
+            server: "10.0.0.1".to_string(),
+            result: Ok(CommandOutput { exit_code: 0, stdout: "ok".to_string(), stderr: String::new() }),
+        });
+        assert_eq!((ok.exit_code, ok.stdout.as_str(), ok.error), (Some(0), "ok", None));
+        let failed = RunbookHostResult::from(HostResult {
+            server: "10.0.0.2".to_string(),
+            result: Err(SshFailure::new(FailureClass::Timeout, "timeout".to_string())),
+        });
+        assert_eq!((failed.exit_code, failed.error.as_deref()), (None, Some("timeout")));
+    }
+}
@@ -1,22 +1,85 @@
 use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
+use std::future::Future;
 use std::sync::{Arc, Mutex};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use std::cmp::Ordering;
 use anyhow::anyhow;
 use redis::{Client, AsyncCommands, Script};
+use sqlx::PgPool;
 use std::env;
 use tracing::info;
+use crate::domain::queue::{JobMeta, QueueEntry, QueueSnapshot};
 
 const ACQUIRE_JOB_SCRIPT: &str = include_str!("../script/acquire_job.lua");
+const RENEW_LOCK_SCRIPT: &str = include_str!("../script/renew_lock.lua");
+const RELEASE_LOCK_SCRIPT: &str = include_str!("../script/release_lock.lua");
+// 任务进入processing后的超时死线，执行期间由worker定时续期
+pub const PROCESSING_TIMEOUT_MS: i64 = 10_000;
+// 超时任务放回pending后的延迟
+const TIMEOUT_RETRY_DELAY_MS: i64 = 5_000;
+// 一次acquire最多从多少个到期任务中按优先级和group上限挑选
+const ACQUIRE_SCAN_LIMIT: i64 = 100;
+
+/// 调度队列的抽象，pending按执行时间排序，processing按超时死线排序
+/// 实现：Redis(JobScheduler)、内存(Scheduler)、Postgres(PgJobQueue)
+pub trait JobQueue: Clone + Send + Sync + 'static {
+    /// 从待执行队列获取一个到期任务，并移入处理中队列
+    /// 到期任务中取优先级最高的，同优先级取最早到期的；所属group在processing中的任务数达到上限时跳过
+    fn get_job(&self) -> impl Future<Output = Result<Option<i32>, anyhow::Error>> + Send;
+    /// 添加任务到待执行队列，已存在则更新执行时间
+    fn add_job(&self, job_id: i32, execute_at: i64) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+    /// 任务不在待执行队列时才添加，已存在则保留原执行时间，返回是否添加
+    fn add_job_nx(&self, job_id: i32, execute_at: i64) -> impl Future<Output = Result<bool, anyhow::Error>> + Send;
+    /// 从待执行队列移除
+    fn del_job_pending(&self, job_id: i32) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+    /// 任务完成，从处理中队列移除
+    fn del_job(&self, job_id: i32) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+    /// 清除所有队列，返回 (pending数量, processing数量)
+    fn clear_all_jobs(&self) -> impl Future<Output = Result<(usize, usize), anyhow::Error>> + Send;
+    /// 任务失败，从处理中移除并重新放回待执行队列
+    fn retry_job(&self, job_id: i32, retry_after: i64) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+    /// 清理超时任务，移回待执行队列
+    fn del_timeout_jobs(&self) -> impl Future<Output = Result<Vec<i32>, anyhow::Error>> + Send;
+    /// 续期处理中任务的超时死线，任务已不在处理中队列时返回false
+    fn renew_job(&self, job_id: i32) -> impl Future<Output = Result<bool, anyhow::Error>> + Send;
+    /// 获取任务锁，锁被别人持有且未过期时返回false
+    fn acquire_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> impl Future<Output = Result<bool, anyhow::Error>> + Send;
+    /// 强制抢占任务锁，原持有者续期失败后自行取消
+    fn force_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+    /// 续期任务锁，锁已不属于自己时返回false
+    fn renew_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> impl Future<Output = Result<bool, anyhow::Error>> + Send;
+    /// 释放自己持有的任务锁
+    fn release_lock(&self, job_id: i32, token: &str) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+    /// 查看两个队列的内容，pending的score为执行时间，processing的score为超时死线，均按score排序
+    fn list_jobs(&self) -> impl Future<Output = Result<QueueSnapshot, anyhow::Error>> + Send;
+    /// 同步任务的优先级和所属group，供get_job挑选任务
+    fn set_job_meta(&self, job_id: i32, meta: JobMeta) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+    /// 设置group同时执行的任务数上限，None为不限制
+    fn set_group_limit(&self, group_id: i32, limit: Option<i32>) -> impl Future<Output = Result<(), anyhow::Error>> + Send;
+}
+
+fn lock_key(job_id: i32) -> String {
+    format!("scheduler:lock:{}", job_id)
+}
+
 #[derive(Debug,Eq,PartialEq,PartialOrd)]
 pub struct CronWorker {
     pub next_execute_at: DateTime<Utc>,
     pub cronjob_id: i32
 }
 pub struct SchedulerInner{ // 小顶堆
-    heap: BinaryHeap<Reverse<CronWorker>>
+    heap: BinaryHeap<Reverse<CronWorker>>,
+    // 堆里允许存在旧记录，以pending中的时间为准
+    pending: HashMap<i32, DateTime<Utc>>,
+    processing: HashMap<i32, i64>,
+    // job_id -> (token, 过期时间)
+    locks: HashMap<i32, (String, i64)>,
+    meta: HashMap<i32, JobMeta>,
+    group_limits: HashMap<i32, i32>,
 }
+/// 单进程内存队列，适合单节点部署和测试，多个worker之间不共享
+#[derive(Clone)]
 pub struct Scheduler{
     inner: Arc<Mutex<SchedulerInner>>,
 }
@@ -33,31 +96,237 @@ impl CronWorker{
         }
     }
 }
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Scheduler{
     pub fn new() -> Self{
         Scheduler{
             inner: Arc::new(Mutex::new(
                 SchedulerInner{
                     heap: BinaryHeap::new(),
+                    pending: HashMap::new(),
+                    processing: HashMap::new(),
+                    locks: HashMap::new(),
+                    meta: HashMap::new(),
+                    group_limits: HashMap::new(),
                 }
             ))
         }
     }
     pub fn push(&self, worker: CronWorker) -> Result<(),anyhow::Error> {
         let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        lock.pending.insert(worker.cronjob_id, worker.next_execute_at);
         Ok(lock.heap.push(Reverse(worker)))
     }
     pub fn pop(&self) -> Result<Option<Reverse<CronWorker>>, anyhow::Error> {
         let lock = self.inner.lock();
         match lock{
             Ok(mut worker) => {
-                Ok(worker.heap.pop())
+                let inner = &mut *worker;
+                while let Some(Reverse(top)) = inner.heap.pop() {
+                    if inner.pending.get(&top.cronjob_id) == Some(&top.next_execute_at) {
+                        inner.pending.remove(&top.cronjob_id);
+                        return Ok(Some(Reverse(top)));
+                    }
+                }
+                Ok(None)
             }
             _ => Err(anyhow::Error::msg("can't get mutex lock"))
         }
     }
 }
 
+impl JobQueue for Scheduler {
+    async fn get_job(&self) -> Result<Option<i32>, anyhow::Error> {
+        let now = Utc::now();
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        let inner = &mut *lock;
+        // 被覆盖、删除或已取走的旧记录直接丢弃
+        while let Some(Reverse(top)) = inner.heap.peek() {
+            if inner.pending.get(&top.cronjob_id) == Some(&top.next_execute_at) {
+                break;
+            }
+            inner.heap.pop();
+        }
+        // 最早的任务都没到期
+        if inner.heap.peek().is_none_or(|Reverse(top)| top.next_execute_at > now) {
+            return Ok(None);
+        }
+        // 超过死线的记录属于已经退出的worker，不占group的名额
+        let mut running: HashMap<i32, i32> = HashMap::new();
+        for (job_id, _) in inner.processing.iter().filter(|(_, deadline)| **deadline > now.timestamp_millis()) {
+            if let Some(group_id) = inner.meta.get(job_id).and_then(|meta| meta.group_id) {
+                *running.entry(group_id).or_default() += 1;
+            }
+        }
+        let group_full = |job_id: &i32| {
+            inner.meta.get(job_id).and_then(|meta| meta.group_id).is_some_and(|group_id| {
+                inner.group_limits.get(&group_id).is_some_and(|limit| running.get(&group_id).copied().unwrap_or(0) >= *limit)
+            })
+        };
+        let job_id = inner.pending.iter()
+            .filter(|(job_id, execute_at)| **execute_at <= now && !group_full(job_id))
+            .max_by_key(|(job_id, execute_at)| {
+                (inner.meta.get(job_id).map(|meta| meta.priority).unwrap_or(0), Reverse(**execute_at), Reverse(**job_id))
+            })
+            .map(|(job_id, _)| *job_id);
+        // 取走的任务在堆里的记录留到下次清理
+        if let Some(job_id) = job_id {
+            inner.pending.remove(&job_id);
+            inner.processing.insert(job_id, now.timestamp_millis() + PROCESSING_TIMEOUT_MS);
+        }
+        Ok(job_id)
+    }
+
+    async fn add_job(&self, job_id: i32, execute_at: i64) -> Result<(), anyhow::Error> {
+        let execute_at = Utc.timestamp_millis_opt(execute_at).single()
+            .ok_or_else(|| anyhow!("invalid execute time {}", execute_at))?;
+        self.push(CronWorker::new(execute_at, job_id))?;
+        info!("job {} added to queue", job_id);
+        Ok(())
+    }
+
+    async fn add_job_nx(&self, job_id: i32, execute_at: i64) -> Result<bool, anyhow::Error> {
+        let execute_at = Utc.timestamp_millis_opt(execute_at).single()
+            .ok_or_else(|| anyhow!("invalid execute time {}", execute_at))?;
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        if lock.pending.contains_key(&job_id) {
+            return Ok(false);
+        }
+        lock.pending.insert(job_id, execute_at);
+        lock.heap.push(Reverse(CronWorker::new(execute_at, job_id)));
+        info!("job {} added to queue", job_id);
+        Ok(true)
+    }
+
+    async fn del_job_pending(&self, job_id: i32) -> Result<(), anyhow::Error> {
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        lock.pending.remove(&job_id);
+        info!("job {} deleted from queue", job_id);
+        Ok(())
+    }
+
+    async fn del_job(&self, job_id: i32) -> Result<(), anyhow::Error> {
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        lock.processing.remove(&job_id);
+        info!("job {} complete del from processing", job_id);
+        Ok(())
+    }
+
+    async fn clear_all_jobs(&self) -> Result<(usize, usize), anyhow::Error> {
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        let pending_count = lock.pending.len();
+        let processing_count = lock.processing.len();
+        lock.heap.clear();
+        lock.pending.clear();
+        lock.processing.clear();
+        info!(
+            "Cleared {} pending jobs and {} processing jobs",
+            pending_count,
+            processing_count
+        );
+        Ok((pending_count, processing_count))
+    }
+
+    async fn retry_job(&self, job_id: i32, retry_after: i64) -> Result<(), anyhow::Error> {
+        self.del_job(job_id).await?;
+        self.add_job(job_id, retry_after).await
+    }
+
+    async fn del_timeout_jobs(&self) -> Result<Vec<i32>, anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        let timeout_jobs: Vec<i32> = {
+            let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+            let jobs: Vec<i32> = lock.processing.iter()
+                .filter(|(_, deadline)| **deadline <= current_ts)
+                .map(|(job_id, _)| *job_id)
+                .collect();
+            for job_id in &jobs {
+                lock.processing.remove(job_id);
+            }
+            jobs
+        };
+        for job_id in &timeout_jobs {
+            self.add_job(*job_id, current_ts + TIMEOUT_RETRY_DELAY_MS).await?;
+        }
+        Ok(timeout_jobs)
+    }
+
+    async fn renew_job(&self, job_id: i32) -> Result<bool, anyhow::Error> {
+        let deadline = Utc::now().timestamp_millis() + PROCESSING_TIMEOUT_MS;
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        Ok(lock.processing.get_mut(&job_id).map(|old| *old = deadline).is_some())
+    }
+
+    async fn acquire_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<bool, anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        if let Some((_, expires_at)) = lock.locks.get(&job_id)
+            && *expires_at > current_ts
+        {
+            return Ok(false);
+        }
+        lock.locks.insert(job_id, (token.to_string(), current_ts + ttl_ms));
+        Ok(true)
+    }
+
+    async fn force_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<(), anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        lock.locks.insert(job_id, (token.to_string(), current_ts + ttl_ms));
+        Ok(())
+    }
+
+    async fn renew_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<bool, anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        match lock.locks.get_mut(&job_id) {
+            Some((owner, expires_at)) if owner == token => {
+                *expires_at = current_ts + ttl_ms;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn release_lock(&self, job_id: i32, token: &str) -> Result<(), anyhow::Error> {
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        if lock.locks.get(&job_id).is_some_and(|(owner, _)| owner == token) {
+            lock.locks.remove(&job_id);
+        }
+        Ok(())
+    }
+
+    async fn list_jobs(&self) -> Result<QueueSnapshot, anyhow::Error> {
+        let lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        let pending = lock.pending.iter()
+            .map(|(job_id, execute_at)| QueueEntry::new(*job_id, execute_at.timestamp_millis()))
+            .collect();
+        let processing = lock.processing.iter()
+            .map(|(job_id, deadline)| QueueEntry::new(*job_id, *deadline))
+            .collect();
+        Ok(QueueSnapshot::new(pending, processing))
+    }
+
+    async fn set_job_meta(&self, job_id: i32, meta: JobMeta) -> Result<(), anyhow::Error> {
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        lock.meta.insert(job_id, meta);
+        Ok(())
+    }
+
+    async fn set_group_limit(&self, group_id: i32, limit: Option<i32>) -> Result<(), anyhow::Error> {
+        let mut lock = self.inner.lock().map_err(|e| anyhow!(e.to_string()))?;
+        match limit {
+            Some(limit) => lock.group_limits.insert(group_id, limit),
+            None => lock.group_limits.remove(&group_id),
+        };
+        Ok(())
+    }
+}
+
 #[derive(Debug,Clone)]
 pub struct JobScheduler {
     redis: Client,
@@ -81,20 +350,25 @@ impl JobScheduler {
             script_sha: sha,
         })
     }
+}
+impl JobQueue for JobScheduler {
     /// 从待执行队列获取一个到期任务
-    pub async fn get_job(&self) -> Result<Option<i32>, anyhow::Error> {
+    async fn get_job(&self) -> Result<Option<i32>, anyhow::Error> {
         let mut con = self.redis.get_multiplexed_async_connection().await?;
 
         let current_ts = Utc::now().timestamp_millis();
-        let timeout_ms = 10_000;  // 30秒超时
 
         let job_id: Option<i32> = redis::cmd("EVALSHA")
             .arg(&self.script_sha)
-            .arg(2)  // 2 个 KEYS
+            .arg(5)  // 5 个 KEYS
             .arg("scheduler:pending")
             .arg("scheduler:processing")
+            .arg("scheduler:priority")
+            .arg("scheduler:job_group")
+            .arg("scheduler:group_limit")
             .arg(current_ts)
-            .arg(timeout_ms)
+            .arg(PROCESSING_TIMEOUT_MS)
+            .arg(ACQUIRE_SCAN_LIMIT)
             .query_async(&mut con)
             .await?;
 
@@ -102,7 +376,7 @@ impl JobScheduler {
     }
 
     /// 添加任务到待执行队列
-    pub async fn add_job(
+    async fn add_job(
         &self,
         job_id: i32,
         execute_at: i64  // 毫秒时间戳
@@ -115,8 +389,25 @@ impl JobScheduler {
 
         Ok(())
     }
+    /// 不在待执行队列时才添加
+    async fn add_job_nx(&self, job_id: i32, execute_at: i64) -> Result<bool, anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+
+        // ZADD scheduler:pending NX <timestamp> <job_id>
+        let added: i32 = redis::cmd("ZADD")
+            .arg("scheduler:pending")
+            .arg("NX")
+            .arg(execute_at)
+            .arg(job_id)
+            .query_async(&mut con)
+            .await?;
+        if added > 0 {
+            info!("job {} added to queue", job_id);
+        }
+        Ok(added > 0)
+    }
     /// 任务完成，从待执行队列移除
-    pub async fn del_job_pending(&self, job_id: i32) -> Result<(), anyhow::Error> {
+    async fn del_job_pending(&self, job_id: i32) -> Result<(), anyhow::Error> {
         let mut con = self.redis.get_multiplexed_async_connection().await?;
 
         let _: () = con.zrem("scheduler:pending", job_id).await?;
@@ -124,7 +415,7 @@ impl JobScheduler {
         Ok(())
     }
         /// 任务完成，从待执行队列移除
-    pub async fn del_job(&self, job_id: i32) -> Result<(), anyhow::Error> {
+    async fn del_job(&self, job_id: i32) -> Result<(), anyhow::Error> {
         let mut con = self.redis.get_multiplexed_async_connection().await?;
 
         let _:() =con.zrem("scheduler:processing", job_id).await?;
@@ -132,7 +423,7 @@ impl JobScheduler {
         Ok(())
     }
     /// 清除所有队列（包括待执行和处理中）
-    pub async fn clear_all_jobs(&self) -> Result<(usize, usize), anyhow::Error> {
+    async fn clear_all_jobs(&self) -> Result<(usize, usize), anyhow::Error> {
         let mut con = self.redis.get_multiplexed_async_connection().await?;
 
         // 删除待执行队列
@@ -150,7 +441,7 @@ impl JobScheduler {
         Ok((pending_count, processing_count))
     }
     /// 任务失败，重新放回待执行队列
-    pub async fn retry_job(
+    async fn retry_job(
         &self,
         job_id: i32,
         retry_after: i64  // 毫秒时间戳
@@ -167,7 +458,7 @@ impl JobScheduler {
     }
 
     /// 清理超时任务（容错机制）
-    pub async fn del_timeout_jobs(&self) -> Result<Vec<i32>, anyhow::Error> {
+    async fn del_timeout_jobs(&self) -> Result<Vec<i32>, anyhow::Error> {
         let mut con = self.redis.get_multiplexed_async_connection().await?;
 
         let current_ts = Utc::now().timestamp_millis();
@@ -183,10 +474,606 @@ impl JobScheduler {
             let _:() =con.zrem("scheduler:processing", job_id).await?;
 
             // 立即重试或延迟重试
-            let retry_at = current_ts + 5000;  // 5秒后重试
+            let retry_at = current_ts + TIMEOUT_RETRY_DELAY_MS;  // 5秒后重试
             let _:() =con.zadd("scheduler:pending", job_id, retry_at).await?;
         }
 
         Ok(timeout_jobs)
     }
-}
\ No newline at end of file
+
+    /// ZADD XX只更新已有的记录，任务被移出processing后不会再加回去
+    async fn renew_job(&self, job_id: i32) -> Result<bool, anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+
+        let deadline = Utc::now().timestamp_millis() + PROCESSING_TIMEOUT_MS;
+        let renewed: i32 = redis::cmd("ZADD")
+            .arg("scheduler:processing")
+            .arg("XX")
+            .arg("CH")
+            .arg(deadline)
+            .arg(job_id)
+            .query_async(&mut con)
+            .await?;
+        Ok(renewed > 0)
+    }
+
+    /// SET NX PX，锁的值为持有者token
+    async fn acquire_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<bool, anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(lock_key(job_id))
+            .arg(token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl_ms)
+            .query_async(&mut con)
+            .await?;
+        Ok(reply.is_some())
+    }
+
+    async fn force_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<(), anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+        let _: () = redis::cmd("SET")
+            .arg(lock_key(job_id))
+            .arg(token)
+            .arg("PX")
+            .arg(ttl_ms)
+            .query_async(&mut con)
+            .await?;
+        Ok(())
+    }
+
+    async fn renew_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<bool, anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+        let renewed: i32 = Script::new(RENEW_LOCK_SCRIPT)
+            .key(lock_key(job_id))
+            .arg(token)
+            .arg(ttl_ms)
+            .invoke_async(&mut con)
+            .await?;
+        Ok(renewed == 1)
+    }
+
+    async fn release_lock(&self, job_id: i32, token: &str) -> Result<(), anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+        let _: i32 = Script::new(RELEASE_LOCK_SCRIPT)
+            .key(lock_key(job_id))
+            .arg(token)
+            .invoke_async(&mut con)
+            .await?;
+        Ok(())
+    }
+    async fn list_jobs(&self) -> Result<QueueSnapshot, anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+        let pending: Vec<(i32, i64)> = con.zrange_withscores("scheduler:pending", 0, -1).await?;
+        let processing: Vec<(i32, i64)> = con.zrange_withscores("scheduler:processing", 0, -1).await?;
+        Ok(QueueSnapshot::new(
+            pending.into_iter().map(|(job_id, score)| QueueEntry::new(job_id, score)).collect(),
+            processing.into_iter().map(|(job_id, score)| QueueEntry::new(job_id, score)).collect(),
+        ))
+    }
+
+    async fn set_job_meta(&self, job_id: i32, meta: JobMeta) -> Result<(), anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().hset("scheduler:priority", job_id, meta.priority).ignore();
+        match meta.group_id {
+            Some(group_id) => pipe.hset("scheduler:job_group", job_id, group_id).ignore(),
+            None => pipe.hdel("scheduler:job_group", job_id).ignore(),
+        };
+        let _: () = pipe.query_async(&mut con).await?;
+        Ok(())
+    }
+
+    async fn set_group_limit(&self, group_id: i32, limit: Option<i32>) -> Result<(), anyhow::Error> {
+        let mut con = self.redis.get_multiplexed_async_connection().await?;
+        match limit {
+            Some(limit) => { let _: () = con.hset("scheduler:group_limit", group_id, limit).await?; }
+            None => { let _: () = con.hdel("scheduler:group_limit", group_id).await?; }
+        }
+        Ok(())
+    }
+}
+
+
+/// 只依赖Postgres的队列，scheduler_queue表模拟Redis的两个zset，
+/// 多个worker通过 FOR UPDATE SKIP LOCKED 抢任务，只在取有并发上限的group的任务时按group加锁
+#[derive(Debug,Clone)]
+pub struct PgJobQueue {
+    pool: PgPool,
+}
+impl PgJobQueue {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+impl JobQueue for PgJobQueue {
+    async fn get_job(&self) -> Result<Option<i32>, anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        let mut tx = self.pool.begin().await?;
+        // 优先级和group上限直接读cronjobs、groups表，不需要同步
+        let next = sqlx::query!(
+            r#"
+            SELECT q.job_id, g.group_id AS "group_id?", g.max_concurrency
+            FROM scheduler_queue q
+            LEFT JOIN cronjobs c ON c.id = q.job_id
+            LEFT JOIN groups g ON g.group_id = c.group_id AND g.max_concurrency IS NOT NULL
+            WHERE q.state = 'pending' AND q.score <= $1
+            AND (g.max_concurrency IS NULL OR g.max_concurrency > (
+                SELECT count(*) FROM scheduler_queue p
+                JOIN cronjobs pc ON pc.id = p.job_id
+                WHERE p.state = 'processing' AND p.score > $1 AND pc.group_id = g.group_id
+            ))
+            ORDER BY coalesce(c.priority, 0) DESC, q.score
+            LIMIT 1
+            FOR UPDATE OF q SKIP LOCKED
+            "#,
+            current_ts
+        ).fetch_optional(&mut *tx).await?;
+        let Some(next) = next else {
+            return Ok(None);
+        };
+        // 有上限的group：统计并发数和取走任务之间不能插入同一group的其他worker，按group加锁后重新统计
+        // 两个int的key和单个bigint的key(工作流的锁)不会冲突
+        if let (Some(group_id), Some(limit)) = (next.group_id, next.max_concurrency) {
+            sqlx::query("SELECT pg_advisory_xact_lock(hashtext('scheduler_group'), $1)").bind(group_id).execute(&mut *tx).await?;
+            let running = sqlx::query_scalar!(
+                r#"
+                SELECT count(*) AS "count!" FROM scheduler_queue p
+                JOIN cronjobs pc ON pc.id = p.job_id
+                WHERE p.state = 'processing' AND p.score > $1 AND pc.group_id = $2
+                "#,
+                current_ts,
+                group_id
+            ).fetch_one(&mut *tx).await?;
+            if running >= limit as i64 {
+                // group刚被其他worker占满，任务留在pending
+                return Ok(None);
+            }
+        }
+        let row = sqlx::query!(
+            r#"
+            WITH next AS (
+                DELETE FROM scheduler_queue WHERE state = 'pending' AND job_id = $1
+                RETURNING job_id
+            )
+            INSERT INTO scheduler_queue (job_id, state, score)
+            SELECT job_id, 'processing', $2::bigint FROM next
+            ON CONFLICT (job_id, state) DO UPDATE SET score = EXCLUDED.score
+            RETURNING job_id
+            "#,
+            next.job_id,
+            current_ts + PROCESSING_TIMEOUT_MS
+        ).fetch_optional(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(row.map(|row| row.job_id))
+    }
+
+    async fn add_job(&self, job_id: i32, execute_at: i64) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO scheduler_queue (job_id, state, score) VALUES ($1, 'pending', $2)
+            ON CONFLICT (job_id, state) DO UPDATE SET score = EXCLUDED.score
+            "#,
+            job_id,
+            execute_at
+        ).execute(&self.pool).await?;
+        info!("job {} added to queue", job_id);
+        Ok(())
+    }
+
+    async fn add_job_nx(&self, job_id: i32, execute_at: i64) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO scheduler_queue (job_id, state, score) VALUES ($1, 'pending', $2)
+            ON CONFLICT (job_id, state) DO NOTHING
+            "#,
+            job_id,
+            execute_at
+        ).execute(&self.pool).await?;
+        if result.rows_affected() > 0 {
+            info!("job {} added to queue", job_id);
+        }
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn del_job_pending(&self, job_id: i32) -> Result<(), anyhow::Error> {
+        sqlx::query!("DELETE FROM scheduler_queue WHERE job_id = $1 AND state = 'pending'", job_id)
+            .execute(&self.pool).await?;
+        info!("job {} deleted from queue", job_id);
+        Ok(())
+    }
+
+    async fn del_job(&self, job_id: i32) -> Result<(), anyhow::Error> {
+        sqlx::query!("DELETE FROM scheduler_queue WHERE job_id = $1 AND state = 'processing'", job_id)
+            .execute(&self.pool).await?;
+        info!("job {} complete del from processing", job_id);
+        Ok(())
+    }
+
+    async fn clear_all_jobs(&self) -> Result<(usize, usize), anyhow::Error> {
+        let pending_count = sqlx::query!("DELETE FROM scheduler_queue WHERE state = 'pending'")
+            .execute(&self.pool).await?.rows_affected() as usize;
+        let processing_count = sqlx::query!("DELETE FROM scheduler_queue WHERE state = 'processing'")
+            .execute(&self.pool).await?.rows_affected() as usize;
+        info!(
+            "Cleared {} pending jobs and {} processing jobs",
+            pending_count,
+            processing_count
+        );
+        Ok((pending_count, processing_count))
+    }
+
+    async fn retry_job(&self, job_id: i32, retry_after: i64) -> Result<(), anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("DELETE FROM scheduler_queue WHERE job_id = $1 AND state = 'processing'", job_id)
+            .execute(&mut *tx).await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO scheduler_queue (job_id, state, score) VALUES ($1, 'pending', $2)
+            ON CONFLICT (job_id, state) DO UPDATE SET score = EXCLUDED.score
+            "#,
+            job_id,
+            retry_after
+        ).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn del_timeout_jobs(&self) -> Result<Vec<i32>, anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        let rows = sqlx::query!(
+            r#"
+            WITH expired AS (
+                DELETE FROM scheduler_queue
+                WHERE state = 'processing' AND score <= $1
+                RETURNING job_id
+            )
+            INSERT INTO scheduler_queue (job_id, state, score)
+            SELECT job_id, 'pending', $2::bigint FROM expired
+            ON CONFLICT (job_id, state) DO UPDATE SET score = EXCLUDED.score
+            RETURNING job_id
+            "#,
+            current_ts,
+            current_ts + TIMEOUT_RETRY_DELAY_MS
+        ).fetch_all(&self.pool).await?;
+        Ok(rows.into_iter().map(|row| row.job_id).collect())
+    }
+
+    async fn renew_job(&self, job_id: i32) -> Result<bool, anyhow::Error> {
+        let result = sqlx::query!(
+            "UPDATE scheduler_queue SET score = $1 WHERE job_id = $2 AND state = 'processing'",
+            Utc::now().timestamp_millis() + PROCESSING_TIMEOUT_MS,
+            job_id
+        ).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn acquire_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<bool, anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        // 锁不存在或已过期才能拿到
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO scheduler_locks (job_id, token, expires_at) VALUES ($1, $2, $3)
+            ON CONFLICT (job_id) DO UPDATE SET token = EXCLUDED.token, expires_at = EXCLUDED.expires_at
+            WHERE scheduler_locks.expires_at <= $4
+            RETURNING job_id
+            "#,
+            job_id,
+            token,
+            current_ts + ttl_ms,
+            current_ts
+        ).fetch_optional(&self.pool).await?;
+        Ok(row.is_some())
+    }
+
+    async fn force_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<(), anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        sqlx::query!(
+            r#"
+            INSERT INTO scheduler_locks (job_id, token, expires_at) VALUES ($1, $2, $3)
+            ON CONFLICT (job_id) DO UPDATE SET token = EXCLUDED.token, expires_at = EXCLUDED.expires_at
+            "#,
+            job_id,
+            token,
+            current_ts + ttl_ms
+        ).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn renew_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<bool, anyhow::Error> {
+        let current_ts = Utc::now().timestamp_millis();
+        let result = sqlx::query!(
+            "UPDATE scheduler_locks SET expires_at = $1 WHERE job_id = $2 AND token = $3",
+            current_ts + ttl_ms,
+            job_id,
+            token
+        ).execute(&self.pool).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn release_lock(&self, job_id: i32, token: &str) -> Result<(), anyhow::Error> {
+        sqlx::query!("DELETE FROM scheduler_locks WHERE job_id = $1 AND token = $2", job_id, token)
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+    async fn list_jobs(&self) -> Result<QueueSnapshot, anyhow::Error> {
+        let rows = sqlx::query!("SELECT job_id, state, score FROM scheduler_queue ORDER BY score")
+            .fetch_all(&self.pool).await?;
+        let (pending, processing): (Vec<_>, Vec<_>) = rows.into_iter().partition(|row| row.state == "pending");
+        Ok(QueueSnapshot::new(
+            pending.into_iter().map(|row| QueueEntry::new(row.job_id, row.score)).collect(),
+            processing.into_iter().map(|row| QueueEntry::new(row.job_id, row.score)).collect(),
+        ))
+    }
+
+    // get_job直接读cronjobs和groups表
+    async fn set_job_meta(&self, _job_id: i32, _meta: JobMeta) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    async fn set_group_limit(&self, _group_id: i32, _limit: Option<i32>) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
+
+
+/// API进程使用的队列，和worker共用同一个后端，用于查看和管理队列
+/// memory后端只存在于worker进程内，API无法访问
+#[derive(Debug,Clone)]
+pub enum QueueBackend {
+    Redis(JobScheduler),
+    Postgres(PgJobQueue),
+}
+impl QueueBackend {
+    /// 按SCHEDULER_BACKEND选择后端，memory返回None
+    pub async fn from_env(pool: &PgPool) -> Result<Option<Self>, anyhow::Error> {
+        let backend = env::var("SCHEDULER_BACKEND").unwrap_or("redis".to_string());
+        match backend.as_str() {
+            "redis" => Ok(Some(QueueBackend::Redis(JobScheduler::new().await?))),
+            "postgres" => Ok(Some(QueueBackend::Postgres(PgJobQueue::new(pool.clone())))),
+            "memory" => Ok(None),
+            other => Err(anyhow!("Unknown SCHEDULER_BACKEND: {}", other)),
+        }
+    }
+}
+impl JobQueue for QueueBackend {
+    async fn get_job(&self) -> Result<Option<i32>, anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.get_job().await,
+            QueueBackend::Postgres(queue) => queue.get_job().await,
+        }
+    }
+
+    async fn add_job(&self, job_id: i32, execute_at: i64) -> Result<(), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.add_job(job_id, execute_at).await,
+            QueueBackend::Postgres(queue) => queue.add_job(job_id, execute_at).await,
+        }
+    }
+
+    async fn add_job_nx(&self, job_id: i32, execute_at: i64) -> Result<bool, anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.add_job_nx(job_id, execute_at).await,
+            QueueBackend::Postgres(queue) => queue.add_job_nx(job_id, execute_at).await,
+        }
+    }
+
+    async fn del_job_pending(&self, job_id: i32) -> Result<(), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.del_job_pending(job_id).await,
+            QueueBackend::Postgres(queue) => queue.del_job_pending(job_id).await,
+        }
+    }
+
+    async fn del_job(&self, job_id: i32) -> Result<(), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.del_job(job_id).await,
+            QueueBackend::Postgres(queue) => queue.del_job(job_id).await,
+        }
+    }
+
+    async fn clear_all_jobs(&self) -> Result<(usize, usize), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.clear_all_jobs().await,
+            QueueBackend::Postgres(queue) => queue.clear_all_jobs().await,
+        }
+    }
+
+    async fn retry_job(&self, job_id: i32, retry_after: i64) -> Result<(), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.retry_job(job_id, retry_after).await,
+            QueueBackend::Postgres(queue) => queue.retry_job(job_id, retry_after).await,
+        }
+    }
+
+    async fn del_timeout_jobs(&self) -> Result<Vec<i32>, anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.del_timeout_jobs().await,
+            QueueBackend::Postgres(queue) => queue.del_timeout_jobs().await,
+        }
+    }
+
+    async fn renew_job(&self, job_id: i32) -> Result<bool, anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.renew_job(job_id).await,
+            QueueBackend::Postgres(queue) => queue.renew_job(job_id).await,
+        }
+    }
+
+    async fn acquire_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<bool, anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.acquire_lock(job_id, token, ttl_ms).await,
+            QueueBackend::Postgres(queue) => queue.acquire_lock(job_id, token, ttl_ms).await,
+        }
+    }
+
+    async fn force_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<(), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.force_lock(job_id, token, ttl_ms).await,
+            QueueBackend::Postgres(queue) => queue.force_lock(job_id, token, ttl_ms).await,
+        }
+    }
+
+    async fn renew_lock(&self, job_id: i32, token: &str, ttl_ms: i64) -> Result<bool, anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.renew_lock(job_id, token, ttl_ms).await,
+            QueueBackend::Postgres(queue) => queue.renew_lock(job_id, token, ttl_ms).await,
+        }
+    }
+
+    async fn release_lock(&self, job_id: i32, token: &str) -> Result<(), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.release_lock(job_id, token).await,
+            QueueBackend::Postgres(queue) => queue.release_lock(job_id, token).await,
+        }
+    }
+
+    async fn list_jobs(&self) -> Result<QueueSnapshot, anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.list_jobs().await,
+            QueueBackend::Postgres(queue) => queue.list_jobs().await,
+        }
+    }
+
+    async fn set_job_meta(&self, job_id: i32, meta: JobMeta) -> Result<(), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.set_job_meta(job_id, meta).await,
+            QueueBackend::Postgres(queue) => queue.set_job_meta(job_id, meta).await,
+        }
+    }
+
+    async fn set_group_limit(&self, group_id: i32, limit: Option<i32>) -> Result<(), anyhow::Error> {
+        match self {
+            QueueBackend::Redis(queue) => queue.set_group_limit(group_id, limit).await,
+            QueueBackend::Postgres(queue) => queue.set_group_limit(group_id, limit).await,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_memory_queue_pops_earliest_due_job() {
+        let queue = Scheduler::new();
+        let now = Utc::now();
+        queue.add_job(1, (now - Duration::seconds(1)).timestamp_millis()).await.unwrap();
+        queue.add_job(2, (now - Duration::seconds(5)).timestamp_millis()).await.unwrap();
+        queue.add_job(3, (now + Duration::hours(1)).timestamp_millis()).await.unwrap();
+
+        assert_eq!(queue.get_job().await.unwrap(), Some(2));
+        assert_eq!(queue.get_job().await.unwrap(), Some(1));
+        // 未到期的任务不会被取出
+        assert_eq!(queue.get_job().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_readd_and_delete() {
+        let queue = Scheduler::new();
+        let now = Utc::now();
+        queue.add_job(1, (now + Duration::hours(1)).timestamp_millis()).await.unwrap();
+        // 重复添加以最新的执行时间为准，和ZADD一致
+        queue.add_job(1, (now - Duration::seconds(1)).timestamp_millis()).await.unwrap();
+        queue.add_job(2, (now - Duration::seconds(1)).timestamp_millis()).await.unwrap();
+        queue.del_job_pending(2).await.unwrap();
+
+        assert_eq!(queue.get_job().await.unwrap(), Some(1));
+        assert_eq!(queue.get_job().await.unwrap(), None);
+        assert_eq!(queue.clear_all_jobs().await.unwrap(), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_retry_job() {
+        let queue = Scheduler::new();
+        let now = Utc::now();
+        queue.add_job(1, (now - Duration::seconds(1)).timestamp_millis()).await.unwrap();
+        assert_eq!(queue.get_job().await.unwrap(), Some(1));
+
+        queue.retry_job(1, (now - Duration::seconds(1)).timestamp_millis()).await.unwrap();
+        assert_eq!(queue.get_job().await.unwrap(), Some(1));
+        queue.del_job(1).await.unwrap();
+        assert_eq!(queue.clear_all_jobs().await.unwrap(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_job_lock() {
+        let queue = Scheduler::new();
+        assert!(queue.acquire_lock(1, "a", 30_000).await.unwrap());
+        assert!(!queue.acquire_lock(1, "b", 30_000).await.unwrap());
+        // replace 抢占后原持有者续期失败
+        queue.force_lock(1, "b", 30_000).await.unwrap();
+        assert!(!queue.renew_lock(1, "a", 30_000).await.unwrap());
+        queue.release_lock(1, "a").await.unwrap();
+        assert!(queue.renew_lock(1, "b", 30_000).await.unwrap());
+        queue.release_lock(1, "b").await.unwrap();
+        assert!(queue.acquire_lock(1, "c", 30_000).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_priority() {
+        let queue = Scheduler::new();
+        let now = Utc::now();
+        queue.set_job_meta(1, JobMeta { priority: 0, group_id: None }).await.unwrap();
+        queue.set_job_meta(2, JobMeta { priority: 10, group_id: None }).await.unwrap();
+        queue.add_job(1, (now - Duration::seconds(5)).timestamp_millis()).await.unwrap();
+        queue.add_job(2, (now - Duration::seconds(1)).timestamp_millis()).await.unwrap();
+        queue.add_job(3, (now - Duration::seconds(3)).timestamp_millis()).await.unwrap();
+        queue.set_job_meta(4, JobMeta { priority: 100, group_id: None }).await.unwrap();
+        queue.add_job(4, (now + Duration::hours(1)).timestamp_millis()).await.unwrap();
+
+        // 优先级高的先取，同优先级按到期时间，未到期的优先级再高也不取
+        assert_eq!(queue.get_job().await.unwrap(), Some(2));
+        assert_eq!(queue.get_job().await.unwrap(), Some(1));
+        assert_eq!(queue.get_job().await.unwrap(), Some(3));
+        assert_eq!(queue.get_job().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_group_limit() {
+        let queue = Scheduler::new();
+        let due = (Utc::now() - Duration::seconds(1)).timestamp_millis();
+        queue.set_group_limit(7, Some(1)).await.unwrap();
+        for job_id in 1..=3 {
+            let group_id = if job_id == 3 { None } else { Some(7) };
+            queue.set_job_meta(job_id, JobMeta { priority: 0, group_id }).await.unwrap();
+            queue.add_job(job_id, due).await.unwrap();
+        }
+
+        assert_eq!(queue.get_job().await.unwrap(), Some(1));
+        // group 7 已满，跳过2
+        assert_eq!(queue.get_job().await.unwrap(), Some(3));
+        assert_eq!(queue.get_job().await.unwrap(), None);
+        queue.del_job(1).await.unwrap();
+        assert_eq!(queue.get_job().await.unwrap(), Some(2));
+        queue.del_job(2).await.unwrap();
+        queue.set_group_limit(7, None).await.unwrap();
+        queue.add_job(1, due).await.unwrap();
+        queue.add_job(2, due).await.unwrap();
+        assert_eq!(queue.get_job().await.unwrap(), Some(1));
+        assert_eq!(queue.get_job().await.unwrap(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_expired_processing() {
+        let queue = Scheduler::new();
+        let due = (Utc::now() - Duration::seconds(1)).timestamp_millis();
+        queue.set_group_limit(7, Some(1)).await.unwrap();
+        for job_id in 1..=2 {
+            queue.set_job_meta(job_id, JobMeta { priority: 0, group_id: Some(7) }).await.unwrap();
+            queue.add_job(job_id, due).await.unwrap();
+        }
+        assert_eq!(queue.get_job().await.unwrap(), Some(1));
+        assert!(queue.renew_job(1).await.unwrap());
+        assert!(!queue.renew_job(2).await.unwrap());
+        assert_eq!(queue.get_job().await.unwrap(), None);
+        // 1的worker退出后死线过期，不再占用group的名额，reload时放回pending
+        queue.inner.lock().unwrap().processing.insert(1, due);
+        assert_eq!(queue.get_job().await.unwrap(), Some(2));
+        assert_eq!(queue.del_timeout_jobs().await.unwrap(), vec![1]);
+        assert!(!queue.renew_job(1).await.unwrap());
+    }
+}
@@ -0,0 +1,186 @@
+use std::str::FromStr;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::command_template::shell_quote;
+
+// 临时文件的前缀，执行结束后删除
+const TEMP_FILE_TEMPLATE: &str = "/tmp/cnok-script.XXXXXX";
+
+// 保存在服务端的脚本，执行时通过stdin或临时文件传到server上，不再塞进command字符串
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Script {
+    pub id: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,
+    pub interpreter: String,
+    pub args: Vec<String>,  // 默认参数，执行时可以覆盖
+    pub delivery: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateScript {
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,
+    pub interpreter: Option<Interpreter>,   // 默认bash
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub delivery: Option<ScriptDelivery>,   // 默认stdin
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateScript {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub body: Option<String>,
+    pub interpreter: Option<Interpreter>,
+    pub args: Option<Vec<String>>,
+    pub delivery: Option<ScriptDelivery>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpreter {
+    #[default]
+    Bash,
+    Sh,
+    Python3,
+}
+
+impl Interpreter {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interpreter::Bash => "bash",
+            Interpreter::Sh => "sh",
+            Interpreter::Python3 => "python3",
+        }
+    }
+
+    // 从stdin读取脚本的写法
+    fn stdin_command(&self) -> &'static str {
+        match self {
+            Interpreter::Bash => "bash -s --",
+            Interpreter::Sh => "sh -s --",
+            Interpreter::Python3 => "python3 -",
+        }
+    }
+}
+
+impl FromStr for Interpreter {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Interpreter::Bash),
+            "sh" => Ok(Interpreter::Sh),
+            "python3" => Ok(Interpreter::Python3),
+            other => Err(anyhow!("unknown interpreter: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptDelivery {
+    #[default]
+    Stdin,     // 通过stdin传给解释器，不落盘，但脚本自己读不到stdin
+    TempFile,  // 写入临时文件再执行，退出时删除
+}
+
+impl ScriptDelivery {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScriptDelivery::Stdin => "stdin",
+            ScriptDelivery::TempFile => "temp_file",
+        }
+    }
+}
+
+impl FromStr for ScriptDelivery {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdin" => Ok(ScriptDelivery::Stdin),
+            "temp_file" => Ok(ScriptDelivery::TempFile),
+            other => Err(anyhow!("unknown script delivery: {}", other)),
+        }
+    }
+}
+
+impl Script {
+    // 在server上执行的命令，脚本内容作为这条命令的stdin发送；args为None时使用默认参数
+    pub fn invocation(&self, args: Option<&[String]>) -> Result<String, anyhow::Error> {
+        let interpreter: Interpreter = self.interpreter.parse()?;
+        let args = args.unwrap_or(&self.args).iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>();
+        let command = match self.delivery.parse()? {
+            ScriptDelivery::Stdin => format!("{} {}", interpreter.stdin_command(), args.join(" ")),
+            ScriptDelivery::TempFile => {
+                // trap在退出时删除临时文件，退出码保持为脚本的退出码
+                let inner = format!(
+                    r#"f=$(mktemp {}) || exit 1; trap 'rm -f "$f"' EXIT; cat > "$f" && chmod 700 "$f" && {} "$f" {}"#,
+                    TEMP_FILE_TEMPLATE, interpreter.as_str(), args.join(" ")
+                );
+                format!("sh -c {}", shell_quote(inner.trim_end()))
+            }
+        };
+        Ok(command.trim_end().to_string())
+    }
+}
+
+pub fn validate_script_body(body: &str) -> Result<(), anyhow::Error> {
+    if body.trim().is_empty() {
+        return Err(anyhow!("script body must not be empty"));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(interpreter: Interpreter, delivery: ScriptDelivery, args: &[&str]) -> Script {
+        Script {
+            id: 1,
+            name: "cleanup".to_string(),
+            description: None,
+            body: "echo \"$1\"\n".to_string(),
+            interpreter: interpreter.as_str().to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            delivery: delivery.as_str().to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_stdin_invocation() {
+        let bash = script(Interpreter::Bash, ScriptDelivery::Stdin, &["/var/log", "it's"]);
+        assert_eq!(bash.invocation(None).unwrap(), r#"bash -s -- /var/log 'it'\''s'"#);
+        let override_args = vec!["7".to_string()];
+        assert_eq!(bash.invocation(Some(&override_args)).unwrap(), "bash -s -- 7");
+        assert_eq!(script(Interpreter::Python3, ScriptDelivery::Stdin, &[]).invocation(None).unwrap(), "python3 -");
+        assert_eq!(script(Interpreter::Sh, ScriptDelivery::Stdin, &["a"]).invocation(None).unwrap(), "sh -s -- a");
+    }
+
+    #[test]
+    fn test_temp_file_invocation() {
+        let python = script(Interpreter::Python3, ScriptDelivery::TempFile, &["x y"]);
+        let command = python.invocation(None).unwrap();
+        assert!(command.starts_with("sh -c '"));
+        assert!(command.contains("mktemp /tmp/cnok-script.XXXXXX"));
+        assert!(command.contains(r#"trap '\''rm -f "$f"'\'' EXIT"#));
+        assert!(command.ends_with(r#"python3 "$f" '\''x y'\'''"#));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("python3".parse::<Interpreter>().unwrap(), Interpreter::Python3);
+        assert!("perl".parse::<Interpreter>().is_err());
+        assert_eq!("temp_file".parse::<ScriptDelivery>().unwrap(), ScriptDelivery::TempFile);
+        assert!(validate_script_body(" \n").is_err());
+    }
+}
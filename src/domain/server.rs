@@ -1,5 +1,8 @@
+use std::collections::HashMap;
 use actix_web::web;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use crate::domain::privilege::BecomeMethod;
 
 #[derive(Deserialize, Debug, Clone,Serialize)]
 pub struct ServiceTerminal {
@@ -10,6 +13,12 @@ pub struct ServiceTerminal {
     pub ip: String,
     pub port: i32,
     pub password: String,
+    pub labels: serde_json::Value,          // 主机标签，命令模板的参数来源之一
+    pub archived_at: Option<DateTime<Utc>>, // 删除后归档的时间
+    pub become_method: Option<String>,      // 提权方式，sudo或su
+    pub become_user: Option<String>,
+    #[serde(skip_serializing)]
+    pub become_password: Option<String>,    // 加密后的提权密码，不返回给客户端
 }
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
@@ -30,6 +39,20 @@ pub struct CreateGroupServiceTerminal { // name为前缀，group必填，ip列
     pub port: Option<i32>,
     pub password: String,
 }
+// 整体替换server的标签
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct ServerLabels {
+    pub labels: HashMap<String, String>,
+}
+
+// 设置server的提权方式，method为空时取消提权；password不给时使用登录密码
+#[derive(Deserialize, Debug, Clone)]
+pub struct ServerBecome {
+    pub method: Option<BecomeMethod>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct UpdateServiceTerminal {
     pub name: Option<String>,
@@ -51,6 +74,11 @@ impl TryFrom<web::Json<ServiceTerminal>> for ServiceTerminal {
             ip: data.ip.clone(),
             port: data.port,
             password: data.password.clone(),
+            labels: data.labels.clone(),
+            archived_at: data.archived_at,
+            become_method: data.become_method.clone(),
+            become_user: data.become_user.clone(),
+            become_password: data.become_password.clone(),
         })
     }
 }
@@ -96,4 +124,11 @@ impl TryFrom<web::Json<UpdateServiceTerminal>> for UpdateServiceTerminal {
             password: data.password.clone(),
         })
     }
-}
\ No newline at end of file
+}
+// 任务解析出的目标server，一台都没有时这次运行失败
+pub fn require_servers(job_id: i32, servers: Vec<ServiceTerminal>) -> Result<Vec<ServiceTerminal>, anyhow::Error> {
+    if servers.is_empty() {
+        return Err(anyhow::anyhow!("job {} has no server to execute", job_id));
+    }
+    Ok(servers)
+}
@@ -1,21 +1,29 @@
 use actix_web::web;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 #[derive(Deserialize, Debug, Clone,Serialize,sqlx::FromRow)]
 pub struct Group{
     pub group_id: i32,
     pub name: String,
     pub description: Option<String>,
+    pub calendar_id: Option<i32>, // 组的维护窗口日历，组内的定时任务和批量执行都受限制
+    pub max_concurrency: Option<i32>, // 组内同时执行的定时任务数上限，为空不限制
+    pub archived_at: Option<DateTime<Utc>>, // 删除后归档的时间
 }
 #[derive(Debug, Clone, Deserialize,Serialize)]
 pub struct CreateGroup {
     pub name: String,
     pub description: Option<String>,
+    pub calendar_id: Option<i32>,
+    pub max_concurrency: Option<i32>,
 }
 
 #[derive(Deserialize, Debug, Clone,Serialize)]
 pub struct UpdateGroup{
     pub name: Option<String>,
     pub description: Option<String>,
+    pub calendar_id: Option<i32>,
+    pub max_concurrency: Option<i32>, // 0表示取消上限
 }
 
 impl TryFrom<web::Json<Group>> for Group {
@@ -26,6 +34,9 @@ impl TryFrom<web::Json<Group>> for Group {
                 group_id: data.group_id,
                 name: data.name.clone(),
                 description: data.description.clone(),
+                calendar_id: data.calendar_id,
+                max_concurrency: data.max_concurrency,
+                archived_at: data.archived_at,
             })
     }
 }
@@ -35,7 +46,9 @@ impl TryFrom<web::Json<CreateGroup>> for CreateGroup {
         Ok(
             CreateGroup {
                 name: data.name.clone(),
-                description: data.description.clone()
+                description: data.description.clone(),
+                calendar_id: data.calendar_id,
+                max_concurrency: data.max_concurrency,
             })
     }
 }
@@ -45,7 +58,9 @@ impl TryFrom<web::Json<UpdateGroup>> for UpdateGroup {
         Ok(
             UpdateGroup {
                 name: data.name.clone(),
-                description: data.description.clone()
+                description: data.description.clone(),
+                calendar_id: data.calendar_id,
+                max_concurrency: data.max_concurrency,
             })
     }
 }
\ No newline at end of file
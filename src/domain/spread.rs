@@ -0,0 +1,99 @@
+use std::time::Duration;
+use anyhow::anyhow;
+use crate::domain::cron_job::CronJob;
+
+// jitter和spread的上限，超过一天的错开没有意义，也会让任务锁一直被占用
+pub const MAX_SPREAD_SECS: i32 = 86_400;
+
+// 定时运行时各server的启动延迟，同一任务同一server每次得到相同的延迟
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostSpread {
+    pub jitter_secs: u64, // 每台server额外的随机延迟上限
+    pub spread_secs: u64, // 各server按任务id和ip散列到这个窗口内
+}
+
+impl HostSpread {
+    pub fn from_job(job: &CronJob) -> Self {
+        Self {
+            jitter_secs: job.jitter_secs.max(0) as u64,
+            spread_secs: job.spread_secs.max(0) as u64,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.jitter_secs == 0 && self.spread_secs == 0
+    }
+
+    // 窗口内的位置和jitter都只由任务id和ip决定，group增减server时其他server的延迟不变
+    // 返回顺序与ips一致
+    pub fn delays(&self, job_id: i32, ips: &[&str]) -> Vec<Duration> {
+        if self.is_zero() {
+            return vec![Duration::ZERO; ips.len()];
+        }
+        let spread_ms = self.spread_secs * 1000;
+        let jitter_ms = self.jitter_secs * 1000;
+        ips.iter().map(|ip| {
+            let hash = host_hash(job_id, ip);
+            let offset = if spread_ms == 0 { 0 } else { hash % spread_ms };
+            // jitter取hash的另一半，不和offset同涨同落
+            let jitter = if jitter_ms == 0 { 0 } else { hash.rotate_left(32) % (jitter_ms + 1) };
+            Duration::from_millis(offset + jitter)
+        }).collect()
+    }
+}
+
+pub fn validate_spread(jitter_secs: Option<i32>, spread_secs: Option<i32>) -> Result<(), anyhow::Error> {
+    for (name, value) in [("jitter_secs", jitter_secs), ("spread_secs", spread_secs)] {
+        if value.is_some_and(|secs| !(0..=MAX_SPREAD_SECS).contains(&secs)) {
+            return Err(anyhow!("{} must be between 0 and {}", name, MAX_SPREAD_SECS));
+        }
+    }
+    Ok(())
+}
+
+// FNV-1a，标准库的DefaultHasher不保证跨版本稳定，升级后同一server的延迟会变
+fn host_hash(job_id: i32, ip: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in job_id.to_le_bytes().iter().chain(ip.as_bytes()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spread_delays() {
+        let ips = ["10.0.0.3", "10.0.0.1", "10.0.0.4", "10.0.0.2"];
+        let spread = HostSpread { jitter_secs: 0, spread_secs: 60 };
+        let delays = spread.delays(1, &ips);
+        assert!(delays.iter().all(|d| *d < Duration::from_secs(60)));
+        assert_eq!(delays[0], Duration::from_millis(host_hash(1, "10.0.0.3") % 60_000));
+        // 加入新server不影响已有server的延迟
+        assert_eq!(spread.delays(1, &["10.0.0.3", "10.0.0.9"])[0], delays[0]);
+        assert_eq!(HostSpread::default().delays(1, &ips), vec![Duration::ZERO; 4]);
+    }
+
+    #[test]
+    fn test_jitter_is_deterministic() {
+        let ips = ["10.0.0.1", "10.0.0.2", "10.0.0.3"];
+        let spread = HostSpread { jitter_secs: 30, spread_secs: 0 };
+        let delays = spread.delays(7, &ips);
+        assert_eq!(delays, spread.delays(7, &ips));
+        assert!(delays.iter().all(|d| *d <= Duration::from_secs(30)));
+        // 同一server单独执行时延迟不变
+        assert_eq!(spread.delays(7, &ips[1..2])[0], delays[1]);
+        assert_ne!(spread.delays(8, &ips), delays);
+    }
+
+    #[test]
+    fn test_validate_spread() {
+        assert!(validate_spread(Some(0), Some(MAX_SPREAD_SECS)).is_ok());
+        assert!(validate_spread(Some(-1), None).is_err());
+        assert!(validate_spread(None, Some(MAX_SPREAD_SECS + 1)).is_err());
+    }
+}
@@ -2,9 +2,11 @@ use russh::client::Handler;
 use std::sync::Arc;
 use russh::{client, ChannelMsg, Disconnect};
 use russh::keys::*;
-use tokio::io::AsyncWriteExt;
 use anyhow::Result;
 use russh::client::Config;
+use crate::domain::ssh_session::CommandOutput;
+use crate::domain::environment::Environment;
+use crate::domain::privilege::{Become, BecomeEvent};
 
 
 pub struct Message{
@@ -49,24 +51,94 @@ pub struct Session {
 }
 impl Session {
     pub async fn call(&mut self, command: &str) -> anyhow::Result<(u32,String)> {
+        let output = self.exec(command).await?;
+        Ok((output.exit_code, output.stdout))
+    }
+
+    // 与call相同，但stdout和stderr分开返回，任务按输出判断成败时使用
+    pub async fn exec(&mut self, command: &str) -> anyhow::Result<CommandOutput> {
+        self.exec_with_input(command, None).await
+    }
+
+    // 在同一个连接上新开一个通道执行，input写入命令的stdin后发送EOF(剧本上传文件、执行脚本时使用)
+    pub async fn exec_with_input(&mut self, command: &str, input: Option<&[u8]>) -> anyhow::Result<CommandOutput> {
+        self.exec_as(command, input, None, None).await
+    }
+
+    // privilege不为空时提权执行：等到提示符再发送密码，提权成功后才发送input，输出里的密码替换掉
+    // env为执行时的环境变量和工作目录，输出里的secret值替换掉
+    pub async fn exec_as(&mut self, command: &str, input: Option<&[u8]>, privilege: Option<&Become>, env: Option<&Environment>) -> anyhow::Result<CommandOutput> {
         let mut channel = self.session.channel_open_session().await?;
-        channel.exec(true, command).await?;
+        let pty = privilege.is_some_and(Become::needs_pty);
+        let mut secret_input = Vec::new();
+        let command = match env {
+            Some(env) if privilege.is_none() => {
+                let rejected = Self::set_env(&mut channel, env).await?;
+                if let Some(name) = rejected.iter().find(|name| env.secrets.contains(**name)) {
+                    anyhow::bail!("server rejected secret environment variable {}, allow it with AcceptEnv in sshd_config", name);
+                }
+                env.apply(command, !rejected.is_empty())
+            }
+            // sudo、su会重置环境，普通变量写进命令里，secret提权成功后从stdin读入
+            Some(env) => {
+                if pty && env.has_secrets() {
+                    anyhow::bail!("su can not be used with secret environment variables, use sudo instead");
+                }
+                secret_input = env.secret_input()?;
+                env.apply_with_stdin(command)
+            }
+            None => command.to_string(),
+        };
+        let command = command.as_str();
+        let mut scanner = privilege.map(Become::scanner);
+        if pty {
+            // pty下没有单独的stdin，写入的内容会被终端处理
+            if input.is_some() {
+                anyhow::bail!("su can not be used with stdin input, use sudo instead");
+            }
+            channel.request_pty(false, "xterm", 200, 24, 0, 0, &[]).await?;
+        }
+        // secret在命令自己的输入之前
+        if let Some(input) = input
+            && !secret_input.is_empty()
+        {
+            secret_input.extend_from_slice(input);
+        }
+        let input = if secret_input.is_empty() { input } else { Some(secret_input.as_slice()) };
+        match privilege {
+            Some(privilege) => channel.exec(true, privilege.wrap(command)).await?,
+            None => {
+                channel.exec(true, command).await?;
+                if let Some(input) = input {
+                    channel.data(input).await?;
+                    channel.eof().await?;
+                }
+            }
+        }
 
         let mut code = None;
-        let mut stdout = tokio::io::stdout();
         let mut output = Vec::new();
+        let mut stderr = Vec::new();
         loop {
             // There's an event available on the session channel
             let Some(msg) = channel.wait().await else {
                 break;
             };
              match msg {
-                // Write data to the terminal
                 ChannelMsg::Data { ref data } => {
                     output.extend_from_slice(data);
-                    stdout.write_all(data).await?;
-                    stdout.flush().await?;
-
+                    if let (Some(scanner), Some(privilege)) = (scanner.as_mut(), privilege) {
+                        let events = scanner.scan(&mut output);
+                        Self::answer(&channel, privilege, events, input, pty).await?;
+                    }
+                }
+                // ext 1 为 stderr
+                ChannelMsg::ExtendedData { ref data, ext: 1 } => {
+                    stderr.extend_from_slice(data);
+                    if let (Some(scanner), Some(privilege)) = (scanner.as_mut(), privilege) {
+                        let events = scanner.scan(&mut stderr);
+                        Self::answer(&channel, privilege, events, input, pty).await?;
+                    }
                 }
                 // The command has returned an exit code
                 ChannelMsg::ExitStatus { exit_status } => {
@@ -77,8 +149,66 @@ impl Session {
             }
         }
         let code = code.expect("program did not exit cleanly");
-        let output_str = String::from_utf8_lossy(&output).to_string();
-        Ok((code,output_str))
+        let mut stdout = String::from_utf8_lossy(&output).to_string();
+        let mut stderr = String::from_utf8_lossy(&stderr).to_string();
+        if let Some(privilege) = privilege {
+            if pty {
+                stdout = stdout.replace("\r\n", "\n");
+            }
+            stdout = privilege.redact(stdout);
+            stderr = privilege.redact(stderr);
+        }
+        if let Some(env) = env {
+            stdout = env.mask(stdout);
+            stderr = env.mask(stderr);
+        }
+        Ok(CommandOutput {
+            exit_code: code,
+            stdout,
+            stderr,
+        })
+    }
+
+    // 通过env请求设置变量，返回被拒绝(sshd的AcceptEnv没有放行)的变量名，server按请求的顺序回复
+    async fn set_env<'a>(channel: &mut russh::Channel<client::Msg>, env: &'a Environment) -> anyhow::Result<Vec<&'a String>> {
+        for (name, value) in &env.vars {
+            channel.set_env(true, name.as_str(), value.as_str()).await?;
+        }
+        let mut names = env.vars.keys();
+        let mut rejected = Vec::new();
+        let mut replies = 0;
+        while replies < env.vars.len() {
+            match channel.wait().await {
+                Some(ChannelMsg::Success) => {
+                    names.next();
+                    replies += 1;
+                }
+                Some(ChannelMsg::Failure) => {
+                    rejected.extend(names.next());
+                    replies += 1;
+                }
+                Some(_) => {}
+                None => anyhow::bail!("channel closed while setting environment"),
+            }
+        }
+        Ok(rejected)
+    }
+
+    async fn answer(channel: &russh::Channel<client::Msg>, privilege: &Become, events: Vec<BecomeEvent>, input: Option<&[u8]>, pty: bool) -> anyhow::Result<()> {
+        for event in events {
+            match event {
+                BecomeEvent::SendPassword => channel.data(format!("{}\n", privilege.password).as_bytes()).await?,
+                BecomeEvent::Abort => channel.eof().await?,
+                BecomeEvent::Ready if !pty => {
+                    if let Some(input) = input {
+                        channel.data(input).await?;
+                    }
+                    channel.eof().await?;
+                }
+                BecomeEvent::Ready => {}
+            }
+        }
+        Ok(())
     }
 
     pub async fn close(&mut self) -> anyhow::Result<()> {
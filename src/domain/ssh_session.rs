@@ -1,14 +1,37 @@
+use std::collections::HashMap;
+use std::sync::Arc;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::domain::environment::Environment;
+use crate::domain::privilege::Become;
 
 #[derive(Debug, Deserialize)]
 pub struct SshRequest {
     pub server_id: i32,
-    pub command: String,           // 要执行的命令
+    #[serde(default)]
+    pub command: String,           // 要执行的命令，引用模板或脚本时可以省略
+    pub template_id: Option<i32>,  // 引用的命令模板，按主机渲染后执行
+    #[serde(default)]
+    pub params: HashMap<String, Value>, // 模板参数
+    pub script_id: Option<i32>,    // 执行保存的脚本，与command、template_id只能给一个
+    pub args: Option<Vec<String>>, // 脚本参数，不给时使用脚本的默认参数
+    #[serde(default)]
+    pub env: HashMap<String, String>, // 环境变量，覆盖模板里的同名变量
+    pub workdir: Option<String>,   // 工作目录，覆盖模板里的
 }
 #[derive(Debug, Deserialize)]
 pub struct BatchSshRequest {
     pub group_id: i32,
-    pub command: String,           // 要执行的命令
+    #[serde(default)]
+    pub command: String,           // 要执行的命令，引用模板或脚本时可以省略
+    pub template_id: Option<i32>,
+    #[serde(default)]
+    pub params: HashMap<String, Value>,
+    pub script_id: Option<i32>,
+    pub args: Option<Vec<String>>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub workdir: Option<String>,
 }
 #[derive(Debug, Serialize)]
 pub struct SshResponse {
@@ -36,4 +59,93 @@ pub struct SshResult {
     pub server: String,
     pub output: String,
     pub exit_code: Option<u32>,
-}
\ No newline at end of file
+}
+
+// ssh执行失败的分类，任务按分类决定是否重试
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    Connect,  // 连接失败、通道异常
+    Auth,     // 认证失败
+    Timeout,  // 连接、认证或命令执行超时
+    NonZero,  // 命令执行完成但退出码不在允许范围内
+    Output,   // stdout/stderr不满足任务的匹配规则
+}
+
+impl FailureClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureClass::Connect => "connect",
+            FailureClass::Auth => "auth",
+            FailureClass::Timeout => "timeout",
+            FailureClass::NonZero => "non_zero",
+            FailureClass::Output => "output",
+        }
+    }
+}
+
+impl std::str::FromStr for FailureClass {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "connect" => Ok(FailureClass::Connect),
+            "auth" => Ok(FailureClass::Auth),
+            "timeout" => Ok(FailureClass::Timeout),
+            "non_zero" => Ok(FailureClass::NonZero),
+            "output" => Ok(FailureClass::Output),
+            _ => Err(anyhow::anyhow!("Invalid failure class: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SshFailure {
+    pub class: FailureClass,
+    pub message: String,
+    pub exit_code: Option<u32>,
+}
+
+impl SshFailure {
+    pub fn new(class: FailureClass, message: String) -> Self {
+        Self { class, message, exit_code: None }
+    }
+
+    pub fn non_zero(exit_code: u32) -> Self {
+        Self { class: FailureClass::NonZero, message: format!("exit code {}", exit_code), exit_code: Some(exit_code) }
+    }
+}
+
+impl std::fmt::Display for SshFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CommandOutput {
+    pub exit_code: u32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+// 在一台server上执行的内容：命令、作为stdin发送的输入(脚本)、提权方式、环境变量和工作目录
+#[derive(Debug, Clone)]
+pub struct RemoteCommand {
+    pub command: String,
+    pub input: Option<Arc<String>>,
+    pub privilege: Option<Become>,
+    pub env: Environment,
+}
+
+impl RemoteCommand {
+    pub fn new(command: String) -> Self {
+        RemoteCommand { command, input: None, privilege: None, env: Environment::default() }
+    }
+}
+
+// 任务执行时单台server的结果，成败由任务的成功规则判断
+#[derive(Debug, Clone)]
+pub struct HostResult {
+    pub server: String,
+    pub result: Result<CommandOutput, SshFailure>,
+}
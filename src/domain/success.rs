@@ -0,0 +1,144 @@
+use anyhow::anyhow;
+use regex::Regex;
+use crate::domain::cron_job::CronJob;
+use crate::domain::ssh_session::{CommandOutput, FailureClass, HostResult, SshFailure};
+
+pub const DEFAULT_SUCCESS_EXIT_CODES: [i32; 1] = [0];
+pub const DEFAULT_MIN_SUCCESS_RATIO: f64 = 1.0;
+
+// 任务的成功规则：单台server按退出码和输出判断，整个任务按成功server的占比判断
+#[derive(Debug, Clone)]
+pub struct SuccessCriteria {
+    pub exit_codes: Vec<i32>,
+    pub stdout_must_match: Option<Regex>,
+    pub stdout_must_not_match: Option<Regex>,
+    pub stderr_must_match: Option<Regex>,
+    pub stderr_must_not_match: Option<Regex>,
+    pub min_success_ratio: f64,
+}
+
+impl Default for SuccessCriteria {
+    fn default() -> Self {
+        Self {
+            exit_codes: DEFAULT_SUCCESS_EXIT_CODES.to_vec(),
+            stdout_must_match: None,
+            stdout_must_not_match: None,
+            stderr_must_match: None,
+            stderr_must_not_match: None,
+            min_success_ratio: DEFAULT_MIN_SUCCESS_RATIO,
+        }
+    }
+}
+
+impl SuccessCriteria {
+    pub fn from_job(job: &CronJob) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            exit_codes: job.success_exit_codes.clone(),
+            stdout_must_match: compile(job.stdout_must_match.as_deref())?,
+            stdout_must_not_match: compile(job.stdout_must_not_match.as_deref())?,
+            stderr_must_match: compile(job.stderr_must_match.as_deref())?,
+            stderr_must_not_match: compile(job.stderr_must_not_match.as_deref())?,
+            min_success_ratio: job.min_success_ratio,
+        })
+    }
+
+    // 单台server的失败原因，None表示成功
+    pub fn check(&self, host: &HostResult) -> Option<SshFailure> {
+        match &host.result {
+            Ok(output) => self.check_output(output),
+            Err(e) => Some(e.clone()),
+        }
+    }
+
+    pub fn check_output(&self, output: &CommandOutput) -> Option<SshFailure> {
+        if !self.exit_codes.contains(&(output.exit_code as i32)) {
+            return Some(SshFailure::non_zero(output.exit_code));
+        }
+        let rules = [
+            ("stdout", &output.stdout, &self.stdout_must_match, true),
+            ("stdout", &output.stdout, &self.stdout_must_not_match, false),
+            ("stderr", &output.stderr, &self.stderr_must_match, true),
+            ("stderr", &output.stderr, &self.stderr_must_not_match, false),
+        ];
+        for (stream, text, regex, must_match) in rules {
+            let Some(regex) = regex else { continue };
+            if regex.is_match(text) != must_match {
+                let verb = if must_match { "does not match" } else { "matches" };
+                let mut failure = SshFailure::new(FailureClass::Output, format!("{} {} /{}/", stream, verb, regex.as_str()));
+                failure.exit_code = Some(output.exit_code);
+                return Some(failure);
+            }
+        }
+        None
+    }
+
+    // 成功的server占比是否达到要求
+    pub fn is_satisfied(&self, succeeded: usize, total: usize) -> bool {
+        if total == 0 {
+            return false;
+        }
+        succeeded as f64 / total as f64 >= self.min_success_ratio
+    }
+}
+
+fn compile(pattern: Option<&str>) -> Result<Option<Regex>, anyhow::Error> {
+    pattern
+        .filter(|p| !p.is_empty())
+        .map(|p| Regex::new(p).map_err(|e| anyhow!("Invalid regex {}: {}", p, e)))
+        .transpose()
+}
+
+// 创建、更新时校验，错误信息直接返回给前端
+pub fn validate_success_rules(patterns: &[Option<&str>], min_success_ratio: Option<f64>) -> Result<(), anyhow::Error> {
+    for pattern in patterns {
+        compile(*pattern)?;
+    }
+    if let Some(ratio) = min_success_ratio
+        && !(0.0..=1.0).contains(&ratio)
+    {
+        return Err(anyhow!("min_success_ratio must be between 0 and 1, got {}", ratio));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(exit_code: u32, stdout: &str, stderr: &str) -> CommandOutput {
+        CommandOutput { exit_code, stdout: stdout.to_string(), stderr: stderr.to_string() }
+    }
+
+    #[test]
+    fn test_check_output() {
+        let criteria = SuccessCriteria {
+            exit_codes: vec![0, 2],
+            stdout_must_match: Some(Regex::new("done").unwrap()),
+            stderr_must_not_match: Some(Regex::new("(?i)error").unwrap()),
+            ..Default::default()
+        };
+        assert!(criteria.check_output(&output(0, "backup done", "")).is_none());
+        assert!(criteria.check_output(&output(2, "done", "warning")).is_none());
+        assert_eq!(criteria.check_output(&output(1, "done", "")).unwrap().class, FailureClass::NonZero);
+        assert_eq!(criteria.check_output(&output(0, "started", "")).unwrap().class, FailureClass::Output);
+        assert_eq!(criteria.check_output(&output(0, "done", "ERROR: disk full")).unwrap().class, FailureClass::Output);
+    }
+
+    #[test]
+    fn test_is_satisfied() {
+        let criteria = SuccessCriteria { min_success_ratio: 0.8, ..Default::default() };
+        assert!(criteria.is_satisfied(8, 10));
+        assert!(!criteria.is_satisfied(7, 10));
+        assert!(!criteria.is_satisfied(0, 0));
+        assert!(SuccessCriteria::default().is_satisfied(3, 3));
+        assert!(!SuccessCriteria::default().is_satisfied(2, 3));
+    }
+
+    #[test]
+    fn test_validate_success_rules() {
+        assert!(validate_success_rules(&[Some("ok$"), None], Some(0.5)).is_ok());
+        assert!(validate_success_rules(&[Some("(unclosed")], None).is_err());
+        assert!(validate_success_rules(&[], Some(1.5)).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::domain::cron_run::RunStatus;
+
+// 任务之间的依赖：job_id 在 upstream_id 的运行结束后，按 trigger_on 决定是否触发
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct CronJobDependency {
+    pub job_id: i32,
+    pub upstream_id: i32,
+    pub trigger_on: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateCronJobDependency {
+    pub upstream_id: i32,
+    pub trigger_on: Option<TriggerOn>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerOn {
+    #[default]
+    Success,  // 上游成功才触发
+    Failure,  // 上游失败才触发
+    Always,   // 上游结束就触发
+}
+
+impl TriggerOn {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TriggerOn::Success => "success",
+            TriggerOn::Failure => "failure",
+            TriggerOn::Always => "always",
+        }
+    }
+
+    // 上游运行的最终状态是否满足触发条件，取消、跳过的运行不触发下游
+    pub fn matches(&self, upstream_status: &str) -> bool {
+        let success = upstream_status == RunStatus::Success.as_str();
+        let failed = upstream_status == RunStatus::Failed.as_str();
+        match self {
+            TriggerOn::Success => success,
+            TriggerOn::Failure => failed,
+            TriggerOn::Always => success || failed,
+        }
+    }
+}
+
+impl FromStr for TriggerOn {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(TriggerOn::Success),
+            "failure" => Ok(TriggerOn::Failure),
+            "always" => Ok(TriggerOn::Always),
+            _ => Err(anyhow!("Invalid trigger condition: {}", s)),
+        }
+    }
+}
+
+// 同一个工作流中，下游任务的所有上游都结束后才做决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownstreamDecision {
+    Wait,  // 还有上游没有结束
+    Skip,  // 有上游的结果不满足触发条件
+    Run,
+}
+
+pub fn is_finished(status: &str) -> bool {
+    status != RunStatus::Queued.as_str() && status != RunStatus::Running.as_str()
+}
+
+// conditions 为下游每条依赖的触发条件，以及上游在本工作流中最近一次运行的状态(没有运行为None)
+pub fn downstream_decision(conditions: &[(TriggerOn, Option<String>)]) -> DownstreamDecision {
+    let mut decision = DownstreamDecision::Run;
+    for (trigger_on, status) in conditions {
+        match status {
+            Some(status) if is_finished(status) => {
+                if !trigger_on.matches(status) {
+                    return DownstreamDecision::Skip;
+                }
+            }
+            _ => decision = DownstreamDecision::Wait,
+        }
+    }
+    decision
+}
+
+// edges 为已有的 (job_id, upstream_id)，判断新增 job_id <- upstream_id 后是否成环
+pub fn would_create_cycle(edges: &[(i32, i32)], job_id: i32, upstream_id: i32) -> bool {
+    if job_id == upstream_id {
+        return true;
+    }
+    let mut upstreams: HashMap<i32, Vec<i32>> = HashMap::new();
+    for (job, upstream) in edges {
+        upstreams.entry(*job).or_default().push(*upstream);
+    }
+    // 从新的上游往上找，能找到job_id说明job_id已经是它的上游
+    let mut stack = vec![upstream_id];
+    let mut visited = HashSet::new();
+    while let Some(current) = stack.pop() {
+        if current == job_id {
+            return true;
+        }
+        if visited.insert(current) {
+            stack.extend(upstreams.get(&current).into_iter().flatten());
+        }
+    }
+    false
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_would_create_cycle() {
+        // 2 依赖 1，3 依赖 2
+        let edges = vec![(2, 1), (3, 2)];
+        assert!(would_create_cycle(&edges, 1, 3));
+        assert!(would_create_cycle(&edges, 1, 2));
+        assert!(would_create_cycle(&edges, 4, 4));
+        assert!(!would_create_cycle(&edges, 3, 1));
+        assert!(!would_create_cycle(&edges, 4, 3));
+    }
+
+    #[test]
+    fn test_downstream_decision() {
+        let success = Some("success".to_string());
+        let failed = Some("failed".to_string());
+        let running = Some("running".to_string());
+        assert_eq!(downstream_decision(&[(TriggerOn::Success, success.clone())]), DownstreamDecision::Run);
+        assert_eq!(downstream_decision(&[(TriggerOn::Success, failed.clone())]), DownstreamDecision::Skip);
+        assert_eq!(downstream_decision(&[(TriggerOn::Failure, failed.clone())]), DownstreamDecision::Run);
+        assert_eq!(downstream_decision(&[(TriggerOn::Always, failed.clone())]), DownstreamDecision::Run);
+        assert_eq!(downstream_decision(&[(TriggerOn::Always, Some("cancelled".to_string()))]), DownstreamDecision::Skip);
+        assert_eq!(downstream_decision(&[(TriggerOn::Success, success.clone()), (TriggerOn::Success, running)]), DownstreamDecision::Wait);
+        assert_eq!(downstream_decision(&[(TriggerOn::Success, None), (TriggerOn::Success, failed)]), DownstreamDecision::Skip);
+    }
+}
@@ -0,0 +1,18 @@
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use log::{error, info};
+use crate::db::pool::AppState;
+use crate::domain::archive::{PurgeQuery, purge_cutoff};
+use crate::repository::archive::purge_archived_db;
+
+
+// ?older_than_days=30 彻底删除归档超过这么多天的任务、server、group，任务的日志和运行记录一起删除
+pub async fn purge_archived(data: web::Data<AppState>,query: web::Query<PurgeQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let before = purge_cutoff(Utc::now(), query.older_than_days)
+        .map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let result = purge_archived_db(&data.db_pool, before).await.map_err(|e| {
+        error!("Failed to purge archived: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to purge archived")})?;
+    info!("Purged archived before {}: {:?}", before, result);
+    Ok(HttpResponse::Ok().json(result))
+}
@@ -0,0 +1,74 @@
+use actix_web::{HttpResponse, web};
+use log::error;
+use crate::db::pool::AppState;
+use crate::domain::calendar::{CalendarDetail, CreateCalendar, CreateCalendarWindow};
+use crate::domain::cron_job::parse_timezone;
+use crate::repository::calendar::*;
+
+
+pub async fn get_all_calendars(data: web::Data<AppState>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_all_calendars_db(&data.db_pool).await.map_err(|e| {
+        error!("Failed to get calendars: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get calendars")})?;
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+
+// 日历和它的所有窗口
+pub async fn get_calendar_by_id(data: web::Data<AppState>,calendar_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let calendar_id = calendar_id.into_inner();
+    let calendar = get_calendar_by_id_db(&data.db_pool, calendar_id).await.map_err(|e| {
+        error!("Failed to get a calendar: {:?}", e);
+        actix_web::error::ErrorNotFound("Calendar not found")})?;
+    let windows = get_calendar_windows_db(&data.db_pool, calendar_id).await.map_err(|e| {
+        error!("Failed to get calendar windows: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get calendar windows")})?;
+    Ok(HttpResponse::Ok().json(CalendarDetail { calendar, windows }))
+}
+
+
+pub async fn create_calendar(data: web::Data<AppState>,body: web::Json<CreateCalendar>) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(timezone) = &body.timezone {
+        parse_timezone(timezone).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    }
+    let row = create_calendar_db(&data.db_pool, body.into_inner()).await.map_err(|e| {
+        error!("Failed to create a calendar: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a calendar")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+pub async fn delete_calendar(data: web::Data<AppState>,calendar_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let deleted = delete_calendar_db(&data.db_pool, calendar_id.into_inner()).await.map_err(|e| {
+        error!("Failed to delete a calendar: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to delete a calendar")})?;
+    if deleted == 0 {
+        return Err(actix_web::error::ErrorNotFound("Calendar not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+
+pub async fn create_calendar_window(data: web::Data<AppState>,calendar_id: web::Path<i32>,body: web::Json<CreateCalendarWindow>) -> Result<HttpResponse, actix_web::Error> {
+    let calendar_id = calendar_id.into_inner();
+    body.validate().map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    get_calendar_by_id_db(&data.db_pool, calendar_id).await.map_err(|e| {
+        error!("Failed to get a calendar: {:?}", e);
+        actix_web::error::ErrorNotFound("Calendar not found")})?;
+    let row = create_calendar_window_db(&data.db_pool, calendar_id, body.into_inner()).await.map_err(|e| {
+        error!("Failed to create a calendar window: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a calendar window")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+pub async fn delete_calendar_window(data: web::Data<AppState>,params: web::Path<(i32, i32)>) -> Result<HttpResponse, actix_web::Error> {
+    let (calendar_id, window_id) = params.into_inner();
+    let deleted = delete_calendar_window_db(&data.db_pool, calendar_id, window_id).await.map_err(|e| {
+        error!("Failed to delete a calendar window: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to delete a calendar window")})?;
+    if deleted == 0 {
+        return Err(actix_web::error::ErrorNotFound("Calendar window not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
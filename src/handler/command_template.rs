@@ -0,0 +1,107 @@
+use actix_web::{HttpResponse, web};
+use log::error;
+use serde_json::{Map, Value};
+use crate::db::pool::AppState;
+use crate::domain::archive::ReferencedBy;
+use crate::domain::command_template::*;
+use crate::repository::command_template::*;
+use crate::repository::server::get_server_by_id_db;
+
+
+pub async fn get_all_command_templates(data: web::Data<AppState>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_all_command_templates_db(&data.db_pool).await.map_err(|e| {
+        error!("Failed to get command templates: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get command templates")})?;
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+
+pub async fn get_command_template_by_id(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let row = find_template(&data, id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+pub async fn create_command_template(data: web::Data<AppState>,body: web::Json<CreateCommandTemplate>) -> Result<HttpResponse, actix_web::Error> {
+    validate_template(&body.template, &body.params).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    validate_template_env(&body.env, body.workdir.as_deref(), &body.params).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let row = create_command_template_db(&data.db_pool, body.into_inner()).await.map_err(|e| {
+        error!("Failed to create a command template: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a command template")})?;
+    Ok(HttpResponse::Created().json(row))
+}
+
+
+pub async fn update_command_template(data: web::Data<AppState>,id: web::Path<i32>,body: web::Json<UpdateCommandTemplate>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    let current = find_template(&data, id).await?;
+    // 模板和参数声明要一起校验，只改其中一个时用另一个的当前值
+    let template = body.template.as_deref().unwrap_or(&current.template);
+    let params = body.params.as_deref().unwrap_or(&current.params);
+    validate_template(template, params).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let env = body.env.as_ref().unwrap_or(&current.env);
+    let workdir = body.workdir.as_deref().or(current.workdir.as_deref());
+    validate_template_env(env, workdir, params).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let row = update_command_template_db(&data.db_pool, id, body.into_inner()).await.map_err(|e| {
+        error!("Failed to update a command template: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update a command template")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+// 仍有任务引用时返回409和这些任务
+pub async fn delete_command_template(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    find_template(&data, id).await?;
+    let cronjob_ids = get_template_cronjob_ids_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get cronjobs of a command template: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjobs of a command template")})?;
+    if !cronjob_ids.is_empty() {
+        return Ok(HttpResponse::Conflict().json(ReferencedBy {
+            message: format!("command template {} is used by {} cronjobs", id, cronjob_ids.len()),
+            cronjob_ids,
+        }));
+    }
+    delete_command_template_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to delete a command template: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to delete a command template")})?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+
+// 预览渲染结果，不执行；参数错误返回422
+pub async fn render_command_template(data: web::Data<AppState>,id: web::Path<i32>,body: Option<web::Json<RenderTemplate>>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    let params = body.map(|b| b.into_inner()).unwrap_or_default();
+    let template = find_template(&data, id).await?;
+    let server = match params.server_id {
+        Some(server_id) => Some(get_server_by_id_db(&data.db_pool, server_id).await.map_err(|e| {
+            error!("Failed to fetch server: {:?}", e);
+            actix_web::error::ErrorNotFound("Server not found")})?),
+        None => None,
+    };
+    let values: Map<_, _> = params.params.into_iter().collect();
+    let command = template.render(&[&values], server.as_ref())
+        .map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let env = template.render_env(&[&values], server.as_ref())
+        .map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(RenderedCommand { template_id: id, server_id: params.server_id, command, env: env.vars, workdir: env.workdir }))
+}
+
+
+async fn find_template(data: &web::Data<AppState>, id: i32) -> Result<CommandTemplate, actix_web::Error> {
+    get_command_template_by_id_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get a command template: {:?}", e);
+        actix_web::error::ErrorNotFound("Command template not found")})
+}
+
+
+// 任务或运行请求引用模板时检查模板存在、给出的参数都声明过且类型正确
+// 必填参数可能来自运行请求或主机标签，这里不检查是否缺少
+pub async fn check_template_values(data: &web::Data<AppState>, template_id: i32, values: &Map<String, Value>) -> Result<CommandTemplate, actix_web::Error> {
+    let template = get_command_template_by_id_db(&data.db_pool, template_id).await.map_err(|e| {
+        error!("Failed to get a command template: {:?}", e);
+        actix_web::error::ErrorUnprocessableEntity(format!("command template {} not found", template_id))})?;
+    validate_values(&template.params, values).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    Ok(template)
+}
@@ -1,12 +1,25 @@
-use actix_web::{web,HttpResponse};
+use actix_web::{web,HttpRequest,HttpResponse};
 use log::error;
+use serde_json::{Map, Value};
 use tracing::field::debug;
 use crate::db::pool::AppState;
-use crate::domain::cron_job::{CreateCronJob, UpdateCronJob};
-use crate::repository::cron_job::{get_all_cronjobs_db, get_cronjob_by_id_db,create_cronjob_db,update_cronjob_db};
-
-pub async fn get_all_cronjobs(data:web::Data<AppState>) -> Result<HttpResponse, actix_web::Error>{
-    let rows = get_all_cronjobs_db(&data.db_pool).await.map_err(|e| {
+use crate::domain::cron_job::{CreateCronJob, CronJobQuery, ScheduleType, UpdateCronJob, parse_timezone, validate_schedule, DEFAULT_TIMEZONE};
+use crate::domain::cron_preview::{CronPreviewRequest, preview_cron, validate_cron_expression};
+use crate::domain::spread::validate_spread;
+use crate::domain::environment::validate_env;
+use crate::domain::privilege::BecomeMethod;
+use crate::handler::command_template::check_template_values;
+use crate::repository::playbook::get_playbook_by_id_db;
+use crate::repository::runbook::get_runbook_by_id_db;
+use crate::repository::script::get_script_by_id_db;
+use crate::handler::cron_job_version::changed_by;
+use crate::domain::success::validate_success_rules;
+use crate::repository::cron_job::{get_all_cronjobs_db, get_cronjob_by_id_db,create_cronjob_db,update_cronjob_db,enable_cronjob_db,archive_cronjob_db,restore_cronjob_db};
+use crate::repository::server::get_server_by_id_db;
+use crate::repository::servergroup::get_group_by_id_db;
+
+pub async fn get_all_cronjobs(data:web::Data<AppState>,query: web::Query<CronJobQuery>) -> Result<HttpResponse, actix_web::Error>{
+    let rows = get_all_cronjobs_db(&data.db_pool, query.archived.unwrap_or(false)).await.map_err(|e| {
         error!("Failed to get cronjobs: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to get cronjobs")})?;
     Ok(HttpResponse::Ok().json(rows))
@@ -24,33 +37,182 @@ pub async fn get_cronjob_by_id(data: web::Data<AppState>,params: web::Path<i32>)
 
 
 
-pub async fn create_cronjob(data: web::Data<AppState>,job: web::Json<CreateCronJob>) -> Result<HttpResponse, actix_web::Error> {
+pub async fn create_cronjob(req: HttpRequest,data: web::Data<AppState>,job: web::Json<CreateCronJob>) -> Result<HttpResponse, actix_web::Error> {
     debug("test cron job handler started");
-    let row = create_cronjob_db(&data.db_pool, job.into_inner().try_into()?).await.map_err(|e| {
+    let timezone = job.timezone.as_deref().unwrap_or(DEFAULT_TIMEZONE);
+    let schedule_type = job.schedule_type.unwrap_or_default();
+    if schedule_type == ScheduleType::Cron {
+        validate_cron_expression(&job.cron_expression, timezone).map_err(|e| {
+            actix_web::error::ErrorUnprocessableEntity(e.to_string())})?;
+    } else {
+        parse_timezone(timezone).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+        validate_schedule(schedule_type, job.run_at, job.interval_secs).map_err(|e| {
+            actix_web::error::ErrorUnprocessableEntity(e.to_string())})?;
+    }
+    validate_success_rules(
+        &[job.stdout_must_match.as_deref(), job.stdout_must_not_match.as_deref(), job.stderr_must_match.as_deref(), job.stderr_must_not_match.as_deref()],
+        job.min_success_ratio,
+    ).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    validate_spread(job.jitter_secs, job.spread_secs).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    for vars in [job.env.as_ref(), job.secret_env.as_ref()].into_iter().flatten() {
+        validate_env(vars).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    }
+    let values = job.template_params.clone().unwrap_or_default().into_iter().collect();
+    check_job_command(&data, &job.command, job.template_id, &values, job.runbook_id, job.playbook_id, job.script_id).await?;
+    let row = create_cronjob_db(&data.db_pool, job.into_inner().try_into()?, changed_by(&req)).await.map_err(|e| {
         error!("Failed to create a cronjob: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to create a cronjob")})?;
     Ok(HttpResponse::Ok().json(row))
 }
 
 
-pub async fn update_cronjob(data: web::Data<AppState>,job_id:web::Path<i32>,job: web::Json<UpdateCronJob>) -> Result<HttpResponse, actix_web::Error> {
-    let row = update_cronjob_db(&data.db_pool, job_id.into_inner(),job.into_inner().try_into()?).await.map_err(|e| {
+pub async fn update_cronjob(req: HttpRequest,data: web::Data<AppState>,job_id:web::Path<i32>,job: web::Json<UpdateCronJob>) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(timezone) = &job.timezone {
+        parse_timezone(timezone).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    }
+    if let Some(expression) = &job.cron_expression {
+        // 表达式本身的合法性与时区无关，时区已在上面单独校验
+        validate_cron_expression(expression, DEFAULT_TIMEZONE).map_err(|e| {
+            actix_web::error::ErrorUnprocessableEntity(e.to_string())})?;
+    }
+    // 改为once/interval时需要同时给出run_at/interval_secs
+    if let Some(schedule_type) = job.schedule_type {
+        validate_schedule(schedule_type, job.run_at, job.interval_secs).map_err(|e| {
+            actix_web::error::ErrorUnprocessableEntity(e.to_string())})?;
+    }
+    if job.interval_secs.is_some_and(|secs| secs <= 0) {
+        return Err(actix_web::error::ErrorUnprocessableEntity("interval_secs must be greater than 0"));
+    }
+    if let Some(method) = job.become_method.as_deref().filter(|m| !m.is_empty()) {
+        method.parse::<BecomeMethod>().map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    }
+    validate_success_rules(
+        &[job.stdout_must_match.as_deref(), job.stdout_must_not_match.as_deref(), job.stderr_must_match.as_deref(), job.stderr_must_not_match.as_deref()],
+        job.min_success_ratio,
+    ).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    validate_spread(job.jitter_secs, job.spread_secs).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    for vars in [job.env.as_ref(), job.secret_env.as_ref()].into_iter().flatten() {
+        validate_env(vars).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    }
+    if job.template_id.is_some() || job.template_params.is_some() || job.runbook_id.is_some() || job.playbook_id.is_some() || job.script_id.is_some() {
+        check_job_update_command(&data, *job_id, &job).await?;
+    }
+    let row = update_cronjob_db(&data.db_pool, job_id.into_inner(),job.into_inner().try_into()?, changed_by(&req)).await.map_err(|e| {
         error!("Failed to update cronjob: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to update cronjob")})?;
     Ok(HttpResponse::Ok().json(row))
 }
 
 
+// 任务执行的命令来源：template_id、runbook_id、playbook_id、script_id最多一个，都没有时要有command
+async fn check_job_command(data: &web::Data<AppState>, command: &str, template_id: Option<i32>, values: &Map<String, Value>, runbook_id: Option<i32>, playbook_id: Option<i32>, script_id: Option<i32>) -> Result<(), actix_web::Error> {
+    let sources = [template_id, runbook_id, playbook_id, script_id].iter().filter(|id| id.is_some()).count();
+    if sources > 1 {
+        return Err(actix_web::error::ErrorUnprocessableEntity("only one of template_id, runbook_id, playbook_id and script_id can be used"));
+    }
+    if let Some(template_id) = template_id {
+        check_template_values(data, template_id, values).await?;
+    }
+    if let Some(runbook_id) = runbook_id {
+        get_runbook_by_id_db(&data.db_pool, runbook_id).await.map_err(|e| {
+            error!("Failed to get a runbook: {:?}", e);
+            actix_web::error::ErrorUnprocessableEntity(format!("runbook {} not found", runbook_id))})?;
+    }
+    if let Some(playbook_id) = playbook_id {
+        get_playbook_by_id_db(&data.db_pool, playbook_id).await.map_err(|e| {
+            error!("Failed to get a playbook: {:?}", e);
+            actix_web::error::ErrorUnprocessableEntity(format!("playbook {} not found", playbook_id))})?;
+    }
+    if let Some(script_id) = script_id {
+        get_script_by_id_db(&data.db_pool, script_id).await.map_err(|e| {
+            error!("Failed to get a script: {:?}", e);
+            actix_web::error::ErrorUnprocessableEntity(format!("script {} not found", script_id))})?;
+    }
+    if sources == 0 && command.trim().is_empty() {
+        return Err(actix_web::error::ErrorUnprocessableEntity("command, template_id, runbook_id, playbook_id or script_id is required"));
+    }
+    Ok(())
+}
 
 
+// 修改命令来源时按修改后的任务检查，没有修改的字段用当前值，传0表示取消引用
+async fn check_job_update_command(data: &web::Data<AppState>, job_id: i32, job: &UpdateCronJob) -> Result<(), actix_web::Error> {
+    let current = get_cronjob_by_id_db(&data.db_pool, job_id).await.map_err(|e| {
+        error!("Failed to get a cronjob: {:?}", e);
+        actix_web::error::ErrorNotFound("Cronjob not found")})?;
+    let template_id = job.template_id.or(current.template_id).filter(|id| *id != 0);
+    let runbook_id = job.runbook_id.or(current.runbook_id).filter(|id| *id != 0);
+    let playbook_id = job.playbook_id.or(current.playbook_id).filter(|id| *id != 0);
+    let script_id = job.script_id.or(current.script_id).filter(|id| *id != 0);
+    let values = match &job.template_params {
+        Some(values) => values.clone().into_iter().collect(),
+        None => current.template_params.as_object().cloned().unwrap_or_default(),
+    };
+    let command = job.command.as_deref().unwrap_or(&current.command);
+    check_job_command(data, command, template_id, &values, runbook_id, playbook_id, script_id).await
+}
 
 
+// 重新启用任务(例如被disable_on_failure停用后)，下次执行时间从现在开始计算
+pub async fn enable_cronjob(data: web::Data<AppState>,job_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let job_id = job_id.into_inner();
+    get_cronjob_by_id_db(&data.db_pool, job_id).await.map_err(|e| {
+        error!("Failed to get a cronjob: {:?}", e);
+        actix_web::error::ErrorNotFound("Cronjob not found")})?;
+    let row = enable_cronjob_db(&data.db_pool, job_id).await.map_err(|e| {
+        error!("Failed to enable cronjob: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to enable cronjob")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
 
 
+// 删除任务只做归档，运行记录和日志保留，可以恢复，超过保留时间后由purge彻底删除
+pub async fn delete_cronjob(data: web::Data<AppState>,job_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let job_id = job_id.into_inner();
+    get_cronjob_by_id_db(&data.db_pool, job_id).await.map_err(|e| {
+        error!("Failed to get a cronjob: {:?}", e);
+        actix_web::error::ErrorNotFound("Cronjob not found")})?;
+    let row = archive_cronjob_db(&data.db_pool, job_id).await.map_err(|e| {
+        error!("Failed to archive cronjob: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to archive cronjob")})?
+        .ok_or_else(|| actix_web::error::ErrorConflict("Cronjob already deleted"))?;
+    Ok(HttpResponse::Ok().json(row))
+}
 
 
+// 恢复归档的任务，引用的server或group仍在归档中时返回409
+pub async fn restore_cronjob(data: web::Data<AppState>,job_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let job_id = job_id.into_inner();
+    let job = get_cronjob_by_id_db(&data.db_pool, job_id).await.map_err(|e| {
+        error!("Failed to get a cronjob: {:?}", e);
+        actix_web::error::ErrorNotFound("Cronjob not found")})?;
+    if job.archived_at.is_none() {
+        return Err(actix_web::error::ErrorConflict("Cronjob is not archived"));
+    }
+    if let Some(server_id) = job.server_id {
+        let server = get_server_by_id_db(&data.db_pool, server_id).await.map_err(|e| {
+            error!("Failed to fetch server: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch server")})?;
+        if server.archived_at.is_some() {
+            return Err(actix_web::error::ErrorConflict(format!("server {} is archived, restore it first", server_id)));
+        }
+    }
+    if let Some(group_id) = job.group_id {
+        let group = get_group_by_id_db(&data.db_pool, group_id).await.map_err(|e| {
+            error!("Failed to fetch group: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch group")})?;
+        if group.archived_at.is_some() {
+            return Err(actix_web::error::ErrorConflict(format!("group {} is archived, restore it first", group_id)));
+        }
+    }
+    let row = restore_cronjob_db(&data.db_pool, job_id).await.map_err(|e| {
+        error!("Failed to restore cronjob: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to restore cronjob")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
 
 
-
-
-
+// 预览cron表达式：是否合法、可读描述、接下来N次执行时间
+pub async fn preview_cronjob(req: web::Json<CronPreviewRequest>) -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok().json(preview_cron(&req.into_inner())))
+}
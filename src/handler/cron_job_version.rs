@@ -0,0 +1,66 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use log::error;
+use crate::db::pool::AppState;
+use crate::domain::cron_job_version::*;
+use crate::repository::cron_job_version::*;
+
+
+// 请求头里的修改人，没有时为空
+pub fn changed_by(req: &HttpRequest) -> Option<String> {
+    normalize_changed_by(req.headers().get(CHANGED_BY_HEADER).and_then(|value| value.to_str().ok()))
+}
+
+
+// 任务的版本历史，最新的在前
+pub async fn get_cronjob_versions(data: web::Data<AppState>,job_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_cronjob_versions_db(&data.db_pool, job_id.into_inner()).await.map_err(|e| {
+        error!("Failed to get cronjob versions: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjob versions")})?;
+    let rows: Vec<CronJobVersion> = rows.into_iter().map(CronJobVersion::masked).collect();
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+
+pub async fn get_cronjob_version(data: web::Data<AppState>,params: web::Path<(i32, i32)>) -> Result<HttpResponse, actix_web::Error> {
+    let (job_id, version) = params.into_inner();
+    let row = find_version(&data, job_id, version).await?;
+    Ok(HttpResponse::Ok().json(row.masked()))
+}
+
+
+// ?from=1&to=3，to省略时与任务当前的定义比较
+pub async fn diff_cronjob_versions(data: web::Data<AppState>,job_id: web::Path<i32>,query: web::Query<VersionDiffQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let job_id = job_id.into_inner();
+    let from = find_version(&data, job_id, query.from).await?;
+    let (to, snapshot) = match query.to {
+        Some(version) => {
+            let to = find_version(&data, job_id, version).await?;
+            (to.version, to.snapshot)
+        }
+        // 和历史版本一样取数据库里整行的json，不经过CronJob的序列化
+        None => get_cronjob_snapshot_db(&data.db_pool, job_id).await.map_err(|e| {
+            error!("Failed to get a cronjob: {:?}", e);
+            actix_web::error::ErrorNotFound("Cronjob not found")})?,
+    };
+    let changes = diff_snapshots(&from.snapshot, &snapshot);
+    Ok(HttpResponse::Ok().json(VersionDiff { job_id, from: from.version, to, changes }))
+}
+
+
+// 用历史版本的定义覆盖任务，生成新版本；enabled和下次执行时间不随版本回滚
+pub async fn restore_cronjob_version(req: HttpRequest,data: web::Data<AppState>,params: web::Path<(i32, i32)>) -> Result<HttpResponse, actix_web::Error> {
+    let (job_id, version) = params.into_inner();
+    let target = find_version(&data, job_id, version).await?;
+    let row = restore_cronjob_version_db(&data.db_pool, job_id, &target, changed_by(&req)).await.map_err(|e| {
+        error!("Failed to restore cronjob version: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to restore cronjob version")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+async fn find_version(data: &web::Data<AppState>, job_id: i32, version: i32) -> Result<CronJobVersion, actix_web::Error> {
+    get_cronjob_version_db(&data.db_pool, job_id, version).await.map_err(|e| {
+        error!("Failed to get a cronjob version: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get a cronjob version")})?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Cronjob version not found"))
+}
@@ -0,0 +1,71 @@
+use actix_web::{HttpResponse, web};
+use log::error;
+use serde_json::{Map, Value};
+use crate::db::pool::AppState;
+use crate::domain::cron_run::{CronRunDetail, RunCronJob, RunStatus, RunTrigger};
+use crate::handler::command_template::check_template_values;
+use crate::repository::cron_job::get_cronjob_by_id_db;
+use crate::repository::cron_log::get_cron_log_by_run_id_db;
+use crate::repository::cron_run::{create_run_db, get_run_by_id_db, get_runs_by_job_id_db};
+
+
+// 手动运行一次任务：写入queued的运行记录，由worker收到通知后认领执行，不影响next_execute_at
+// 请求体可以省略，返回的run_id用于查询运行状态和日志
+pub async fn run_cronjob(data: web::Data<AppState>,job_id: web::Path<i32>,body: Option<web::Json<RunCronJob>>) -> Result<HttpResponse, actix_web::Error> {
+    let job_id = job_id.into_inner();
+    let params = body.map(|b| b.into_inner()).unwrap_or_default();
+    if params.command.as_deref().is_some_and(|c| c.trim().is_empty()) {
+        return Err(actix_web::error::ErrorUnprocessableEntity("command must not be empty"));
+    }
+    let job = get_cronjob_by_id_db(&data.db_pool, job_id).await.map_err(|e| {
+        error!("Failed to get a cronjob: {:?}", e);
+        actix_web::error::ErrorNotFound("Cronjob not found")})?;
+    if job.archived_at.is_some() {
+        return Err(actix_web::error::ErrorConflict("Cronjob is archived"));
+    }
+    // 本次运行的模板参数只在任务引用了模板时有意义
+    let template_params = match (&params.params, job.template_id) {
+        (Some(values), Some(template_id)) => {
+            let values: Map<String, Value> = values.clone().into_iter().collect();
+            check_template_values(&data, template_id, &values).await?;
+            Some(Value::Object(values))
+        }
+        (Some(_), None) => return Err(actix_web::error::ErrorUnprocessableEntity("cronjob does not use a command template")),
+        (None, _) => None,
+    };
+    let mut conn = data.db_pool.acquire().await.map_err(|e| {
+        error!("Failed to acquire a connection: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a cronjob run")})?;
+    let run = create_run_db(
+        &mut conn,
+        job_id,
+        RunTrigger::Manual,
+        RunStatus::Queued,
+        params.command,
+        template_params,
+        params.dry_run.unwrap_or(false),
+    ).await.map_err(|e| {
+        error!("Failed to create a cronjob run: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a cronjob run")})?;
+    Ok(HttpResponse::Accepted().json(run))
+}
+
+
+pub async fn get_run_by_id(data: web::Data<AppState>,run_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let run_id = run_id.into_inner();
+    let run = get_run_by_id_db(&data.db_pool, run_id).await.map_err(|e| {
+        error!("Failed to get a cronjob run: {:?}", e);
+        actix_web::error::ErrorNotFound("Cronjob run not found")})?;
+    let logs = get_cron_log_by_run_id_db(&data.db_pool, run_id).await.map_err(|e| {
+        error!("Failed to get cronlog: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronlog")})?;
+    Ok(HttpResponse::Ok().json(CronRunDetail { run, logs }))
+}
+
+
+pub async fn get_runs_by_job_id(data: web::Data<AppState>,job_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_runs_by_job_id_db(&data.db_pool, job_id.into_inner()).await.map_err(|e| {
+        error!("Failed to get cronjob runs: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjob runs")})?;
+    Ok(HttpResponse::Ok().json(rows))
+}
@@ -0,0 +1,94 @@
+use actix_web::{HttpResponse, web};
+use log::error;
+use crate::db::pool::AppState;
+use crate::domain::cron_run::{RunStatus, RunTrigger};
+use crate::domain::dead_letter::*;
+use crate::repository::cron_job::{enable_cronjob_db, get_cronjob_by_id_db};
+use crate::repository::cron_log::get_cron_log_by_run_id_db;
+use crate::repository::cron_run::{create_run_db, get_run_by_id_db};
+use crate::repository::dead_letter::*;
+
+
+// ?status=open&job_id=1
+pub async fn get_dead_letters(data: web::Data<AppState>,query: web::Query<DeadLetterQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_dead_letters_db(&data.db_pool, query.into_inner()).await.map_err(|e| {
+        error!("Failed to get dead letters: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get dead letters")})?;
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+
+// 死信和失败那次运行的全部日志
+pub async fn get_dead_letter_by_id(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let dead_letter = get_dead_letter_by_id_db(&data.db_pool, id.into_inner()).await.map_err(|e| {
+        error!("Failed to get a dead letter: {:?}", e);
+        actix_web::error::ErrorNotFound("Dead letter not found")})?;
+    let logs = match dead_letter.run_id {
+        Some(run_id) => get_cron_log_by_run_id_db(&data.db_pool, run_id).await.map_err(|e| {
+            error!("Failed to get cronlog: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to get cronlog")})?,
+        None => Vec::new(),
+    };
+    Ok(HttpResponse::Ok().json(DeadLetterDetail { dead_letter, logs }))
+}
+
+
+// 以手动运行重新执行失败时的命令，默认在任务因这次失败被停用时重新启用任务
+// 请求体可以省略，只有open的死信可以重放，重复处理返回409
+pub async fn replay_dead_letter(data: web::Data<AppState>,id: web::Path<i32>,body: Option<web::Json<ReplayDeadLetter>>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    let params = body.map(|b| b.into_inner()).unwrap_or_default();
+    let dead_letter = get_dead_letter_by_id_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get a dead letter: {:?}", e);
+        actix_web::error::ErrorNotFound("Dead letter not found")})?;
+    let job = get_cronjob_by_id_db(&data.db_pool, dead_letter.job_id).await.map_err(|e| {
+        error!("Failed to get a cronjob: {:?}", e);
+        actix_web::error::ErrorNotFound("Cronjob not found")})?;
+    // 失败那次运行可能指定了临时命令或模板参数，运行记录被删除时使用任务当前的定义
+    let (command, template_params) = match dead_letter.run_id {
+        Some(run_id) => get_run_by_id_db(&data.db_pool, run_id).await.ok()
+            .map(|run| (run.command, run.template_params))
+            .unwrap_or_default(),
+        None => (None, None),
+    };
+    // 占住死信、创建运行、记下重放的运行在同一个事务里，并发重放只有一个能成功，失败时死信仍是open
+    let mut tx = data.db_pool.begin().await.map_err(|e| {
+        error!("Failed to begin a transaction: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to replay a dead letter")})?;
+    resolve_dead_letter_db(&mut tx, id, DeadLetterStatus::Replayed).await.map_err(|e| {
+        error!("Failed to replay a dead letter: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to replay a dead letter")})?
+        .ok_or_else(|| actix_web::error::ErrorConflict("Dead letter already resolved"))?;
+    let run = create_run_db(&mut tx, job.id, RunTrigger::Manual, RunStatus::Queued, command, template_params, false).await.map_err(|e| {
+        error!("Failed to create a cronjob run: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a cronjob run")})?;
+    let dead_letter = set_replay_run_db(&mut tx, id, run.run_id).await.map_err(|e| {
+        error!("Failed to update a dead letter: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update a dead letter")})?;
+    tx.commit().await.map_err(|e| {
+        error!("Failed to commit a dead letter replay: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to replay a dead letter")})?;
+    if params.enable_job.unwrap_or(dead_letter.job_disabled) && !job.enabled {
+        enable_cronjob_db(&data.db_pool, job.id).await.map_err(|e| {
+            error!("Failed to enable a cronjob: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to enable a cronjob")})?;
+    }
+    Ok(HttpResponse::Accepted().json(ReplayResult { dead_letter, run }))
+}
+
+
+// 确认不需要处理，不改动任务
+pub async fn discard_dead_letter(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    get_dead_letter_by_id_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get a dead letter: {:?}", e);
+        actix_web::error::ErrorNotFound("Dead letter not found")})?;
+    let mut conn = data.db_pool.acquire().await.map_err(|e| {
+        error!("Failed to acquire a connection: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to discard a dead letter")})?;
+    let dead_letter = resolve_dead_letter_db(&mut conn, id, DeadLetterStatus::Discarded).await.map_err(|e| {
+        error!("Failed to discard a dead letter: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to discard a dead letter")})?
+        .ok_or_else(|| actix_web::error::ErrorConflict("Dead letter already resolved"))?;
+    Ok(HttpResponse::Ok().json(dead_letter))
+}
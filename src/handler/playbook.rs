@@ -0,0 +1,86 @@
+use actix_web::{HttpResponse, web};
+use log::error;
+use crate::db::pool::AppState;
+use crate::domain::archive::ReferencedBy;
+use crate::domain::environment::Environment;
+use crate::domain::playbook::*;
+use crate::handler::runbook::target_servers;
+use crate::repository::playbook::*;
+use crate::repository::ssh::servers_playbook_back;
+
+
+pub async fn get_all_playbooks(data: web::Data<AppState>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_all_playbooks_db(&data.db_pool).await.map_err(|e| {
+        error!("Failed to get playbooks: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get playbooks")})?;
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+
+pub async fn get_playbook_by_id(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let row = find_playbook(&data, id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+pub async fn create_playbook(data: web::Data<AppState>,body: web::Json<CreatePlaybook>) -> Result<HttpResponse, actix_web::Error> {
+    validate_steps(&body.steps).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let row = create_playbook_db(&data.db_pool, body.into_inner()).await.map_err(|e| {
+        error!("Failed to create a playbook: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a playbook")})?;
+    Ok(HttpResponse::Created().json(row))
+}
+
+
+pub async fn update_playbook(data: web::Data<AppState>,id: web::Path<i32>,body: web::Json<UpdatePlaybook>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    find_playbook(&data, id).await?;
+    if let Some(steps) = &body.steps {
+        validate_steps(steps).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    }
+    let row = update_playbook_db(&data.db_pool, id, body.into_inner()).await.map_err(|e| {
+        error!("Failed to update a playbook: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update a playbook")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+// 仍有任务执行它时返回409和这些任务
+pub async fn delete_playbook(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    find_playbook(&data, id).await?;
+    let cronjob_ids = get_playbook_cronjob_ids_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get cronjobs of a playbook: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjobs of a playbook")})?;
+    if !cronjob_ids.is_empty() {
+        return Ok(HttpResponse::Conflict().json(ReferencedBy {
+            message: format!("playbook {} is used by {} cronjobs", id, cronjob_ids.len()),
+            cronjob_ids,
+        }));
+    }
+    delete_playbook_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to delete a playbook: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to delete a playbook")})?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+
+// 临时执行，所有server结束后按server、按步骤返回结果
+pub async fn run_playbook(data: web::Data<AppState>,id: web::Path<i32>,body: web::Json<RunPlaybook>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    let playbook = find_playbook(&data, id).await?;
+    let target = body.target().map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let servers = target_servers(&data, target).await?;
+    if servers.is_empty() {
+        return Err(actix_web::error::ErrorUnprocessableEntity("no server matched the target"));
+    }
+    let results = servers_playbook_back(None, &data.db_pool, servers, Vec::new(), &playbook.steps, None, Environment::default()).await;
+    Ok(HttpResponse::Ok().json(PlaybookRunResult { playbook_id: id, results }))
+}
+
+
+async fn find_playbook(data: &web::Data<AppState>, id: i32) -> Result<Playbook, actix_web::Error> {
+    get_playbook_by_id_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get a playbook: {:?}", e);
+        actix_web::error::ErrorNotFound("Playbook not found")})
+}
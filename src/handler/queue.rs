@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::env;
+use actix_web::{HttpResponse, web};
+use chrono::{Duration, Utc};
+use log::error;
+use crate::db::pool::AppState;
+use crate::domain::queue::*;
+use crate::domain::scheduler::{JobQueue, QueueBackend};
+use crate::repository::cron_job::get_all_cronjobs_db;
+use crate::repository::cron_run::get_pickup_lags_db;
+use crate::scheduler::prepare::resync_job_from_sql;
+
+const DEFAULT_LAG_WINDOW_MINUTES: i64 = 60;
+
+
+fn queue(data: &AppState) -> Result<&QueueBackend, actix_web::Error> {
+    data.queue.as_ref().ok_or_else(|| actix_web::error::ErrorNotImplemented("Scheduler queue is not available in this process"))
+}
+
+fn queue_error(e: anyhow::Error) -> actix_web::Error {
+    error!("Scheduler queue error: {:?}", e);
+    actix_web::error::ErrorInternalServerError("Scheduler queue error")
+}
+
+
+// 两个队列的内容，附带任务名、到期时间和租约死线
+pub async fn get_queue(data: web::Data<AppState>) -> Result<HttpResponse, actix_web::Error> {
+    let snapshot = queue(&data)?.list_jobs().await.map_err(queue_error)?;
+    let names: HashMap<i32, Option<String>> = get_all_cronjobs_db(&data.db_pool, false).await
+        .map(|jobs| jobs.into_iter().map(|job| (job.id, job.name)).collect())
+        .map_err(|e| {
+            error!("Failed to get cronjobs: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to get cronjobs")})?;
+    let now = Utc::now().timestamp_millis();
+    let pending = snapshot.pending.iter().map(|entry| QueuedJob {
+        job_id: entry.job_id,
+        name: names.get(&entry.job_id).cloned().flatten(),
+        due_at: entry.time(),
+        overdue_ms: (now - entry.score).max(0),
+    }).collect();
+    let processing = snapshot.processing.iter().map(|entry| InFlightJob {
+        job_id: entry.job_id,
+        name: names.get(&entry.job_id).cloned().flatten(),
+        lease_deadline: entry.time(),
+        expired: entry.score <= now,
+    }).collect();
+    Ok(HttpResponse::Ok().json(QueueView { pending, processing }))
+}
+
+
+// 放回pending；processing中的记录会被移出，注意原worker如果还活着，任务会再执行一次
+pub async fn requeue_job(data: web::Data<AppState>,job_id: web::Path<i32>,body: Option<web::Json<RequeueJob>>) -> Result<HttpResponse, actix_web::Error> {
+    let job_id = job_id.into_inner();
+    let params = body.map(|b| b.into_inner()).unwrap_or_default();
+    let execute_at = params.execute_at.unwrap_or_else(Utc::now).timestamp_millis();
+    queue(&data)?.retry_job(job_id, execute_at).await.map_err(queue_error)?;
+    Ok(HttpResponse::Ok().json(QueueEntry::new(job_id, execute_at)))
+}
+
+
+// 从队列删除，不修改任务本身，下次reload时仍满足条件的任务会被重新加回
+pub async fn drop_job(data: web::Data<AppState>,job_id: web::Path<i32>,query: web::Query<DropJobQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let job_id = job_id.into_inner();
+    let queue = queue(&data)?;
+    if query.state != Some(QueueState::Processing) {
+        queue.del_job_pending(job_id).await.map_err(queue_error)?;
+    }
+    if query.state != Some(QueueState::Pending) {
+        queue.del_job(job_id).await.map_err(queue_error)?;
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+
+pub async fn resync_queue(data: web::Data<AppState>) -> Result<HttpResponse, actix_web::Error> {
+    let save_secs: u64 = env::var("SAVE_SECS").unwrap_or("300".to_string()).parse()
+        .map_err(|_| actix_web::error::ErrorInternalServerError("SAVE_SECS must be number"))?;
+    let report = resync_job_from_sql(&data.db_pool, queue(&data)?.clone(), save_secs).await.map_err(queue_error)?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+
+// 最近一段时间定时运行从到期到开始执行的延迟，以及当前积压在pending中的到期任务
+pub async fn get_queue_lag(data: web::Data<AppState>,query: web::Query<QueueLagQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let window_minutes = query.minutes.unwrap_or(DEFAULT_LAG_WINDOW_MINUTES).max(1);
+    let samples = get_pickup_lags_db(&data.db_pool, Utc::now() - Duration::minutes(window_minutes)).await.map_err(|e| {
+        error!("Failed to get queue lag: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get queue lag")})?;
+    let snapshot = queue(&data)?.list_jobs().await.map_err(queue_error)?;
+    let now = Utc::now().timestamp_millis();
+    let overdue: Vec<i64> = snapshot.pending.iter().filter(|entry| entry.score <= now).map(|entry| now - entry.score).collect();
+    Ok(HttpResponse::Ok().json(QueueLag {
+        window_minutes,
+        picked_up: lag_stats(samples),
+        overdue_count: overdue.len(),
+        max_overdue_ms: overdue.into_iter().max().unwrap_or(0),
+    }))
+}
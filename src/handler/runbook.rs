@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use log::error;
+use crate::db::pool::AppState;
+use crate::domain::archive::ReferencedBy;
+use crate::domain::calendar::blocked_reason;
+use crate::domain::command_template::validate_labels;
+use crate::domain::runbook::*;
+use crate::domain::server::ServiceTerminal;
+use crate::domain::ssh_session::RemoteCommand;
+use crate::repository::calendar::get_group_calendar_rules_db;
+use crate::repository::runbook::*;
+use crate::repository::server::{get_server_by_group_id_db, get_server_by_id_db, get_servers_by_selector_db};
+use crate::repository::ssh::servers_ssh_back;
+
+
+pub async fn get_all_runbooks(data: web::Data<AppState>,query: web::Query<RunbookQuery>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_all_runbooks_db(&data.db_pool, query.into_inner().tag).await.map_err(|e| {
+        error!("Failed to get runbooks: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get runbooks")})?;
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+
+pub async fn get_runbook_by_id(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let row = find_runbook(&data, id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+pub async fn create_runbook(data: web::Data<AppState>,body: web::Json<CreateRunbook>) -> Result<HttpResponse, actix_web::Error> {
+    let mut body = body.into_inner();
+    if body.body.trim().is_empty() {
+        return Err(actix_web::error::ErrorUnprocessableEntity("body must not be empty"));
+    }
+    body.tags = normalize_tags(body.tags).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    check_default_target(body.server_id, body.group_id, body.selector.as_ref())?;
+    let row = create_runbook_db(&data.db_pool, body).await.map_err(|e| {
+        error!("Failed to create a runbook: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a runbook")})?;
+    Ok(HttpResponse::Created().json(row))
+}
+
+
+pub async fn update_runbook(data: web::Data<AppState>,id: web::Path<i32>,body: web::Json<UpdateRunbook>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    let mut body = body.into_inner();
+    let current = find_runbook(&data, id).await?;
+    if body.body.as_deref().is_some_and(|b| b.trim().is_empty()) {
+        return Err(actix_web::error::ErrorUnprocessableEntity("body must not be empty"));
+    }
+    if let Some(tags) = body.tags.take() {
+        body.tags = Some(normalize_tags(tags).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?);
+    }
+    // 按修改后的默认目标检查，只改其中一个时另外的用当前值
+    let selector = match &body.selector {
+        Some(selector) => selector.clone(),
+        None => serde_json::from_value(current.selector.clone()).unwrap_or_default(),
+    };
+    check_default_target(body.server_id.or(current.server_id), body.group_id.or(current.group_id), Some(&selector))?;
+    let row = update_runbook_db(&data.db_pool, id, body).await.map_err(|e| {
+        error!("Failed to update a runbook: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update a runbook")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+// 仍有任务把它当作命令时返回409和这些任务
+pub async fn delete_runbook(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    find_runbook(&data, id).await?;
+    let cronjob_ids = get_runbook_cronjob_ids_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get cronjobs of a runbook: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjobs of a runbook")})?;
+    if !cronjob_ids.is_empty() {
+        return Ok(HttpResponse::Conflict().json(ReferencedBy {
+            message: format!("runbook {} is used by {} cronjobs", id, cronjob_ids.len()),
+            cronjob_ids,
+        }));
+    }
+    delete_runbook_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to delete a runbook: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to delete a runbook")})?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+
+// 在server、group或标签选中的server上执行，等所有server结束后一起返回结果
+// 请求体可以省略，使用保存的默认目标
+pub async fn run_runbook(data: web::Data<AppState>,id: web::Path<i32>,body: Option<web::Json<RunRunbook>>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    let params = body.map(|b| b.into_inner()).unwrap_or_default();
+    let runbook = find_runbook(&data, id).await?;
+    let target = params.target(&runbook).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let servers = target_servers(&data, target).await?;
+    if servers.is_empty() {
+        return Err(actix_web::error::ErrorUnprocessableEntity("no server matched the target"));
+    }
+    let commands = vec![RemoteCommand::new(runbook.body.clone()); servers.len()];
+    let results = servers_ssh_back(None, &data.db_pool, servers, Vec::new(), commands, None).await;
+    Ok(HttpResponse::Ok().json(RunbookRunResult {
+        runbook_id: id,
+        results: results.into_iter().map(RunbookHostResult::from).collect(),
+    }))
+}
+
+
+// 解析执行目标，剧本也使用：和临时执行一样，归档的server返回409，group或选中server所在的group在封网窗口内返回423
+pub async fn target_servers(data: &web::Data<AppState>, target: RunbookTarget) -> Result<Vec<ServiceTerminal>, actix_web::Error> {
+    match target {
+        RunbookTarget::Server(server_id) => {
+            let server = get_server_by_id_db(&data.db_pool, server_id).await.map_err(|e| {
+                error!("Failed to fetch server: {:?}", e);
+                actix_web::error::ErrorNotFound("Server not found")})?;
+            if server.archived_at.is_some() {
+                return Err(actix_web::error::ErrorConflict("Server is archived"));
+            }
+            Ok(vec![server])
+        }
+        RunbookTarget::Group(group_id) => {
+            check_group_calendar(data, group_id).await?;
+            get_server_by_group_id_db(&data.db_pool, group_id).await.map_err(|e| {
+                error!("Failed to get server by group_id: {:?}", e);
+                actix_web::error::ErrorInternalServerError("Failed to get server by group_id")})
+        }
+        RunbookTarget::Selector(selector) => {
+            let servers = get_servers_by_selector_db(&data.db_pool, &selector).await.map_err(|e| {
+                error!("Failed to get server by selector: {:?}", e);
+                actix_web::error::ErrorInternalServerError("Failed to get server by selector")})?;
+            // 选中的server分属多个group时逐个检查，任何一个在封网窗口内都不执行
+            let mut group_ids: Vec<i32> = servers.iter().filter_map(|server| server.group_id).collect();
+            group_ids.sort();
+            group_ids.dedup();
+            for group_id in group_ids {
+                check_group_calendar(data, group_id).await?;
+            }
+            Ok(servers)
+        }
+    }
+}
+
+
+// group的日历当前不允许执行时返回423
+async fn check_group_calendar(data: &web::Data<AppState>, group_id: i32) -> Result<(), actix_web::Error> {
+    let calendar = get_group_calendar_rules_db(&data.db_pool, group_id).await.map_err(|e| {
+        error!("Failed to get group calendar: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get group calendar")})?;
+    let reason = blocked_reason(calendar.as_slice(), Utc::now()).map_err(|e| {
+        error!("Failed to check group calendar: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to check group calendar")})?;
+    if let Some(reason) = reason {
+        return Err(actix_web::error::ErrorLocked(format!("group {} is blocked: {}", group_id, reason)));
+    }
+    Ok(())
+}
+
+fn check_default_target(server_id: Option<i32>, group_id: Option<i32>, selector: Option<&HashMap<String, String>>) -> Result<(), actix_web::Error> {
+    if let Some(selector) = selector {
+        validate_labels(selector).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    }
+    validate_default_target(server_id, group_id, selector).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))
+}
+
+
+async fn find_runbook(data: &web::Data<AppState>, id: i32) -> Result<Runbook, actix_web::Error> {
+    get_runbook_by_id_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get a runbook: {:?}", e);
+        actix_web::error::ErrorNotFound("Runbook not found")})
+}
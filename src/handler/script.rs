@@ -0,0 +1,79 @@
+use actix_web::{HttpResponse, web};
+use log::error;
+use crate::db::pool::AppState;
+use crate::domain::archive::ReferencedBy;
+use crate::domain::script::*;
+use crate::repository::script::*;
+
+
+pub async fn get_all_scripts(data: web::Data<AppState>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_all_scripts_db(&data.db_pool).await.map_err(|e| {
+        error!("Failed to get scripts: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get scripts")})?;
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+
+pub async fn get_script_by_id(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let row = find_script(&data, id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+pub async fn create_script(data: web::Data<AppState>,body: web::Json<CreateScript>) -> Result<HttpResponse, actix_web::Error> {
+    validate_script_body(&body.body).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let row = create_script_db(&data.db_pool, body.into_inner()).await.map_err(|e| {
+        error!("Failed to create a script: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a script")})?;
+    Ok(HttpResponse::Created().json(row))
+}
+
+
+pub async fn update_script(data: web::Data<AppState>,id: web::Path<i32>,body: web::Json<UpdateScript>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    find_script(&data, id).await?;
+    if let Some(script_body) = &body.body {
+        validate_script_body(script_body).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    }
+    let row = update_script_db(&data.db_pool, id, body.into_inner()).await.map_err(|e| {
+        error!("Failed to update a script: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update a script")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+// 仍有任务执行它时返回409和这些任务
+pub async fn delete_script(data: web::Data<AppState>,id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let id = id.into_inner();
+    find_script(&data, id).await?;
+    let cronjob_ids = get_script_cronjob_ids_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get cronjobs of a script: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjobs of a script")})?;
+    if !cronjob_ids.is_empty() {
+        return Ok(HttpResponse::Conflict().json(ReferencedBy {
+            message: format!("script {} is used by {} cronjobs", id, cronjob_ids.len()),
+            cronjob_ids,
+        }));
+    }
+    delete_script_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to delete a script: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to delete a script")})?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+
+// 临时执行和任务都用：返回在server上执行的命令和作为stdin发送的脚本内容
+pub async fn script_invocation(data: &web::Data<AppState>, script_id: i32, args: Option<&[String]>) -> Result<(String, String), actix_web::Error> {
+    let script = find_script(data, script_id).await?;
+    let command = script.invocation(args).map_err(|e| {
+        error!("Failed to build invocation of script {}: {:?}", script_id, e);
+        actix_web::error::ErrorInternalServerError("Failed to build script invocation")})?;
+    Ok((command, script.body))
+}
+
+
+async fn find_script(data: &web::Data<AppState>, id: i32) -> Result<Script, actix_web::Error> {
+    get_script_by_id_db(&data.db_pool, id).await.map_err(|e| {
+        error!("Failed to get a script: {:?}", e);
+        actix_web::error::ErrorNotFound("Script not found")})
+}
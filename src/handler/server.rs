@@ -3,14 +3,20 @@ use actix_web::{web, HttpResponse};
 use tracing::log::error;
 use crate::repository::server::*;
 use crate::domain::server::*;
+use crate::domain::archive::{ArchiveQuery, DeleteQuery, ReferencedBy};
+use crate::repository::archive::get_server_cronjob_ids_db;
+use crate::repository::servergroup::get_group_by_id_db;
+use crate::domain::command_template::validate_labels;
 
 
 // update的暂时不写了
 
+// ?archived=true 查看已归档的server
 pub async fn get_all_servers(
-    data: web::Data<AppState>
+    data: web::Data<AppState>,
+    query: web::Query<ArchiveQuery>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let servers = get_all_servers_db(&data.db_pool)
+    let servers = get_all_servers_db(&data.db_pool, query.archived.unwrap_or(false))
         .await
         .map_err(|e| {
             error!("Failed to fetch servers: {:?}", e);
@@ -65,15 +71,29 @@ pub async fn create_group_server(data: web::Data<AppState>,server_list: web::Jso
 }
 
 
-pub async fn delete_single_server_by_id(data: web::Data<AppState>,server_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+// 删除只做归档；仍有任务引用时返回409和这些任务，确认后带?force=true把任务一起归档
+pub async fn delete_single_server_by_id(data: web::Data<AppState>,server_id: web::Path<i32>,query: web::Query<DeleteQuery>) -> Result<HttpResponse, actix_web::Error> {
     let server_id = server_id.into_inner();
-    let _ = get_server_by_id_db(&data.db_pool,server_id)
+    let server = get_server_by_id_db(&data.db_pool,server_id)
         .await
         .map_err(|e| {
             error!("Delete server Failed to fetch server: {:?}", e);
-            actix_web::error::ErrorInternalServerError("Delete server Failed to fetch server")
+            actix_web::error::ErrorNotFound("Server not found")
         })?;
-    let ans = delete_single_server_by_id_db(&data.db_pool,server_id).await
+    if server.archived_at.is_some() {
+        return Err(actix_web::error::ErrorConflict("Server already archived"));
+    }
+    let cronjob_ids = get_server_cronjob_ids_db(&data.db_pool, server_id).await.map_err(|e| {
+        error!("Delete server Failed to fetch cronjobs: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Delete server Failed to fetch cronjobs")
+    })?;
+    if !cronjob_ids.is_empty() && !query.force.unwrap_or(false) {
+        return Ok(HttpResponse::Conflict().json(ReferencedBy {
+            message: format!("server {} is used by {} cronjobs, retry with ?force=true to archive them together", server_id, cronjob_ids.len()),
+            cronjob_ids,
+        }));
+    }
+    let ans = archive_server_db(&data.db_pool,server_id).await
         .map_err(|e| {
         error!("Failed to Delete server: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to Delete server")
@@ -81,3 +101,60 @@ pub async fn delete_single_server_by_id(data: web::Data<AppState>,server_id: web
     Ok(HttpResponse::Ok().json(ans))
 }
 
+
+// 恢复归档的server，所在group仍在归档中时返回409；一起归档的任务需要单独恢复
+pub async fn restore_server(data: web::Data<AppState>,server_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let server_id = server_id.into_inner();
+    let server = get_server_by_id_db(&data.db_pool,server_id).await.map_err(|e| {
+        error!("Restore server Failed to fetch server: {:?}", e);
+        actix_web::error::ErrorNotFound("Server not found")
+    })?;
+    if server.archived_at.is_none() {
+        return Err(actix_web::error::ErrorConflict("Server is not archived"));
+    }
+    if let Some(group_id) = server.group_id {
+        let group = get_group_by_id_db(&data.db_pool, group_id).await.map_err(|e| {
+            error!("Restore server Failed to fetch group: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Restore server Failed to fetch group")
+        })?;
+        if group.archived_at.is_some() {
+            return Err(actix_web::error::ErrorConflict(format!("group {} is archived, restore it first", group_id)));
+        }
+    }
+    let server = restore_server_db(&data.db_pool, server_id).await.map_err(|e| {
+        error!("Failed to restore server: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to restore server")
+    })?;
+    Ok(HttpResponse::Ok().json(server))
+}
+
+
+// 整体替换server的标签，命令模板通过 {{label.key}} 或同名参数取值
+pub async fn update_server_labels(data: web::Data<AppState>,server_id: web::Path<i32>,body: web::Json<ServerLabels>) -> Result<HttpResponse, actix_web::Error> {
+    let server_id = server_id.into_inner();
+    validate_labels(&body.labels).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    get_server_by_id_db(&data.db_pool,server_id).await.map_err(|e| {
+        error!("Update labels Failed to fetch server: {:?}", e);
+        actix_web::error::ErrorNotFound("Server not found")
+    })?;
+    let server = update_server_labels_db(&data.db_pool, server_id, body.into_inner()).await.map_err(|e| {
+        error!("Failed to update server labels: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update server labels")
+    })?;
+    Ok(HttpResponse::Ok().json(server))
+}
+
+
+// 设置登录后的提权方式(sudo/su)和目标用户，密码加密保存，不会出现在命令和日志里
+pub async fn update_server_become(data: web::Data<AppState>,server_id: web::Path<i32>,body: web::Json<ServerBecome>) -> Result<HttpResponse, actix_web::Error> {
+    let server_id = server_id.into_inner();
+    get_server_by_id_db(&data.db_pool,server_id).await.map_err(|e| {
+        error!("Update become Failed to fetch server: {:?}", e);
+        actix_web::error::ErrorNotFound("Server not found")
+    })?;
+    let server = update_server_become_db(&data.db_pool, server_id, body.into_inner()).await.map_err(|e| {
+        error!("Failed to update server become: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update server become")
+    })?;
+    Ok(HttpResponse::Ok().json(server))
+}
@@ -1,12 +1,16 @@
 use crate::db::pool::AppState;
 use actix_web::{web, HttpResponse};
 use crate::domain::servergroup::*;
-use tracing::log::error;
+use tracing::log::{error, warn};
+use crate::domain::scheduler::JobQueue;
 use crate::repository::servergroup::*;
 use crate::repository::server::get_server_by_group_id_db;
+use crate::domain::archive::{ArchiveQuery, DeleteQuery, ReferencedBy};
+use crate::repository::archive::get_group_cronjob_ids_db;
 
-pub async fn get_all_groups(data: web::Data<AppState>) -> Result<HttpResponse, actix_web::Error>{
-    let groups = get_all_groups_db(&data.db_pool).await.map_err(|e| {
+// ?archived=true 查看已归档的group
+pub async fn get_all_groups(data: web::Data<AppState>, query: web::Query<ArchiveQuery>) -> Result<HttpResponse, actix_web::Error>{
+    let groups = get_all_groups_db(&data.db_pool, query.archived.unwrap_or(false)).await.map_err(|e| {
         error!("Failed to fetch servers: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to fetch servers")
     })?;
@@ -26,45 +30,98 @@ pub async fn get_group_by_id(data: web::Data<AppState>, id: web::Path<i32>) -> R
 }
 
 
+// group的并发上限立即同步到共用的队列，worker的定时reload也会同步
+async fn sync_group_limit(data: &AppState, group: &Group) {
+    if let Some(queue) = &data.queue
+        && let Err(e) = queue.set_group_limit(group.group_id, group.max_concurrency).await
+    {
+        warn!("Failed to sync group {} limit to queue: {:?}", group.group_id, e);
+    }
+}
+
+fn validate_max_concurrency(max_concurrency: Option<i32>) -> Result<(), actix_web::Error> {
+    if max_concurrency.is_some_and(|limit| limit < 0) {
+        return Err(actix_web::error::ErrorUnprocessableEntity("max_concurrency must not be negative"));
+    }
+    Ok(())
+}
+
+
 pub async fn create_group(data: web::Data<AppState>,group: web::Json<CreateGroup>) -> Result<HttpResponse, actix_web::Error> {
+    validate_max_concurrency(group.max_concurrency)?;
     let group = create_group_db(&data.db_pool,group.try_into()?).await.map_err(|e| {
         error!("Failed to create server: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to fetch servers")
     })?;
+    sync_group_limit(&data, &group).await;
     Ok(HttpResponse::Created().json(group))
 }
 
 
 
-pub async fn delete_group_by_id(data: web::Data<AppState>,group_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+// 删除只做归档；组内还有未归档的server时不能删除，仍有任务引用时返回409和这些任务，确认后带?force=true把任务一起归档
+pub async fn delete_group_by_id(data: web::Data<AppState>,group_id: web::Path<i32>,query: web::Query<DeleteQuery>) -> Result<HttpResponse, actix_web::Error> {
     let group_id = group_id.into_inner();
     // 查询下这个group，如果有报错则未查到
-    let _ = get_group_by_id_db(&data.db_pool, group_id).await.map_err(|e| {
+    let group = get_group_by_id_db(&data.db_pool, group_id).await.map_err(|e| {
         error!("Delete group but can't find this group: {:?}", e);
-        actix_web::error::ErrorInternalServerError("Delete group but can't find this group")
+        actix_web::error::ErrorNotFound("Delete group but can't find this group")
     })?;
+    if group.archived_at.is_some() {
+        return Err(actix_web::error::ErrorConflict("Group already archived"));
+    }
     // 获取这个group下的server
     let server = get_server_by_group_id_db(&data.db_pool, group_id).await.map_err(|e| {
         error!("Delete group Failed to get server: {:?}", e);
         actix_web::error::ErrorInternalServerError("Delete group Failed to get server")
     })?;
-    // 判断是否有server，如果没有则删除，有则报错
-    let ans = match server.len() {
-        0 => delete_group_by_id_db(&data.db_pool,group_id).await.map_err(|e| {
-            error!("can't delete this group: {:?}", e);
-            actix_web::error::ErrorInternalServerError("can't delete this group")
-        })?,
-        _ => Err(actix_web::error::ErrorInternalServerError("the group have server"))?,
-    };
+    if !server.is_empty() {
+        return Err(actix_web::error::ErrorConflict("the group have server"));
+    }
+    let cronjob_ids = get_group_cronjob_ids_db(&data.db_pool, group_id).await.map_err(|e| {
+        error!("Delete group Failed to get cronjobs: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Delete group Failed to get cronjobs")
+    })?;
+    if !cronjob_ids.is_empty() && !query.force.unwrap_or(false) {
+        return Ok(HttpResponse::Conflict().json(ReferencedBy {
+            message: format!("group {} is used by {} cronjobs, retry with ?force=true to archive them together", group_id, cronjob_ids.len()),
+            cronjob_ids,
+        }));
+    }
+    let ans = archive_group_db(&data.db_pool,group_id).await.map_err(|e| {
+        error!("can't delete this group: {:?}", e);
+        actix_web::error::ErrorInternalServerError("can't delete this group")
+    })?;
     Ok(HttpResponse::Ok().json(ans))
 }
 
 
+// 恢复归档的group，一起归档的任务需要单独恢复
+pub async fn restore_group(data: web::Data<AppState>,group_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let group_id = group_id.into_inner();
+    let group = get_group_by_id_db(&data.db_pool, group_id).await.map_err(|e| {
+        error!("Restore group but can't find this group: {:?}", e);
+        actix_web::error::ErrorNotFound("Group not found")
+    })?;
+    if group.archived_at.is_none() {
+        return Err(actix_web::error::ErrorConflict("Group is not archived"));
+    }
+    let group = restore_group_db(&data.db_pool, group_id).await.map_err(|e| {
+        error!("can't restore this group: {:?}", e);
+        actix_web::error::ErrorInternalServerError("can't restore this group")
+    })?;
+    sync_group_limit(&data, &group).await;
+    Ok(HttpResponse::Ok().json(group))
+}
+
+
 pub async fn update_group_by_id(data: web::Data<AppState>,group_id: web::Path<i32>,newgroup: web::Json<UpdateGroup>) -> Result<HttpResponse, actix_web::Error> {
     let group_id = group_id.into_inner();
+    validate_max_concurrency(newgroup.max_concurrency)?;
     let group = update_group_by_id_db(&data.db_pool, group_id,newgroup.try_into()?).await.map_err(|e| {
         error!("can't update this group: {:?}", e);
         actix_web::error::ErrorInternalServerError("can't update this group")
     })?;
+    sync_group_limit(&data, &group).await;
     Ok(HttpResponse::Ok().json(group))
 }
\ No newline at end of file
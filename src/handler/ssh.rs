@@ -1,4 +1,4 @@
-use crate::{domain::ssh_configuration::Message, repository::ssh::{test_connect_back,single_server_ssh_back,batch_server_ssh_back}};
+use crate::{domain::ssh_configuration::Message, repository::ssh::{test_connect_back,single_server_ssh_back,batch_server_ssh_back,host_become}};
 use actix_web::{web, HttpResponse};
 use crate::domain::ssh_session::*;
 use tracing::log::error;
@@ -6,6 +6,15 @@ use crate::db::pool::AppState;
 use crate::repository::server::*;
 use crate::utils::crypto::*;
 use crate::domain::server::ServiceTerminal;
+use crate::domain::calendar::blocked_reason;
+use crate::repository::calendar::get_group_calendar_rules_db;
+use crate::handler::command_template::check_template_values;
+use crate::handler::script::script_invocation;
+use crate::domain::environment::{validate_env, Environment};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 
@@ -32,8 +41,17 @@ pub async fn single_server_ssh_handler(data: web::Data<AppState>,body:web::Json<
         error!("Failed to get server please register server: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to get server please register server")
     })?;
+    if server.archived_at.is_some() {
+        return Err(actix_web::error::ErrorConflict("Server is archived"));
+    }
+    let source = AdHocSource {
+        command: &body.command, template_id: body.template_id, params: &body.params, script_id: body.script_id, args: body.args.as_deref(),
+        env: &body.env, workdir: body.workdir.as_deref(),
+    };
+    let commands = ad_hoc_commands(&data, source, std::slice::from_ref(&server)).await?;
+    let command = remote_commands(std::slice::from_ref(&server), commands)?.remove(0);
     let msg = Message::new(server.ssh_user, server.password.clone(),server.port.to_string(), Some(server.ip),None);
-    let (code,output) = single_server_ssh_back(None,&data.db_pool,msg, body.command.clone()).await?;
+    let (code,output) = single_server_ssh_back(None,&data.db_pool,msg, command).await?;
     Ok(HttpResponse::Ok().json(SshResponse {
         exit_code: code,
         output,
@@ -46,11 +64,29 @@ pub async fn batch_server_ssh_handler(data: web::Data<AppState>,body:web::Json<B
     // 处理server信息，获取地址的vec
     let body = body.into_inner();
     let group_id = body.group_id;
+    // group的封网窗口同样限制临时的批量执行
+    let calendar = get_group_calendar_rules_db(&data.db_pool, group_id).await.map_err(|e| {
+        error!("Failed to get group calendar: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get group calendar")
+    })?;
+    let reason = blocked_reason(calendar.as_slice(), Utc::now()).map_err(|e| {
+        error!("Failed to check group calendar: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to check group calendar")
+    })?;
+    if let Some(reason) = reason {
+        return Err(actix_web::error::ErrorLocked(format!("group {} is blocked: {}", group_id, reason)));
+    }
     let server_list:Vec<ServiceTerminal> = get_server_by_group_id_db(&data.db_pool, group_id).await.map_err(|e| {
         error!("Failed to get server by group_id: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to get server by group_id")
     })?;
 
+    let source = AdHocSource {
+        command: &body.command, template_id: body.template_id, params: &body.params, script_id: body.script_id, args: body.args.as_deref(),
+        env: &body.env, workdir: body.workdir.as_deref(),
+    };
+    let commands = ad_hoc_commands(&data, source, &server_list).await?;
+    let commands = remote_commands(&server_list, commands)?;
     let ssh_user = server_list[0].ssh_user.clone();
     let password = passwd_decrypt(server_list[0].password.clone()).map_err(|e| {
             error!("Failed to change password: {:?}", e);
@@ -61,7 +97,7 @@ pub async fn batch_server_ssh_handler(data: web::Data<AppState>,body:web::Json<B
     
     let msg = Message::new(ssh_user, password, port, None, Some(server_list));
 
-    let rx = batch_server_ssh_back(None,&data.db_pool,msg, body.command.clone()).await?;
+    let rx = batch_server_ssh_back(None,&data.db_pool,msg, commands).await?;
 
     // 异步
     // let buffer_size = env::var("CNOK_CHANNEL_BUFFER")
@@ -141,3 +177,58 @@ pub async fn batch_server_ssh_handler(data: web::Data<AppState>,body:web::Json<B
             .streaming(stream))
 
 }
+
+
+// 临时执行请求里给出的命令来源，script_id不能和command、template_id一起用
+struct AdHocSource<'a> {
+    command: &'a str,
+    template_id: Option<i32>,
+    params: &'a HashMap<String, Value>,
+    script_id: Option<i32>,
+    args: Option<&'a [String]>,
+    env: &'a HashMap<String, String>,
+    workdir: Option<&'a str>,
+}
+
+
+// 临时执行的命令：引用模板时按每台server渲染，参数错误返回422；引用脚本时脚本内容作为stdin发送；否则每台都执行command
+// 请求里的环境变量和工作目录覆盖模板里的
+async fn ad_hoc_commands(data: &web::Data<AppState>, source: AdHocSource<'_>, servers: &[ServiceTerminal]) -> Result<Vec<RemoteCommand>, actix_web::Error> {
+    validate_env(source.env).map_err(|e| actix_web::error::ErrorUnprocessableEntity(e.to_string()))?;
+    let env = Environment::new(source.env.clone(), source.workdir.map(str::to_string));
+    if let Some(script_id) = source.script_id {
+        if !source.command.trim().is_empty() || source.template_id.is_some() {
+            return Err(actix_web::error::ErrorUnprocessableEntity("script_id can not be used with command or template_id"));
+        }
+        let (command, body) = script_invocation(data, script_id, source.args).await?;
+        let command = RemoteCommand { input: Some(Arc::new(body)), env, ..RemoteCommand::new(command) };
+        return Ok(vec![command; servers.len()]);
+    }
+    let Some(template_id) = source.template_id else {
+        if source.command.trim().is_empty() {
+            return Err(actix_web::error::ErrorUnprocessableEntity("command, template_id or script_id is required"));
+        }
+        return Ok(vec![RemoteCommand { env, ..RemoteCommand::new(source.command.to_string()) }; servers.len()]);
+    };
+    let values: Map<String, Value> = source.params.clone().into_iter().collect();
+    let template = check_template_values(data, template_id, &values).await?;
+    servers.iter().map(|server| {
+        let render = || -> Result<RemoteCommand, anyhow::Error> {
+            let command = template.render(&[&values], Some(server))?;
+            let template_env = template.render_env(&[&values], Some(server))?;
+            Ok(RemoteCommand { env: template_env.merge(env.clone()), ..RemoteCommand::new(command) })
+        };
+        render().map_err(|e| actix_web::error::ErrorUnprocessableEntity(format!("{}: {}", server.ip, e)))
+    }).collect()
+}
+
+
+// 每台server实际执行的内容，按server上的设置提权，提权密码只在执行时解密
+fn remote_commands(servers: &[ServiceTerminal], commands: Vec<RemoteCommand>) -> Result<Vec<RemoteCommand>, actix_web::Error> {
+    servers.iter().zip(commands).map(|(server, command)| {
+        let privilege = host_become(server, None).map_err(|e| {
+            error!("Failed to get become password of {}: {:?}", server.ip, e);
+            actix_web::error::ErrorInternalServerError("Failed to get become password")})?;
+        Ok(RemoteCommand { privilege, ..command })
+    }).collect()
+}
@@ -0,0 +1,64 @@
+use actix_web::{HttpResponse, web};
+use log::error;
+use crate::db::pool::AppState;
+use crate::domain::workflow::{CreateCronJobDependency, would_create_cycle};
+use crate::repository::cron_job::get_cronjob_by_id_db;
+use crate::repository::workflow::*;
+
+
+pub async fn get_dependencies(data: web::Data<AppState>,job_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = data.db_pool.acquire().await.map_err(|e| {
+        error!("Failed to acquire a connection: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjob dependencies")})?;
+    let rows = get_dependencies_db(&mut conn, job_id.into_inner()).await.map_err(|e| {
+        error!("Failed to get cronjob dependencies: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjob dependencies")})?;
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+
+// 给任务添加一个上游，上游运行结束后按trigger_on触发本任务；会形成环的依赖返回422
+pub async fn add_dependency(data: web::Data<AppState>,job_id: web::Path<i32>,body: web::Json<CreateCronJobDependency>) -> Result<HttpResponse, actix_web::Error> {
+    let job_id = job_id.into_inner();
+    let params = body.into_inner();
+    for id in [job_id, params.upstream_id] {
+        get_cronjob_by_id_db(&data.db_pool, id).await.map_err(|e| {
+            error!("Failed to get a cronjob: {:?}", e);
+            actix_web::error::ErrorNotFound(format!("Cronjob {} not found", id))})?;
+    }
+    let edges = get_dependency_edges_db(&data.db_pool).await.map_err(|e| {
+        error!("Failed to get cronjob dependencies: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get cronjob dependencies")})?;
+    if would_create_cycle(&edges, job_id, params.upstream_id) {
+        return Err(actix_web::error::ErrorUnprocessableEntity(format!(
+            "cronjob {} depending on {} would create a cycle", job_id, params.upstream_id)));
+    }
+    let row = create_dependency_db(&data.db_pool, job_id, params).await.map_err(|e| {
+        error!("Failed to create a cronjob dependency: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to create a cronjob dependency")})?;
+    Ok(HttpResponse::Ok().json(row))
+}
+
+
+pub async fn delete_dependency(data: web::Data<AppState>,params: web::Path<(i32, i32)>) -> Result<HttpResponse, actix_web::Error> {
+    let (job_id, upstream_id) = params.into_inner();
+    let deleted = delete_dependency_db(&data.db_pool, job_id, upstream_id).await.map_err(|e| {
+        error!("Failed to delete a cronjob dependency: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to delete a cronjob dependency")})?;
+    if deleted == 0 {
+        return Err(actix_web::error::ErrorNotFound("Cronjob dependency not found"));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+
+// 工作流起点的运行以及它触发的所有下游运行，用于查看整个工作流的进度
+pub async fn get_workflow_runs(data: web::Data<AppState>,run_id: web::Path<i32>) -> Result<HttpResponse, actix_web::Error> {
+    let rows = get_workflow_runs_db(&data.db_pool, run_id.into_inner()).await.map_err(|e| {
+        error!("Failed to get workflow runs: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to get workflow runs")})?;
+    if rows.is_empty() {
+        return Err(actix_web::error::ErrorNotFound("Cronjob run not found"));
+    }
+    Ok(HttpResponse::Ok().json(rows))
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use crate::domain::archive::PurgeResult;
+
+
+// 仍引用这台server的未归档任务
+pub async fn get_server_cronjob_ids_db(pool: &PgPool, server_id: i32) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!(
+        "SELECT id FROM cronjobs WHERE server_id = $1 AND archived_at IS NULL ORDER BY id",
+        server_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
+
+// 仍引用这个group的未归档任务
+pub async fn get_group_cronjob_ids_db(pool: &PgPool, group_id: i32) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!(
+        "SELECT id FROM cronjobs WHERE group_id = $1 AND archived_at IS NULL ORDER BY id",
+        group_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
+
+// 彻底删除早于before删除的任务(连同日志和运行记录)、归档的server、group
+// once任务执行后自动归档的不删，保留执行记录；还有任务引用的server、group不删，避免ON DELETE CASCADE带走任务
+pub async fn purge_archived_db(pool: &PgPool, before: DateTime<Utc>) -> Result<PurgeResult, anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    let cronjobs = sqlx::query!("DELETE FROM cronjobs WHERE deleted_at < $1", before)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    let servers = sqlx::query!(
+        r#"
+        DELETE FROM servers s WHERE s.archived_at < $1
+        AND NOT EXISTS (SELECT 1 FROM cronjobs c WHERE c.server_id = s.id)
+        "#,
+        before
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    let groups = sqlx::query!(
+        r#"
+        DELETE FROM groups g WHERE g.archived_at < $1
+        AND NOT EXISTS (SELECT 1 FROM cronjobs c WHERE c.group_id = g.group_id)
+        AND NOT EXISTS (SELECT 1 FROM servers s WHERE s.group_id = g.group_id AND s.archived_at IS NULL)
+        "#,
+        before
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    tx.commit().await?;
+    Ok(PurgeResult { cronjobs, servers, groups })
+}
@@ -0,0 +1,112 @@
+use sqlx::PgPool;
+use crate::domain::calendar::*;
+use crate::domain::cron_job::{CronJob, DEFAULT_TIMEZONE};
+
+
+pub async fn get_all_calendars_db(pool: &PgPool) -> Result<Vec<Calendar>, anyhow::Error> {
+    let rows = sqlx::query_as!(Calendar, "SELECT * FROM calendars ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+
+pub async fn get_calendar_by_id_db(pool: &PgPool, id: i32) -> Result<Calendar, anyhow::Error> {
+    let row = sqlx::query_as!(Calendar, "SELECT * FROM calendars WHERE id = $1", id)
+        .fetch_one(pool)
+        .await?;
+    Ok(row)
+}
+
+
+pub async fn create_calendar_db(pool: &PgPool, params: CreateCalendar) -> Result<Calendar, anyhow::Error> {
+    let row = sqlx::query_as!(
+        Calendar,
+        "INSERT INTO calendars (name, description, timezone) VALUES ($1, $2, $3) RETURNING *",
+        params.name, params.description, params.timezone.as_deref().unwrap_or(DEFAULT_TIMEZONE)
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+// 引用它的任务和group的calendar_id置空
+pub async fn delete_calendar_db(pool: &PgPool, id: i32) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!("DELETE FROM calendars WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+
+pub async fn get_calendar_windows_db(pool: &PgPool, calendar_id: i32) -> Result<Vec<CalendarWindow>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        CalendarWindow,
+        "SELECT * FROM calendar_windows WHERE calendar_id = $1 ORDER BY id",
+        calendar_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+
+pub async fn create_calendar_window_db(pool: &PgPool, calendar_id: i32, params: CreateCalendarWindow) -> Result<CalendarWindow, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CalendarWindow,
+        r#"
+        INSERT INTO calendar_windows (calendar_id, kind, weekdays, start_time, end_time, starts_at, ends_at, description)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING *
+        "#,
+        calendar_id, params.kind.as_str(), params.weekdays.as_deref(), params.start_time, params.end_time,
+        params.starts_at, params.ends_at, params.description
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn delete_calendar_window_db(pool: &PgPool, calendar_id: i32, window_id: i32) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM calendar_windows WHERE calendar_id = $1 AND id = $2",
+        calendar_id, window_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+
+pub async fn get_calendar_rules_db(pool: &PgPool, calendar_id: i32) -> Result<CalendarRules, anyhow::Error> {
+    let calendar = get_calendar_by_id_db(pool, calendar_id).await?;
+    let windows = get_calendar_windows_db(pool, calendar_id).await?;
+    Ok(CalendarRules { name: calendar.name, timezone: calendar.timezone, windows })
+}
+
+
+// group引用的日历，group没有日历时返回None
+pub async fn get_group_calendar_rules_db(pool: &PgPool, group_id: i32) -> Result<Option<CalendarRules>, anyhow::Error> {
+    let row = sqlx::query!("SELECT calendar_id FROM groups WHERE group_id = $1", group_id)
+        .fetch_one(pool)
+        .await?;
+    match row.calendar_id {
+        Some(calendar_id) => Ok(Some(get_calendar_rules_db(pool, calendar_id).await?)),
+        None => Ok(None),
+    }
+}
+
+
+// 任务自己的日历和目标group的日历，两者都允许时才能执行
+pub async fn get_job_calendar_rules_db(pool: &PgPool, job: &CronJob) -> Result<Vec<CalendarRules>, anyhow::Error> {
+    let mut calendars = Vec::new();
+    if let Some(calendar_id) = job.calendar_id {
+        calendars.push(get_calendar_rules_db(pool, calendar_id).await?);
+    }
+    if let Some(group_id) = job.group_id {
+        calendars.extend(get_group_calendar_rules_db(pool, group_id).await?);
+    }
+    Ok(calendars)
+}
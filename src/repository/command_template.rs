@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use sqlx::PgPool;
+use sqlx::types::Json;
+use crate::domain::command_template::*;
+
+
+pub async fn get_all_command_templates_db(pool: &PgPool) -> Result<Vec<CommandTemplate>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        CommandTemplate,
+        r#"SELECT id, name, description, template, params AS "params: Json<Vec<TemplateParam>>", env AS "env: Json<HashMap<String, String>>", workdir, created_at, updated_at FROM command_templates ORDER BY id"#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+
+pub async fn get_command_template_by_id_db(pool: &PgPool, id: i32) -> Result<CommandTemplate, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CommandTemplate,
+        r#"SELECT id, name, description, template, params AS "params: Json<Vec<TemplateParam>>", env AS "env: Json<HashMap<String, String>>", workdir, created_at, updated_at FROM command_templates WHERE id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn create_command_template_db(pool: &PgPool, params: CreateCommandTemplate) -> Result<CommandTemplate, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CommandTemplate,
+        r#"
+        INSERT INTO command_templates (name, description, template, params, env, workdir)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, name, description, template, params AS "params: Json<Vec<TemplateParam>>", env AS "env: Json<HashMap<String, String>>", workdir, created_at, updated_at
+        "#,
+        params.name, params.description, params.template, Json(params.params) as _, Json(params.env) as _, params.workdir.filter(|w| !w.is_empty())
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+// 修改会影响所有引用它的任务，下一次运行时生效
+pub async fn update_command_template_db(pool: &PgPool, id: i32, params: UpdateCommandTemplate) -> Result<CommandTemplate, anyhow::Error> {
+    let this_template = get_command_template_by_id_db(pool, id).await?;
+    let name = params.name.unwrap_or(this_template.name);
+    let description = params.description.or(this_template.description);
+    let template = params.template.unwrap_or(this_template.template);
+    let template_params = params.params.unwrap_or(this_template.params.0);
+    let env = params.env.unwrap_or(this_template.env.0);
+    let workdir = match params.workdir {
+        Some(workdir) if workdir.is_empty() => None,
+        Some(workdir) => Some(workdir),
+        None => this_template.workdir,
+    };
+    let row = sqlx::query_as!(
+        CommandTemplate,
+        r#"
+        UPDATE command_templates SET name = $1, description = $2, template = $3, params = $4, env = $5, workdir = $6, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $7
+        RETURNING id, name, description, template, params AS "params: Json<Vec<TemplateParam>>", env AS "env: Json<HashMap<String, String>>", workdir, created_at, updated_at
+        "#,
+        name, description, template, Json(template_params) as _, Json(env) as _, workdir, id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn delete_command_template_db(pool: &PgPool, id: i32) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!("DELETE FROM command_templates WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+
+// 引用这个模板的任务，包括已归档的(归档的任务仍然保留外键)
+pub async fn get_template_cronjob_ids_db(pool: &PgPool, id: i32) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!("SELECT id FROM cronjobs WHERE template_id = $1 ORDER BY id", id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
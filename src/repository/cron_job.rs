@@ -1,17 +1,22 @@
-use chrono::Utc;
-use cron_parser::parse;
+use std::collections::HashMap;
+use chrono::{Duration, Utc};
+use serde_json::Value;
 use log::debug;
 use sqlx::PgPool;
-use crate::domain::cron_job::{CreateCronJob, CronJob, CronJobExecutor, UpdateCronJob};
+use crate::domain::cron_job::{CreateCronJob, CronJob, CronJobExecutor, ScheduleType, UpdateCronJob, DEFAULT_MISFIRE_GRACE_SECS, DEFAULT_MISFIRE_LIMIT, next_fire_time};
+use crate::domain::retry::{default_retry_on, DEFAULT_RETRY_DELAY_MS, DEFAULT_RETRY_MAX_DELAY_MS};
+use crate::domain::ssh_session::FailureClass;
+use crate::domain::success::{DEFAULT_MIN_SUCCESS_RATIO, DEFAULT_SUCCESS_EXIT_CODES};
+use crate::repository::cron_job_version::record_cronjob_version_db;
 use crate::repository::server::get_server_by_id_db;
 use crate::repository::servergroup::get_group_by_id_db;
-use crate::domain::scheduler::JobScheduler;
-use crate::scheduler::prepare::judge_time;
+use crate::utils::crypto::passwd_encryption;
 use tracing::info;
 
 
-pub async fn get_all_cronjobs_db(pool:&PgPool) -> Result<Vec<CronJob>, anyhow::Error>{
-    let rows = sqlx::query_as!(CronJob,"select * from cronjobs").fetch_all(pool).await?;
+// archived为true时只查已归档的任务，否则只查未归档的
+pub async fn get_all_cronjobs_db(pool:&PgPool, archived: bool) -> Result<Vec<CronJob>, anyhow::Error>{
+    let rows = sqlx::query_as!(CronJob,"select * from cronjobs where (archived_at is not null) = $1", archived).fetch_all(pool).await?;
     match rows.len(){
         0 => Err(anyhow::Error::msg("get all servers not found")),
         _ => Ok(rows)
@@ -25,33 +30,45 @@ pub async fn get_cronjob_by_id_db( pool:&PgPool, id: i32) -> Result<CronJob, any
 }
 
 
-pub async fn create_cronjob_db(pool: &PgPool, params: CreateCronJob) -> Result<CreateCronJob, anyhow::Error> {
-    let next_time = params.next_tick()?;
+// 创建和修改都会在同一事务里保存一份版本快照，changed_by记录修改人
+pub async fn create_cronjob_db(pool: &PgPool, params: CreateCronJob, changed_by: Option<String>) -> Result<CreateCronJob, anyhow::Error> {
+    let schedule_type = params.schedule_type.unwrap_or_default();
+    // dependency任务不进入调度队列，next_execute_at只是占位
+    let next_time = match schedule_type {
+        ScheduleType::Cron => params.next_tick()?,
+        ScheduleType::Once => params.run_at.ok_or_else(|| anyhow::Error::msg("run_at is required for once job"))?,
+        ScheduleType::Interval => params.run_at.unwrap_or_else(|| Utc::now() + Duration::seconds(params.interval_secs.unwrap_or_default() as i64)),
+        ScheduleType::Dependency => Utc::now(),
+    };
     match (params.server_id, params.group_id) {
         (Some(sid), Some(gid)) => {
-            get_server_by_id_db(pool, sid).await?;
-            get_group_by_id_db(pool, gid).await?;
+            ensure_server_active(pool, sid).await?;
+            ensure_group_active(pool, gid).await?;
         }
         (Some(sid), None) => {
-            get_server_by_id_db(pool, sid).await?;
+            ensure_server_active(pool, sid).await?;
         }
         (None, Some(gid)) => {
-            get_group_by_id_db(pool, gid).await?;
+            ensure_group_active(pool, gid).await?;
         }
         (None, None) => {
             return Err(anyhow::Error::msg("must provide server_id or group_id"));
         }
     }
 
+    let secret_env = encrypt_secret_env(params.secret_env.clone().unwrap_or_default())?;
     debug!("create new cronjob db");
+    let mut tx = pool.begin().await?;
     let row = sqlx::query!(
         r#"
-        INSERT INTO cronjobs (name,cron_expression,server_id,group_id,command,enabled,timeout,retry_count,description,next_execute_at)
-        VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10)
-        RETURNING id,name,cron_expression,server_id,group_id,command,enabled,timeout,retry_count,description,next_execute_at
+        INSERT INTO cronjobs (name,cron_expression,timezone,schedule_type,server_id,group_id,command,enabled,timeout,retry_count,description,next_execute_at,misfire_policy,misfire_grace_secs,misfire_limit,overlap_policy,retry_backoff,retry_delay_ms,retry_max_delay_ms,retry_on,retry_exit_codes,disable_on_failure,success_exit_codes,stdout_must_match,stdout_must_not_match,stderr_must_match,stderr_must_not_match,min_success_ratio,interval_secs,calendar_id,calendar_policy,jitter_secs,spread_secs,priority,template_id,template_params,runbook_id,playbook_id,script_id,script_args,become_method,become_user,env,secret_env,workdir)
+        VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15,$16,$17,$18,$19,$20,$21,$22,$23,$24,$25,$26,$27,$28,$29,$30,$31,$32,$33,$34,$35,$36,$37,$38,$39,$40,$41,$42,$43,$44,$45)
+        RETURNING id,name,cron_expression,timezone,schedule_type,interval_secs,server_id,group_id,command,enabled,timeout,retry_count,description,next_execute_at,misfire_policy,misfire_grace_secs,misfire_limit,overlap_policy,retry_backoff,retry_delay_ms,retry_max_delay_ms,retry_on,retry_exit_codes,disable_on_failure,success_exit_codes,stdout_must_match,stdout_must_not_match,stderr_must_match,stderr_must_not_match,min_success_ratio,calendar_id,calendar_policy,jitter_secs,spread_secs,priority,template_id,template_params,runbook_id,playbook_id,script_id,script_args,become_method,become_user,env,workdir
         "#,
         params.name.clone(),
         params.cron_expression.clone(),
+        params.get_timezone(),
+        schedule_type.as_str(),
         params.server_id,
         params.group_id,
         params.command.clone(),
@@ -59,19 +76,53 @@ pub async fn create_cronjob_db(pool: &PgPool, params: CreateCronJob) -> Result<C
         params.timeout,
         params.retry_count,
         params.description.clone(),
-        next_time
-    ).fetch_one(pool).await?;
-    if row.enabled{ // 要看enabled是否开启
-        let heap = JobScheduler::new().await?;
-        if judge_time(next_time) {// 下次执行时间 - 当前时间 < redis 的保存时间
-            heap.add_job(row.id,next_time.timestamp_millis()).await?;
-        }
-        info!("created new cronjob: {:?}", row);
-    }
+        next_time,
+        params.misfire_policy.unwrap_or_default().as_str(),
+        params.misfire_grace_secs.unwrap_or(DEFAULT_MISFIRE_GRACE_SECS),
+        params.misfire_limit.unwrap_or(DEFAULT_MISFIRE_LIMIT),
+        params.overlap_policy.unwrap_or_default().as_str(),
+        params.retry_backoff.unwrap_or_default().as_str(),
+        params.retry_delay_ms.unwrap_or(DEFAULT_RETRY_DELAY_MS),
+        params.retry_max_delay_ms.unwrap_or(DEFAULT_RETRY_MAX_DELAY_MS),
+        &params.retry_on.as_deref().map(retry_on_strings).unwrap_or_else(default_retry_on),
+        params.retry_exit_codes.as_deref(),
+        params.disable_on_failure.unwrap_or(false),
+        params.success_exit_codes.as_deref().unwrap_or(&DEFAULT_SUCCESS_EXIT_CODES[..]),
+        params.stdout_must_match.clone(),
+        params.stdout_must_not_match.clone(),
+        params.stderr_must_match.clone(),
+        params.stderr_must_not_match.clone(),
+        params.min_success_ratio.unwrap_or(DEFAULT_MIN_SUCCESS_RATIO),
+        params.interval_secs,
+        params.calendar_id,
+        params.calendar_policy.unwrap_or_default().as_str(),
+        params.jitter_secs.unwrap_or(0),
+        params.spread_secs.unwrap_or(0),
+        params.priority.unwrap_or(0),
+        params.template_id,
+        serde_json::to_value(params.template_params.clone().unwrap_or_default())?,
+        params.runbook_id,
+        params.playbook_id,
+        params.script_id,
+        params.script_args.as_deref(),
+        params.become_method.map(|m| m.as_str()),
+        params.become_user.clone(),
+        serde_json::to_value(params.env.clone().unwrap_or_default())?,
+        secret_env,
+        params.workdir.clone().filter(|w| !w.is_empty())
+    ).fetch_one(&mut *tx).await?;
+    record_cronjob_version_db(&mut tx, row.id, changed_by, None).await?;
+    tx.commit().await?;
+    // 入队交给worker：cronjobs上的触发器会NOTIFY，worker监听后直接加入队列
+    info!("created new cronjob: {:?}", row);
 
     Ok(CreateCronJob{
         name: row.name,
         cron_expression: row.cron_expression,
+        timezone: Some(row.timezone),
+        schedule_type: Some(row.schedule_type.parse()?),
+        run_at: params.run_at,
+        interval_secs: row.interval_secs,
         server_id: row.server_id,
         group_id: row.group_id,
         command: row.command,
@@ -79,16 +130,75 @@ pub async fn create_cronjob_db(pool: &PgPool, params: CreateCronJob) -> Result<C
         timeout: row.timeout,
         retry_count: row.retry_count,
         description: row.description,
+        misfire_policy: Some(row.misfire_policy.parse()?),
+        misfire_grace_secs: Some(row.misfire_grace_secs),
+        misfire_limit: Some(row.misfire_limit),
+        overlap_policy: Some(row.overlap_policy.parse()?),
+        retry_backoff: Some(row.retry_backoff.parse()?),
+        retry_delay_ms: Some(row.retry_delay_ms),
+        retry_max_delay_ms: Some(row.retry_max_delay_ms),
+        retry_on: Some(row.retry_on.iter().map(|c| c.parse()).collect::<Result<_, _>>()?),
+        retry_exit_codes: row.retry_exit_codes,
+        disable_on_failure: Some(row.disable_on_failure),
+        success_exit_codes: Some(row.success_exit_codes),
+        stdout_must_match: row.stdout_must_match,
+        stdout_must_not_match: row.stdout_must_not_match,
+        stderr_must_match: row.stderr_must_match,
+        stderr_must_not_match: row.stderr_must_not_match,
+        min_success_ratio: Some(row.min_success_ratio),
+        calendar_id: row.calendar_id,
+        calendar_policy: Some(row.calendar_policy.parse()?),
+        jitter_secs: Some(row.jitter_secs),
+        spread_secs: Some(row.spread_secs),
+        priority: Some(row.priority),
+        template_id: row.template_id,
+        template_params: Some(serde_json::from_value(row.template_params)?),
+        runbook_id: row.runbook_id,
+        playbook_id: row.playbook_id,
+        script_id: row.script_id,
+        script_args: row.script_args,
+        become_method: row.become_method.map(|m| m.parse()).transpose()?,
+        become_user: row.become_user,
+        env: Some(serde_json::from_value(row.env)?),
+        secret_env: None,
+        workdir: row.workdir,
         next_execute_at: row.next_execute_at,
     })
 }
 
+// secret环境变量的值逐个加密保存，执行时再解密
+fn encrypt_secret_env(secrets: HashMap<String, String>) -> Result<Value, anyhow::Error> {
+    let encrypted = secrets.into_iter()
+        .map(|(name, value)| Ok((name, passwd_encryption(value)?)))
+        .collect::<Result<HashMap<_, _>, anyhow::Error>>()?;
+    Ok(serde_json::to_value(encrypted)?)
+}
+
+// 任务只能引用未归档的server和group
+async fn ensure_server_active(pool: &PgPool, server_id: i32) -> Result<(), anyhow::Error> {
+    if get_server_by_id_db(pool, server_id).await?.archived_at.is_some() {
+        return Err(anyhow::anyhow!("server {} is archived", server_id));
+    }
+    Ok(())
+}
+
+async fn ensure_group_active(pool: &PgPool, group_id: i32) -> Result<(), anyhow::Error> {
+    if get_group_by_id_db(pool, group_id).await?.archived_at.is_some() {
+        return Err(anyhow::anyhow!("group {} is archived", group_id));
+    }
+    Ok(())
+}
+
+fn retry_on_strings(classes: &[FailureClass]) -> Vec<String> {
+    classes.iter().map(|c| c.as_str().to_string()).collect()
+}
+
 
 
 fn check<T>(a: Option<T>, b: Option<T>) -> Option<T> {
     a.or(b)
 }
-pub async fn update_cronjob_db(pool: &PgPool, id: i32, params: UpdateCronJob) -> Result<CronJob, anyhow::Error> {
+pub async fn update_cronjob_db(pool: &PgPool, id: i32, params: UpdateCronJob, changed_by: Option<String>) -> Result<CronJob, anyhow::Error> {
     let this_job = get_cronjob_by_id_db(pool, id).await?;
     let name = check(params.name.clone(), this_job.name.clone());
     let cron_expression = if let Some(e) = params.cron_expression {
@@ -111,45 +221,155 @@ pub async fn update_cronjob_db(pool: &PgPool, id: i32, params: UpdateCronJob) ->
     let timeout = check(params.timeout.clone(), this_job.timeout.clone());
     let retry_count = check(params.retry_count.clone(), this_job.retry_count.clone());
     let description = check(params.description.clone(), this_job.description.clone());
-    let next_execute_at = parse(&cron_expression, &Utc::now())?;
-
-    let heap = JobScheduler::new().await?;
-    // 如果更改表达式，则重新判断这条任务是否进入heap,并且需要enabled为true
-    if cron_expression != this_job.cron_expression && enabled {
-        if judge_time(next_execute_at){
-            heap.add_job(this_job.id,next_execute_at.timestamp_millis()).await?;
-        }else {
-            heap.del_job_pending(this_job.id).await?;
-            // 意义为 如果开启任务，这个分支代表了下次执行时间大于save time的任务，那么就从redis删除，等待reload进入redis
-        }
-    }else if enabled != this_job.enabled && enabled == false{ // 修改了enable且为false
-        info!("enabled changed..");
-        heap.del_job_pending(this_job.id).await?;
-    }else if enabled != this_job.enabled && enabled == true{// 修改了enable且为true
+    let misfire_policy = params.misfire_policy.map(|p| p.as_str().to_string()).unwrap_or(this_job.misfire_policy.clone());
+    let misfire_grace_secs = params.misfire_grace_secs.unwrap_or(this_job.misfire_grace_secs);
+    let misfire_limit = params.misfire_limit.unwrap_or(this_job.misfire_limit);
+    let overlap_policy = params.overlap_policy.map(|p| p.as_str().to_string()).unwrap_or(this_job.overlap_policy.clone());
+    let timezone = params.timezone.clone().unwrap_or(this_job.timezone.clone());
+    let retry_backoff = params.retry_backoff.map(|b| b.as_str().to_string()).unwrap_or(this_job.retry_backoff.clone());
+    let retry_delay_ms = params.retry_delay_ms.unwrap_or(this_job.retry_delay_ms);
+    let retry_max_delay_ms = params.retry_max_delay_ms.unwrap_or(this_job.retry_max_delay_ms);
+    let retry_on = params.retry_on.as_deref().map(retry_on_strings).unwrap_or(this_job.retry_on.clone());
+    let retry_exit_codes = check(params.retry_exit_codes.clone(), this_job.retry_exit_codes.clone());
+    let disable_on_failure = params.disable_on_failure.unwrap_or(this_job.disable_on_failure);
+    let success_exit_codes = params.success_exit_codes.clone().unwrap_or(this_job.success_exit_codes.clone());
+    let stdout_must_match = check(params.stdout_must_match.clone(), this_job.stdout_must_match.clone());
+    let stdout_must_not_match = check(params.stdout_must_not_match.clone(), this_job.stdout_must_not_match.clone());
+    let stderr_must_match = check(params.stderr_must_match.clone(), this_job.stderr_must_match.clone());
+    let stderr_must_not_match = check(params.stderr_must_not_match.clone(), this_job.stderr_must_not_match.clone());
+    let min_success_ratio = params.min_success_ratio.unwrap_or(this_job.min_success_ratio);
+    let schedule_type = params.schedule_type.map(|t| t.as_str().to_string()).unwrap_or(this_job.schedule_type.clone());
+    let interval_secs = check(params.interval_secs, this_job.interval_secs);
+    // calendar_id传0时取消日历
+    let calendar_id = match params.calendar_id {
+        Some(0) => None,
+        Some(cid) => Some(cid),
+        None => this_job.calendar_id,
+    };
+    let calendar_policy = params.calendar_policy.map(|p| p.as_str().to_string()).unwrap_or(this_job.calendar_policy.clone());
+    let jitter_secs = params.jitter_secs.unwrap_or(this_job.jitter_secs);
+    let spread_secs = params.spread_secs.unwrap_or(this_job.spread_secs);
+    let priority = params.priority.unwrap_or(this_job.priority);
+    // template_id传0时取消引用，改回直接执行command
+    let template_id = match params.template_id {
+        Some(0) => None,
+        Some(tid) => Some(tid),
+        None => this_job.template_id,
+    };
+    let runbook_id = match params.runbook_id {
+        Some(0) => None,
+        Some(rid) => Some(rid),
+        None => this_job.runbook_id,
+    };
+    let playbook_id = match params.playbook_id {
+        Some(0) => None,
+        Some(pid) => Some(pid),
+        None => this_job.playbook_id,
+    };
+    let script_id = match params.script_id {
+        Some(0) => None,
+        Some(sid) => Some(sid),
+        None => this_job.script_id,
+    };
+    let script_args = check(params.script_args.clone(), this_job.script_args.clone());
+    let become_method = match params.become_method.as_deref() {
+        Some("") => None,
+        Some(method) => Some(method.to_string()),
+        None => this_job.become_method.clone(),
+    };
+    let become_user = check(params.become_user.clone(), this_job.become_user.clone()).filter(|u| !u.is_empty());
+    let env = match params.env.clone() {
+        Some(vars) => serde_json::to_value(vars)?,
+        None => this_job.env.clone(),
+    };
+    let secret_env = match params.secret_env.clone() {
+        Some(secrets) => encrypt_secret_env(secrets)?,
+        None => this_job.secret_env.clone(),
+    };
+    let workdir = match params.workdir.as_deref() {
+        Some("") => None,
+        Some(workdir) => Some(workdir.to_string()),
+        None => this_job.workdir.clone(),
+    };
+    let template_params = match params.template_params {
+        Some(values) => serde_json::to_value(values)?,
+        None => this_job.template_params.clone(),
+    };
+    let schedule: ScheduleType = schedule_type.parse()?;
+    // 执行过后自动归档的一次性任务，给了新的run_at就恢复并重新启用；删除的任务要通过restore恢复
+    let revived = schedule == ScheduleType::Once && params.run_at.is_some() && this_job.archived_at.is_some() && this_job.deleted_at.is_none();
+    let archived_at = if revived { None } else { this_job.archived_at };
+    let enabled = enabled || revived;
+    let next_execute_at = match schedule {
+        ScheduleType::Cron => next_fire_time(&cron_expression, &timezone, Utc::now())?,
+        ScheduleType::Once => params.run_at.unwrap_or(this_job.next_execute_at),
+        ScheduleType::Interval => params.run_at.unwrap_or_else(|| Utc::now() + Duration::seconds(interval_secs.unwrap_or_default() as i64)),
+        ScheduleType::Dependency => Utc::now(),
+    };
+    if enabled != this_job.enabled {
         info!("enabled changed..");
-        if judge_time(next_execute_at){ // 代表了下次执行时间小于于save time的任务，add进入Redis
-            heap.add_job(this_job.id,next_execute_at.timestamp_millis()).await?;
-        }
     }
+    // 表达式或enabled的变化不再在这里操作Redis，UPDATE触发NOTIFY后由worker判断是否入队/出队
     match (server_id, group_id) {
         (Some(sid), Some(gid)) => {
-            get_server_by_id_db(pool, sid).await?;
-            get_group_by_id_db(pool, gid).await?;
+            ensure_server_active(pool, sid).await?;
+            ensure_group_active(pool, gid).await?;
         }
         (Some(sid), None) => {
-            get_server_by_id_db(pool, sid).await?;
+            ensure_server_active(pool, sid).await?;
         }
         (None, Some(gid)) => {
-            get_group_by_id_db(pool, gid).await?;
+            ensure_group_active(pool, gid).await?;
         }
         (None, None) => {
             return Err(anyhow::Error::msg("must provide server_id or group_id"));
         }
     }
+    let mut tx = pool.begin().await?;
+    let row = sqlx::query_as!(
+        CronJob,
+        "UPDATE cronjobs SET name=$1,cron_expression=$2,group_id=$3,server_id=$4,command=$5,enabled=$6,timeout=$7,retry_count=$8,description=$9,next_execute_at=$10,misfire_policy=$11,misfire_grace_secs=$12,misfire_limit=$13,overlap_policy=$14,timezone=$15,retry_backoff=$16,retry_delay_ms=$17,retry_max_delay_ms=$18,retry_on=$19,retry_exit_codes=$20,disable_on_failure=$21,success_exit_codes=$22,stdout_must_match=$23,stdout_must_not_match=$24,stderr_must_match=$25,stderr_must_not_match=$26,min_success_ratio=$27,schedule_type=$28,interval_secs=$29,calendar_id=$30,calendar_policy=$31,jitter_secs=$32,spread_secs=$33,priority=$34,template_id=$35,template_params=$36,runbook_id=$37,playbook_id=$38,script_id=$39,script_args=$40,become_method=$41,become_user=$42,env=$43,secret_env=$44,workdir=$45,archived_at=$46,version=version+1 WHERE id=$47 returning *",
+        name,cron_expression,group_id,server_id,command,enabled,timeout,retry_count,description,next_execute_at,misfire_policy,misfire_grace_secs,misfire_limit,overlap_policy,timezone,retry_backoff,retry_delay_ms,retry_max_delay_ms,&retry_on,retry_exit_codes.as_deref(),disable_on_failure,&success_exit_codes,stdout_must_match,stdout_must_not_match,stderr_must_match,stderr_must_not_match,min_success_ratio,schedule_type,interval_secs,calendar_id,calendar_policy,jitter_secs,spread_secs,priority,template_id,template_params,runbook_id,playbook_id,script_id,script_args.as_deref(),become_method,become_user,env,secret_env,workdir,archived_at,id
+    ).fetch_one(&mut *tx).await?;
+    record_cronjob_version_db(&mut tx, id, changed_by, None).await?;
+    tx.commit().await?;
+    Ok(row)
+}
+
+
+// 重新启用任务，下次执行时间从现在开始计算，避免把停用期间错过的轮次当作misfire补跑
+pub async fn enable_cronjob_db(pool: &PgPool, id: i32) -> Result<CronJob, anyhow::Error> {
+    let this_job = get_cronjob_by_id_db(pool, id).await?;
+    let next_execute_at = this_job.next_schedule_time(Utc::now())?.unwrap_or(this_job.next_execute_at);
+    let row = sqlx::query_as!(
+        CronJob,
+        "UPDATE cronjobs SET enabled=true,next_execute_at=$1 WHERE id=$2 returning *",
+        next_execute_at,id
+    ).fetch_one(pool).await?;
+    Ok(row)
+}
+
+
+// 删除任务只做归档，不再调度，日志、运行记录和版本历史保留，超过保留时间后清理；已经删除的返回None
+// once任务执行后的自动归档也可以删除，删除后才会被清理
+pub async fn archive_cronjob_db(pool: &PgPool, id: i32) -> Result<Option<CronJob>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CronJob,
+        "UPDATE cronjobs SET archived_at=coalesce(archived_at, now()),deleted_at=now() WHERE id=$1 AND deleted_at IS NULL returning *",
+        id
+    ).fetch_optional(pool).await?;
+    Ok(row)
+}
+
+
+// 恢复归档的任务，下次执行时间和重新启用一样从现在开始计算
+pub async fn restore_cronjob_db(pool: &PgPool, id: i32) -> Result<CronJob, anyhow::Error> {
+    let this_job = get_cronjob_by_id_db(pool, id).await?;
+    let next_execute_at = this_job.next_schedule_time(Utc::now())?.unwrap_or(this_job.next_execute_at);
     let row = sqlx::query_as!(
         CronJob,
-        "UPDATE cronjobs SET name=$1,cron_expression=$2,group_id=$3,server_id=$4,command=$5,enabled=$6,timeout=$7,retry_count=$8,description=$9,next_execute_at=$10 WHERE id=$11 returning *",
-        name,cron_expression,group_id,server_id,command,enabled,timeout,retry_count,description,next_execute_at,id
+        "UPDATE cronjobs SET archived_at=NULL,deleted_at=NULL,next_execute_at=$1 WHERE id=$2 returning *",
+        next_execute_at,id
     ).fetch_one(pool).await?;
     Ok(row)
 }
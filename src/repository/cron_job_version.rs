@@ -0,0 +1,102 @@
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+use crate::domain::cron_job::CronJob;
+use crate::domain::cron_job_version::{CronJobVersion, RUNTIME_FIELDS};
+
+
+// 保存任务当前整行的快照，和修改任务的语句放在同一个事务里
+pub async fn record_cronjob_version_db(
+    conn: &mut PgConnection,
+    job_id: i32,
+    changed_by: Option<String>,
+    change_note: Option<String>,
+) -> Result<CronJobVersion, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CronJobVersion,
+        r#"
+        INSERT INTO cronjob_versions (job_id, version, snapshot, changed_by, change_note)
+        SELECT c.id, c.version, to_jsonb(c), $2, $3 FROM cronjobs c WHERE c.id = $1
+        RETURNING *
+        "#,
+        job_id, changed_by, change_note
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(row)
+}
+
+
+// 任务当前整行的快照和版本号，与历史版本的快照格式一致，用于比较
+pub async fn get_cronjob_snapshot_db(pool: &PgPool, job_id: i32) -> Result<(i32, serde_json::Value), anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT c.version, to_jsonb(c) AS "snapshot!" FROM cronjobs c WHERE c.id = $1"#,
+        job_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok((row.version, row.snapshot))
+}
+
+
+// 最新的版本在前
+pub async fn get_cronjob_versions_db(pool: &PgPool, job_id: i32) -> Result<Vec<CronJobVersion>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        CronJobVersion,
+        "SELECT * FROM cronjob_versions WHERE job_id = $1 ORDER BY version DESC",
+        job_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+
+pub async fn get_cronjob_version_db(pool: &PgPool, job_id: i32, version: i32) -> Result<Option<CronJobVersion>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CronJobVersion,
+        "SELECT * FROM cronjob_versions WHERE job_id = $1 AND version = $2",
+        job_id, version
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+
+// 用历史版本的定义覆盖任务，生成一个新版本而不是回退版本号
+// 快照里没有的列(之后新增的)保持当前值；enabled、下次执行时间等运行状态不回滚，下次执行时间按恢复后的定义重新计算
+pub async fn restore_cronjob_version_db(
+    pool: &PgPool,
+    job_id: i32,
+    version: &CronJobVersion,
+    changed_by: Option<String>,
+) -> Result<CronJob, anyhow::Error> {
+    let runtime_fields: Vec<String> = RUNTIME_FIELDS.iter().map(|f| f.to_string()).collect();
+    let mut tx = pool.begin().await?;
+    let restored = sqlx::query_as!(
+        CronJob,
+        r#"
+        UPDATE cronjobs c SET
+            (name,cron_expression,timezone,schedule_type,interval_secs,server_id,group_id,command,timeout,retry_count,description,misfire_policy,misfire_grace_secs,misfire_limit,overlap_policy,retry_backoff,retry_delay_ms,retry_max_delay_ms,retry_on,retry_exit_codes,disable_on_failure,success_exit_codes,stdout_must_match,stdout_must_not_match,stderr_must_match,stderr_must_not_match,min_success_ratio,calendar_id,calendar_policy,jitter_secs,spread_secs,priority,template_id,template_params,runbook_id,playbook_id,script_id,script_args,become_method,become_user,env,secret_env,workdir)
+            = (SELECT s.name,s.cron_expression,s.timezone,s.schedule_type,s.interval_secs,s.server_id,s.group_id,s.command,s.timeout,s.retry_count,s.description,s.misfire_policy,s.misfire_grace_secs,s.misfire_limit,s.overlap_policy,s.retry_backoff,s.retry_delay_ms,s.retry_max_delay_ms,s.retry_on,s.retry_exit_codes,s.disable_on_failure,s.success_exit_codes,s.stdout_must_match,s.stdout_must_not_match,s.stderr_must_match,s.stderr_must_not_match,s.min_success_ratio,s.calendar_id,s.calendar_policy,s.jitter_secs,s.spread_secs,s.priority,s.template_id,s.template_params,s.runbook_id,s.playbook_id,s.script_id,s.script_args,s.become_method,s.become_user,s.env,s.secret_env,s.workdir
+               FROM jsonb_populate_record(c, $2::jsonb - $3::text[]) s),
+            version = c.version + 1
+        WHERE c.id = $1
+        RETURNING *
+        "#,
+        job_id, version.snapshot, &runtime_fields
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let next_execute_at = restored.next_schedule_time(Utc::now())?.unwrap_or(restored.next_execute_at);
+    let row = sqlx::query_as!(
+        CronJob,
+        "UPDATE cronjobs SET next_execute_at=$1 WHERE id=$2 returning *",
+        next_execute_at, job_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    record_cronjob_version_db(&mut tx, job_id, changed_by, Some(format!("restored from version {}", version.version))).await?;
+    tx.commit().await?;
+    Ok(row)
+}
@@ -0,0 +1,142 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use crate::domain::cron_run::{CronRun, RunStatus, RunTrigger};
+
+
+pub async fn create_run_db(
+    conn: &mut PgConnection,
+    job_id: i32,
+    trigger: RunTrigger,
+    status: RunStatus,
+    command: Option<String>,
+    template_params: Option<serde_json::Value>,
+    dry_run: bool,
+) -> Result<CronRun, anyhow::Error> {
+    // 定时触发的运行创建时就已经开始执行
+    let started_at = (status == RunStatus::Running).then(chrono::Utc::now);
+    let row = sqlx::query_as!(
+        CronRun,
+        r#"
+        INSERT INTO cronjob_runs (job_id, trigger_type, status, command, template_params, dry_run, started_at, job_version)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, (SELECT version FROM cronjobs WHERE id = $1))
+        RETURNING *
+        "#,
+        job_id, trigger.as_str(), status.as_str(), command, template_params, dry_run, started_at
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(row)
+}
+
+
+// 定时触发的运行，记录本该执行的时间用于统计队列延迟
+pub async fn create_scheduled_run_db(pool: &PgPool, job_id: i32, scheduled_at: DateTime<Utc>) -> Result<CronRun, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CronRun,
+        r#"
+        INSERT INTO cronjob_runs (job_id, trigger_type, status, scheduled_at, started_at, job_version)
+        VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP, (SELECT version FROM cronjobs WHERE id = $1))
+        RETURNING *
+        "#,
+        job_id, RunTrigger::Schedule.as_str(), RunStatus::Running.as_str(), scheduled_at
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+// since之后开始的定时运行，从本该执行到实际开始的毫秒数
+pub async fn get_pickup_lags_db(pool: &PgPool, since: DateTime<Utc>) -> Result<Vec<i64>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT (EXTRACT(EPOCH FROM (started_at - scheduled_at)) * 1000)::bigint AS "lag_ms!"
+        FROM cronjob_runs
+        WHERE scheduled_at IS NOT NULL AND started_at >= $1
+        "#,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.lag_ms).collect())
+}
+
+
+// 多个worker都会收到入队通知，只有把queued改成running的那个worker执行，job_version取认领时任务的版本
+pub async fn claim_run_db(pool: &PgPool, run_id: i32) -> Result<Option<CronRun>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CronRun,
+        r#"
+        UPDATE cronjob_runs SET status = $1, started_at = CURRENT_TIMESTAMP,
+            job_version = (SELECT version FROM cronjobs WHERE id = cronjob_runs.job_id)
+        WHERE run_id = $2 AND status = $3
+        RETURNING *
+        "#,
+        RunStatus::Running.as_str(), run_id, RunStatus::Queued.as_str()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row)
+}
+
+
+// worker停机时中断的运行放回queued；NOTIFY只在INSERT时触发，这里手动通知其他worker认领
+pub async fn requeue_run_db(pool: &PgPool, run_id: i32) -> Result<(), anyhow::Error> {
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query!(
+        "UPDATE cronjob_runs SET status = $1, started_at = NULL WHERE run_id = $2 AND status = $3",
+        RunStatus::Queued.as_str(), run_id, RunStatus::Running.as_str()
+    )
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() > 0 {
+        sqlx::query!("SELECT pg_notify('cronjob_runs', $1)", run_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+
+pub async fn finish_run_db(pool: &PgPool, run_id: i32, status: RunStatus, error: Option<String>) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "UPDATE cronjob_runs SET status = $1, error = $2, finished_at = CURRENT_TIMESTAMP WHERE run_id = $3",
+        status.as_str(), error, run_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+
+pub async fn get_run_by_id_db(pool: &PgPool, run_id: i32) -> Result<CronRun, anyhow::Error> {
+    let row = sqlx::query_as!(CronRun, "SELECT * FROM cronjob_runs WHERE run_id = $1", run_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(row)
+}
+
+
+pub async fn get_runs_by_job_id_db(pool: &PgPool, job_id: i32) -> Result<Vec<CronRun>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        CronRun,
+        "SELECT * FROM cronjob_runs WHERE job_id = $1 ORDER BY created_at DESC",
+        job_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+
+// 还没被认领的手动运行，用于通知丢失时的兜底
+pub async fn get_queued_run_ids_db(pool: &PgPool) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!(
+        "SELECT run_id FROM cronjob_runs WHERE status = $1 ORDER BY created_at",
+        RunStatus::Queued.as_str()
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.run_id).collect())
+}
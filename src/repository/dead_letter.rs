@@ -0,0 +1,79 @@
+use sqlx::{PgConnection, PgPool};
+use crate::domain::dead_letter::{DeadLetter, DeadLetterQuery, DeadLetterStatus};
+
+
+pub async fn create_dead_letter_db(
+    pool: &PgPool,
+    job_id: i32,
+    run_id: i32,
+    reason: String,
+    output: Option<String>,
+    job_disabled: bool,
+) -> Result<DeadLetter, anyhow::Error> {
+    let row = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        INSERT INTO cronjob_dead_letters (job_id, run_id, reason, output, job_disabled)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *
+        "#,
+        job_id, run_id, reason, output, job_disabled
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+// 按状态、任务过滤，最新的在前
+pub async fn get_dead_letters_db(pool: &PgPool, query: DeadLetterQuery) -> Result<Vec<DeadLetter>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT * FROM cronjob_dead_letters
+        WHERE ($1::varchar IS NULL OR status = $1) AND ($2::integer IS NULL OR job_id = $2)
+        ORDER BY id DESC
+        "#,
+        query.status.map(|s| s.as_str()), query.job_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+
+pub async fn get_dead_letter_by_id_db(pool: &PgPool, id: i32) -> Result<DeadLetter, anyhow::Error> {
+    let row = sqlx::query_as!(DeadLetter, "SELECT * FROM cronjob_dead_letters WHERE id = $1", id)
+        .fetch_one(pool)
+        .await?;
+    Ok(row)
+}
+
+
+// 只有open的死信可以处理，已经被处理过返回None
+pub async fn resolve_dead_letter_db(conn: &mut PgConnection, id: i32, status: DeadLetterStatus) -> Result<Option<DeadLetter>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        UPDATE cronjob_dead_letters SET status = $1, resolved_at = CURRENT_TIMESTAMP
+        WHERE id = $2 AND status = $3
+        RETURNING *
+        "#,
+        status.as_str(), id, DeadLetterStatus::Open.as_str()
+    )
+    .fetch_optional(conn)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn set_replay_run_db(conn: &mut PgConnection, id: i32, replay_run_id: i32) -> Result<DeadLetter, anyhow::Error> {
+    let row = sqlx::query_as!(
+        DeadLetter,
+        "UPDATE cronjob_dead_letters SET replay_run_id = $1 WHERE id = $2 RETURNING *",
+        replay_run_id, id
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(row)
+}
@@ -2,4 +2,14 @@ pub mod server;
 pub mod servergroup;
 pub mod cron_job;
 pub mod ssh;
-pub mod cron_log;
\ No newline at end of file
+pub mod cron_log;
+pub mod cron_run;
+pub mod workflow;
+pub mod calendar;
+pub mod dead_letter;
+pub mod cron_job_version;
+pub mod archive;
+pub mod command_template;
+pub mod runbook;
+pub mod playbook;
+pub mod script;
\ No newline at end of file
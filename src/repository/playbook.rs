@@ -0,0 +1,80 @@
+use sqlx::PgPool;
+use sqlx::types::Json;
+use crate::domain::playbook::*;
+
+
+pub async fn get_all_playbooks_db(pool: &PgPool) -> Result<Vec<Playbook>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        Playbook,
+        r#"SELECT id, name, description, steps AS "steps: Json<Vec<PlaybookStep>>", created_at, updated_at FROM playbooks ORDER BY id"#
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+
+pub async fn get_playbook_by_id_db(pool: &PgPool, id: i32) -> Result<Playbook, anyhow::Error> {
+    let row = sqlx::query_as!(
+        Playbook,
+        r#"SELECT id, name, description, steps AS "steps: Json<Vec<PlaybookStep>>", created_at, updated_at FROM playbooks WHERE id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn create_playbook_db(pool: &PgPool, params: CreatePlaybook) -> Result<Playbook, anyhow::Error> {
+    let row = sqlx::query_as!(
+        Playbook,
+        r#"
+        INSERT INTO playbooks (name, description, steps)
+        VALUES ($1, $2, $3)
+        RETURNING id, name, description, steps AS "steps: Json<Vec<PlaybookStep>>", created_at, updated_at
+        "#,
+        params.name, params.description, Json(params.steps) as _
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+// 修改对引用它的任务在下一次运行时生效
+pub async fn update_playbook_db(pool: &PgPool, id: i32, params: UpdatePlaybook) -> Result<Playbook, anyhow::Error> {
+    let this_playbook = get_playbook_by_id_db(pool, id).await?;
+    let name = params.name.unwrap_or(this_playbook.name);
+    let description = params.description.or(this_playbook.description);
+    let steps = params.steps.unwrap_or(this_playbook.steps.0);
+    let row = sqlx::query_as!(
+        Playbook,
+        r#"
+        UPDATE playbooks SET name = $1, description = $2, steps = $3, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $4
+        RETURNING id, name, description, steps AS "steps: Json<Vec<PlaybookStep>>", created_at, updated_at
+        "#,
+        name, description, Json(steps) as _, id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn delete_playbook_db(pool: &PgPool, id: i32) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!("DELETE FROM playbooks WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+
+// 执行这个剧本的任务，包括已归档的
+pub async fn get_playbook_cronjob_ids_db(pool: &PgPool, id: i32) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!("SELECT id FROM cronjobs WHERE playbook_id = $1 ORDER BY id", id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
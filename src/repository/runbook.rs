@@ -0,0 +1,98 @@
+use sqlx::PgPool;
+use crate::domain::runbook::*;
+
+
+// tag为None时查全部
+pub async fn get_all_runbooks_db(pool: &PgPool, tag: Option<String>) -> Result<Vec<Runbook>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        Runbook,
+        "SELECT * FROM runbooks WHERE $1::text IS NULL OR $1 = ANY(tags) ORDER BY name",
+        tag
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+
+pub async fn get_runbook_by_id_db(pool: &PgPool, id: i32) -> Result<Runbook, anyhow::Error> {
+    let row = sqlx::query_as!(Runbook, "SELECT * FROM runbooks WHERE id = $1", id)
+        .fetch_one(pool)
+        .await?;
+    Ok(row)
+}
+
+
+pub async fn create_runbook_db(pool: &PgPool, params: CreateRunbook) -> Result<Runbook, anyhow::Error> {
+    let row = sqlx::query_as!(
+        Runbook,
+        r#"
+        INSERT INTO runbooks (name, description, body, tags, server_id, group_id, selector)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *
+        "#,
+        params.name,
+        params.description,
+        params.body,
+        &params.tags,
+        params.server_id,
+        params.group_id,
+        serde_json::to_value(params.selector.unwrap_or_default())?
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn update_runbook_db(pool: &PgPool, id: i32, params: UpdateRunbook) -> Result<Runbook, anyhow::Error> {
+    let this_runbook = get_runbook_by_id_db(pool, id).await?;
+    let name = params.name.unwrap_or(this_runbook.name);
+    let description = params.description.or(this_runbook.description);
+    let body = params.body.unwrap_or(this_runbook.body);
+    let tags = params.tags.unwrap_or(this_runbook.tags);
+    // 传0清除默认目标
+    let server_id = match params.server_id {
+        Some(0) => None,
+        Some(sid) => Some(sid),
+        None => this_runbook.server_id,
+    };
+    let group_id = match params.group_id {
+        Some(0) => None,
+        Some(gid) => Some(gid),
+        None => this_runbook.group_id,
+    };
+    let selector = match params.selector {
+        Some(selector) => serde_json::to_value(selector)?,
+        None => this_runbook.selector,
+    };
+    let row = sqlx::query_as!(
+        Runbook,
+        r#"
+        UPDATE runbooks SET name = $1, description = $2, body = $3, tags = $4, server_id = $5, group_id = $6, selector = $7, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $8
+        RETURNING *
+        "#,
+        name, description, body, &tags, server_id, group_id, selector, id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn delete_runbook_db(pool: &PgPool, id: i32) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!("DELETE FROM runbooks WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+
+// 把这条命令当作命令的任务，包括已归档的
+pub async fn get_runbook_cronjob_ids_db(pool: &PgPool, id: i32) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!("SELECT id FROM cronjobs WHERE runbook_id = $1 ORDER BY id", id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
@@ -0,0 +1,84 @@
+use sqlx::PgPool;
+use crate::domain::script::*;
+
+
+pub async fn get_all_scripts_db(pool: &PgPool) -> Result<Vec<Script>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        Script,
+        "SELECT id, name, description, body, interpreter, args, delivery, created_at, updated_at FROM scripts ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+
+pub async fn get_script_by_id_db(pool: &PgPool, id: i32) -> Result<Script, anyhow::Error> {
+    let row = sqlx::query_as!(
+        Script,
+        "SELECT id, name, description, body, interpreter, args, delivery, created_at, updated_at FROM scripts WHERE id = $1",
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn create_script_db(pool: &PgPool, params: CreateScript) -> Result<Script, anyhow::Error> {
+    let interpreter = params.interpreter.unwrap_or_default();
+    let delivery = params.delivery.unwrap_or_default();
+    let row = sqlx::query_as!(
+        Script,
+        r#"
+        INSERT INTO scripts (name, description, body, interpreter, args, delivery)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, name, description, body, interpreter, args, delivery, created_at, updated_at
+        "#,
+        params.name, params.description, params.body, interpreter.as_str(), &params.args, delivery.as_str()
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+// 修改对引用它的任务在下一次运行时生效
+pub async fn update_script_db(pool: &PgPool, id: i32, params: UpdateScript) -> Result<Script, anyhow::Error> {
+    let this_script = get_script_by_id_db(pool, id).await?;
+    let name = params.name.unwrap_or(this_script.name);
+    let description = params.description.or(this_script.description);
+    let body = params.body.unwrap_or(this_script.body);
+    let interpreter = params.interpreter.map(|i| i.as_str().to_string()).unwrap_or(this_script.interpreter);
+    let args = params.args.unwrap_or(this_script.args);
+    let delivery = params.delivery.map(|d| d.as_str().to_string()).unwrap_or(this_script.delivery);
+    let row = sqlx::query_as!(
+        Script,
+        r#"
+        UPDATE scripts SET name = $1, description = $2, body = $3, interpreter = $4, args = $5, delivery = $6, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $7
+        RETURNING id, name, description, body, interpreter, args, delivery, created_at, updated_at
+        "#,
+        name, description, body, interpreter, &args, delivery, id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn delete_script_db(pool: &PgPool, id: i32) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!("DELETE FROM scripts WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+
+// 执行这个脚本的任务，包括已归档的
+pub async fn get_script_cronjob_ids_db(pool: &PgPool, id: i32) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!("SELECT id FROM cronjobs WHERE script_id = $1 ORDER BY id", id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
@@ -1,17 +1,21 @@
 
+use std::collections::HashMap;
 use sqlx::PgPool;
 use tracing::log::{error,info};
-use crate::domain::server::{CreateGroupServiceTerminal, CreateSingleServiceTerminal, ServiceTerminal};
+use crate::domain::archive::ArchiveResult;
+use crate::domain::server::{CreateGroupServiceTerminal, CreateSingleServiceTerminal, ServerBecome, ServerLabels, ServiceTerminal};
 use crate::utils::crypto::passwd_encryption;
 use crate::repository::servergroup::get_group_by_id_db;
 
 
 
 
-pub async fn get_all_servers_db(p0: &PgPool) ->  Result<Vec<ServiceTerminal>, anyhow::Error>{
+// archived为true时只查已归档的server，否则只查未归档的
+pub async fn get_all_servers_db(p0: &PgPool, archived: bool) ->  Result<Vec<ServiceTerminal>, anyhow::Error>{
     let rows = sqlx::query_as!(
         ServiceTerminal,
-        "select id,name,group_id,ssh_user,ip,port,password_hash as password from servers"
+        "select id,name,group_id,ssh_user,ip,port,password_hash as password,labels,archived_at,become_method,become_user,become_password_hash as become_password from servers where (archived_at is not null) = $1",
+        archived
     ).fetch_all(p0).await?;
     match rows.len(){
         0 => Err(anyhow::Error::msg("get all servers not found")),
@@ -22,22 +26,33 @@ pub async fn get_all_servers_db(p0: &PgPool) ->  Result<Vec<ServiceTerminal>, an
 pub async fn get_server_by_id_db(p0: &PgPool, id: i32) -> Result<ServiceTerminal, anyhow::Error>{
     let row = sqlx::query_as!(
         ServiceTerminal,
-        "select id,name,group_id,ssh_user,ip,port,password_hash as password from servers where id=$1",
+        "select id,name,group_id,ssh_user,ip,port,password_hash as password,labels,archived_at,become_method,become_user,become_password_hash as become_password from servers where id=$1",
         id
     ).fetch_one(p0).await?;
     Ok(row)
 }
 
+// 组内未归档的server
 pub async fn get_server_by_group_id_db(p0: &PgPool, id: i32) -> Result<Vec<ServiceTerminal>, anyhow::Error>{
     let row = sqlx::query_as!(
         ServiceTerminal,
-        "select id,name,group_id,ssh_user,ip,port,password_hash as password from servers where group_id=$1",
+        "select id,name,group_id,ssh_user,ip,port,password_hash as password,labels,archived_at,become_method,become_user,become_password_hash as become_password from servers where group_id=$1 and archived_at is null",
         id
     ).fetch_all(p0).await?;
     Ok(row)
 }
 
 
+// labels包含selector里所有键值的未归档server
+pub async fn get_servers_by_selector_db(p0: &PgPool, selector: &HashMap<String, String>) -> Result<Vec<ServiceTerminal>, anyhow::Error>{
+    let rows = sqlx::query_as!(
+        ServiceTerminal,
+        "select id,name,group_id,ssh_user,ip,port,password_hash as password,labels,archived_at,become_method,become_user,become_password_hash as become_password from servers where labels @> $1 and archived_at is null order by id",
+        serde_json::to_value(selector)?
+    ).fetch_all(p0).await?;
+    Ok(rows)
+}
+
 pub async fn create_single_server_db(p0: &PgPool, server: CreateSingleServiceTerminal) -> Result<CreateSingleServiceTerminal, anyhow::Error> {
     let ssh_user = if let Some(e) = server.ssh_user{
         e
@@ -128,7 +143,60 @@ pub async fn create_group_server_db(p0: &PgPool,server: CreateGroupServiceTermin
 }
 
 
-pub async fn delete_single_server_by_id_db(p0: &PgPool, id: i32) -> Result<String, anyhow::Error>{
-    let row = sqlx::query!("delete from servers where id=$1",id).execute(p0).await?;
-    Ok(format!("Successfully deleted {:?} group", row))
-}
\ No newline at end of file
+// 删除只做归档，force时仍引用这台server的任务一起归档，任务的日志和运行记录保留
+pub async fn archive_server_db(p0: &PgPool, id: i32) -> Result<ArchiveResult, anyhow::Error> {
+    let mut tx = p0.begin().await?;
+    let jobs = sqlx::query!(
+        "UPDATE cronjobs SET archived_at = now() WHERE server_id = $1 AND archived_at IS NULL RETURNING id",
+        id
+    ).fetch_all(&mut *tx).await?;
+    let row = sqlx::query!(
+        r#"UPDATE servers SET archived_at = now() WHERE id = $1 RETURNING archived_at AS "archived_at!""#,
+        id
+    ).fetch_one(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(ArchiveResult {
+        archived_at: row.archived_at,
+        archived_cronjob_ids: jobs.into_iter().map(|job| job.id).collect(),
+    })
+}
+
+
+// 恢复归档的server，一起归档的任务需要单独恢复
+pub async fn restore_server_db(p0: &PgPool, id: i32) -> Result<ServiceTerminal, anyhow::Error> {
+    let row = sqlx::query_as!(
+        ServiceTerminal,
+        "update servers set archived_at = null where id=$1 returning id,name,group_id,ssh_user,ip,port,password_hash as password,labels,archived_at,become_method,become_user,become_password_hash as become_password",
+        id
+    ).fetch_one(p0).await?;
+    Ok(row)
+}
+
+
+// 整体替换标签
+pub async fn update_server_labels_db(p0: &PgPool, id: i32, labels: ServerLabels) -> Result<ServiceTerminal, anyhow::Error> {
+    let row = sqlx::query_as!(
+        ServiceTerminal,
+        "update servers set labels = $1 where id=$2 returning id,name,group_id,ssh_user,ip,port,password_hash as password,labels,archived_at,become_method,become_user,become_password_hash as become_password",
+        serde_json::to_value(labels.labels)?, id
+    ).fetch_one(p0).await?;
+    Ok(row)
+}
+
+
+// 设置提权方式，method为空时三列都清空；密码加密后保存
+pub async fn update_server_become_db(p0: &PgPool, id: i32, privilege: ServerBecome) -> Result<ServiceTerminal, anyhow::Error> {
+    let (method, user, password) = match privilege.method {
+        Some(method) => {
+            let password = privilege.password.filter(|p| !p.is_empty()).map(passwd_encryption).transpose()?;
+            (Some(method.as_str()), privilege.user.filter(|u| !u.is_empty()), password)
+        }
+        None => (None, None, None),
+    };
+    let row = sqlx::query_as!(
+        ServiceTerminal,
+        "update servers set become_method = $1, become_user = $2, become_password_hash = $3 where id=$4 returning id,name,group_id,ssh_user,ip,port,password_hash as password,labels,archived_at,become_method,become_user,become_password_hash as become_password",
+        method, user, password, id
+    ).fetch_one(p0).await?;
+    Ok(row)
+}
@@ -1,10 +1,12 @@
 use sqlx::PgPool;
 
+use crate::domain::archive::ArchiveResult;
 use crate::domain::servergroup::*;
 
-pub async fn get_all_groups_db(p0: &PgPool) -> Result<Vec<Group>,anyhow::Error>{
+// archived为true时只查已归档的group，否则只查未归档的
+pub async fn get_all_groups_db(p0: &PgPool, archived: bool) -> Result<Vec<Group>,anyhow::Error>{
     let rows = sqlx::query_as!(
-        Group,"select group_id,name,description from groups").fetch_all(p0).await?;
+        Group,"select group_id,name,description,calendar_id,max_concurrency,archived_at from groups where (archived_at is not null) = $1", archived).fetch_all(p0).await?;
     match rows.len(){
         0 => Err(anyhow::Error::msg("get all servers not found")),
         _ => Ok(rows)
@@ -18,7 +20,7 @@ pub async fn get_group_by_id_db(
     let row = sqlx::query_as!(
         Group,
         r#"
-        SELECT group_id,name,description FROM groups WHERE group_id = $1
+        SELECT group_id,name,description,calendar_id,max_concurrency,archived_at FROM groups WHERE group_id = $1
         "#,
         id
     ).fetch_one(pool).await?;
@@ -33,12 +35,14 @@ pub async fn create_group_db(
     let row = sqlx::query_as!(
         Group,
         r#"
-        INSERT INTO groups (name, description)
-        VALUES ($1, $2)
-        RETURNING group_id, name, description
+        INSERT INTO groups (name, description, calendar_id, max_concurrency)
+        VALUES ($1, $2, $3, $4)
+        RETURNING group_id, name, description, calendar_id, max_concurrency, archived_at
         "#,
         group.name,
-        group.description
+        group.description,
+        group.calendar_id,
+        group.max_concurrency.filter(|limit| *limit > 0)
     )
         .fetch_one(pool)
         .await?;
@@ -46,9 +50,32 @@ pub async fn create_group_db(
 }
 
 
-pub async fn delete_group_by_id_db(p0: &PgPool,id: i32) -> Result<String, anyhow::Error>{
-    let row = sqlx::query!("delete from groups where group_id=$1",id).execute(p0).await?;
-    Ok(format!("Successfully deleted {:?} group", row))
+// 删除只做归档，force时引用这个group的任务一起归档
+pub async fn archive_group_db(p0: &PgPool, id: i32) -> Result<ArchiveResult, anyhow::Error> {
+    let mut tx = p0.begin().await?;
+    let jobs = sqlx::query!(
+        "UPDATE cronjobs SET archived_at = now() WHERE group_id = $1 AND archived_at IS NULL RETURNING id",
+        id
+    ).fetch_all(&mut *tx).await?;
+    let row = sqlx::query!(
+        r#"UPDATE groups SET archived_at = now() WHERE group_id = $1 RETURNING archived_at AS "archived_at!""#,
+        id
+    ).fetch_one(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(ArchiveResult {
+        archived_at: row.archived_at,
+        archived_cronjob_ids: jobs.into_iter().map(|job| job.id).collect(),
+    })
+}
+
+
+pub async fn restore_group_db(p0: &PgPool, id: i32) -> Result<Group, anyhow::Error> {
+    let row = sqlx::query_as!(
+        Group,
+        "update groups set archived_at = null where group_id = $1 returning group_id,name,description,calendar_id,max_concurrency,archived_at",
+        id
+    ).fetch_one(p0).await?;
+    Ok(row)
 }
 
 
@@ -59,11 +86,23 @@ pub async fn update_group_by_id_db(p0: &PgPool,id: i32,newgroup: UpdateGroup) ->
 
     // 这个地方肯定有bug，如果传入None那么这个None是否是需要更新的值，如果原来是Some，则我不想修改，默认会传入None，那么就把原来的值改了
     let description = newgroup.description.or(base_group.description);
+    // 传0取消日历
+    let calendar_id = match newgroup.calendar_id {
+        Some(0) => None,
+        Some(cid) => Some(cid),
+        None => base_group.calendar_id,
+    };
+    // 传0取消上限
+    let max_concurrency = match newgroup.max_concurrency {
+        Some(0) => None,
+        Some(limit) => Some(limit),
+        None => base_group.max_concurrency,
+    };
 
     let row = sqlx::query_as!(
         Group,
-        "update groups set name = $1,description= $2 where group_id = $3 returning group_id,name,description",
-        name,description,id
+        "update groups set name = $1,description= $2,calendar_id = $3,max_concurrency = $4 where group_id = $5 returning group_id,name,description,calendar_id,max_concurrency,archived_at",
+        name,description,calendar_id,max_concurrency,id
     ).fetch_one(p0).await?;
 
     Ok(row)
@@ -1,5 +1,5 @@
 use std::sync::Arc;
-use crate::{domain::{cron_log::CreateCronLog, ssh_configuration::{Client, Message, Session}}};
+use crate::{domain::{cron_log::CreateCronLog, cron_run::LogTarget, ssh_configuration::{Client, Message, Session}}};
 use crate::domain::ssh_session::*;
 use actix_web::error::ErrorInternalServerError;
 use tracing::error;
@@ -12,12 +12,20 @@ use std::env;
 use bytes::Bytes;
 use crate::repository::cron_log::create_cron_log_db;
 use russh::client::AuthResult;
+use futures::future::join_all;
+use crate::domain::server::ServiceTerminal;
+use crate::domain::playbook::{HostPlaybookResult, PlaybookStep, StepResult, StepStatus};
+use crate::domain::environment::Environment;
+use crate::domain::privilege::{Become, BecomeSpec};
+use std::collections::HashMap;
+use std::time::Instant;
 
 macro_rules! log_and_record {
-    ($job_id:expr, $pool:expr, $server_ip:expr, $status:expr, $message:expr) => {
-        if let Some(id) = $job_id {
+    ($target:expr, $pool:expr, $server_ip:expr, $status:expr, $message:expr) => {
+        if let Some(target) = $target {
             let job_log = CreateCronLog::new(
-                id,
+                target.job_id,
+                target.run_id,
                 $server_ip.to_string(),
                 $status.to_string(),
                 Some($message.to_string())
@@ -62,22 +70,22 @@ pub async fn test_connect_back(msg: Message) -> Result<String, actix_web::Error>
 
 
 
-pub async fn single_server_ssh_back(job_id: Option<i32>,pool:&PgPool,msg: Message,command: String) -> Result<(u32,String), actix_web::Error> {
+pub async fn single_server_ssh_back(target: Option<LogTarget>,pool:&PgPool,msg: Message,command: RemoteCommand) -> Result<(u32,String), actix_web::Error> {
     let ip = msg.ipaddr.unwrap_or("".to_string());
     let ip_port = format!("{}:{}",ip,msg.port);
     info!("connect to {}",ip_port);
     let password = passwd_decrypt(msg.password.clone())
         .map_err(|e| ErrorInternalServerError(format!("Password decryption failed: {}", e)))?;
     let config = Arc::new(russh::client::Config::default());
-    let command = Arc::new(command.clone());
-    let (code,output) = ssh_execute(job_id, pool, config, ip_port, msg.user, password, command)
+    let output = ssh_execute(target, pool, config, ip_port, msg.user, password, command)
     .await.map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    Ok((code, output))
+    Ok((output.exit_code, output.stdout))
 }
 
 
 
-pub async fn batch_server_ssh_back(job_id: Option<i32>,pool: &PgPool,msg: Message,command: String) -> Result<tokio::sync::mpsc::Receiver<Result<Bytes, std::io::Error>>, actix_web::Error> {
+// commands与server_list一一对应
+pub async fn batch_server_ssh_back(target: Option<LogTarget>,pool: &PgPool,msg: Message,commands: Vec<RemoteCommand>) -> Result<tokio::sync::mpsc::Receiver<Result<Bytes, std::io::Error>>, actix_web::Error> {
     let server_list = msg.server_list.unwrap_or(Vec::new());
     // 异步
     let buffer_size = env::var("CNOK_CHANNEL_BUFFER")
@@ -87,34 +95,39 @@ pub async fn batch_server_ssh_back(job_id: Option<i32>,pool: &PgPool,msg: Messag
     info!("channel buffer is {}",buffer_size);
     let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(buffer_size);
     let config = Arc::new(russh::client::Config::default());
-    let command = Arc::new(command.clone());
-    for server in server_list{
+    for (server, command) in server_list.into_iter().zip(commands){
             let tx = tx.clone();
             let server_label = server.clone();
             let config = Arc::clone(&config);
-            let command = Arc::clone(&command);
             let ip_port = format!("{}:{}",server,msg.port);
             let user = msg.user.clone();
             let password = msg.password.clone();
             let pool_new = pool.clone();
             
         tokio::spawn(async move{
-            let result = ssh_execute(
-                job_id,
-                &pool_new,
-                config, 
-                ip_port.clone(), 
-                user, 
-                password, 
-                command  // 直接传递 Arc<String>
-            ).await;
+            // 接收端被drop(任务被取消或客户端断开)时，中止这台server上的执行
+            let result = tokio::select! {
+                result = ssh_execute(
+                    target,
+                    &pool_new,
+                    config, 
+                    ip_port.clone(), 
+                    user, 
+                    password, 
+                    command
+                ) => result,
+                _ = tx.closed() => {
+                    warn!("batch server: {} cancelled", server_label);
+                    return;
+                }
+            };
             
             let final_json = match result {
-                Ok((_code,output)) => {
+                Ok(output) => {
                     let ssh_result = SshResult {
                         server: server_label.clone(),
-                        output,
-                        exit_code: Some(0),
+                        output: output.stdout,
+                        exit_code: Some(output.exit_code),
                     };
                     let back = serde_json::to_string(&ssh_result).unwrap_or_else(|_| {
                          format!(r#"{{"server":"{}","error":"JSON serialization failed"}}"#, server_label)
@@ -152,16 +165,190 @@ pub async fn batch_server_ssh_back(job_id: Option<i32>,pool: &PgPool,msg: Messag
 
 }
 
+// 任务执行用：在一组server上并发执行，按server返回结构化结果，每台server使用自己的账号和端口
+// commands与servers一一对应(模板按主机渲染后各不相同)；delays为每台server的启动延迟(jitter/spread)，缺省的不延迟；返回顺序与servers一致
+// 命令带着各自的stdin输入和环境变量；privilege为任务上的提权方式，没有时使用每台server自己的
+pub async fn servers_ssh_back(target: Option<LogTarget>,pool: &PgPool,servers: Vec<ServiceTerminal>,delays: Vec<Duration>,commands: Vec<RemoteCommand>,privilege: Option<BecomeSpec>) -> Vec<HostResult> {
+    let config = Arc::new(russh::client::Config::default());
+    let mut delays = delays.into_iter();
+    let tasks = servers.into_iter().zip(commands).map(|(server, command)| {
+        let config = Arc::clone(&config);
+        let privilege = privilege.as_ref();
+        let delay = delays.next().unwrap_or_default();
+        async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            let ip_port = format!("{}:{}",server.ip,server.port);
+            let privilege = host_become(&server, privilege);
+            let result = match (passwd_decrypt(server.password), privilege) {
+                (Ok(password), Ok(privilege)) => {
+                    let command = RemoteCommand { privilege, ..command };
+                    ssh_execute(target, pool, config, ip_port, server.ssh_user, password, command).await
+                }
+                (Err(e), _) | (_, Err(e)) => Err(SshFailure::new(FailureClass::Auth, format!("{} Password decryption failed: {}",ip_port, e))),
+            };
+            HostResult { server: server.ip, result }
+        }
+    });
+    join_all(tasks).await
+}
+
+// 剧本：每台server只建立一次连接，所有步骤在这个连接上依次执行，返回顺序与servers一致
+// privilege为任务上的提权方式，每个步骤都提权执行，没有时使用每台server自己的；env对每个步骤都生效
+pub async fn servers_playbook_back(target: Option<LogTarget>,pool: &PgPool,servers: Vec<ServiceTerminal>,delays: Vec<Duration>,steps: &[PlaybookStep],privilege: Option<BecomeSpec>,env: Environment) -> Vec<HostPlaybookResult> {
+    let config = Arc::new(russh::client::Config::default());
+    let mut delays = delays.into_iter();
+    let tasks = servers.into_iter().map(|server| {
+        let config = Arc::clone(&config);
+        let privilege = privilege.as_ref();
+        let env = &env;
+        let delay = delays.next().unwrap_or_default();
+        async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            playbook_execute(target, pool, config, server, steps, privilege, env).await
+        }
+    });
+    join_all(tasks).await
+}
+
+// 依次执行步骤，失败(未设置continue_on_error)或条件不满足后剩下的步骤跳过
+async fn playbook_execute(
+    target: Option<LogTarget>,
+    pool: &PgPool,
+    config: Arc<russh::client::Config>,
+    server: ServiceTerminal,
+    steps: &[PlaybookStep],
+    privilege: Option<&BecomeSpec>,
+    env: &Environment,
+) -> HostPlaybookResult {
+    let ip_port = format!("{}:{}",server.ip,server.port);
+    let privilege = host_become(&server, privilege);
+    let connected = match (passwd_decrypt(server.password), privilege) {
+        (Ok(password), Ok(privilege)) => ssh_connect(target, pool, config, &ip_port, server.ssh_user, password).await.map(|ssh| (ssh, privilege)),
+        (Err(e), _) | (_, Err(e)) => Err(SshFailure::new(FailureClass::Auth, format!("{} Password decryption failed: {}",ip_port, e))),
+    };
+    let (mut ssh, privilege) = match connected {
+        Ok(connected) => connected,
+        Err(failure) => return HostPlaybookResult::connect_failed(server.ip, failure),
+    };
+    let mut vars = HashMap::new();
+    let mut results = Vec::with_capacity(steps.len());
+    let mut stopped = false;
+    for step in steps {
+        if stopped {
+            results.push(StepResult::skipped(step));
+            continue;
+        }
+        let started = Instant::now();
+        let outcome = match step.command(&vars) {
+            Ok(command) => {
+                let input = command.input.as_deref().map(str::as_bytes);
+                match timeout(step.timeout(), ssh.exec_as(&command.command, input, privilege.as_ref(), Some(env))).await {
+                    Ok(Ok(output)) => Ok(output),
+                    Ok(Err(e)) => Err(SshFailure::new(FailureClass::Connect, format!("execution failed: {}", e))),
+                    Err(_) => Err(SshFailure::new(FailureClass::Timeout, format!("timeout after {}s", step.timeout().as_secs()))),
+                }
+            }
+            // 变量替换失败，命令没有发出去
+            Err(e) => Err(SshFailure::new(FailureClass::Connect, e.to_string())),
+        };
+        let result = step.finish(outcome, started.elapsed().as_millis() as u64, &mut vars);
+        let status = result.exit_code.map(|code| code.to_string()).unwrap_or_else(|| "ERROR".to_string());
+        let message = match &result.error {
+            Some(error) => format!("[{}] {}", step.name, error),
+            None if result.stderr.is_empty() => format!("[{}]\n{}", step.name, result.stdout),
+            None => format!("[{}]\n{}\n[stderr]\n{}", step.name, result.stdout, result.stderr),
+        };
+        log_and_record!(target, pool, ip_port, &status, &message);
+        stopped = result.stops();
+        results.push(result);
+    }
+    if let Err(e) = ssh.close().await {
+        warn!("{} Failed to close connection: {}", ip_port, e);
+    }
+    let success = !results.iter().any(|r| r.status == StepStatus::Failed);
+    HostPlaybookResult { server: server.ip, success, error: None, steps: results, variables: vars, failure: None }
+}
+
 // 防止batch server ssh handler中tokio spawn中的嵌套，所以单独拿出来这部分，后续加密钥认证方便改
 async fn ssh_execute(
-    job_id: Option<i32>,
+    target: Option<LogTarget>,
     pool: &PgPool,
     config: Arc<russh::client::Config>,
     ip_port: String,
     user: String,
     password: String,
-    command: Arc<String>
-) -> Result<(u32,String), String> {
+    command: RemoteCommand
+) -> Result<CommandOutput, SshFailure> {
+    let mut ssh = ssh_connect(target, pool, config, &ip_port, user, password).await?;
+
+    // let (_code, output) = timeout(
+    //     COMMAND_TIMEOUT,
+    //     ssh.call(command.as_str())
+    // )
+    // .await
+    // .map_err(|_| "Command execution timeout".to_string())?
+    // .map_err(|e| format!("Command execution failed: {}", e))?;
+    let input = command.input.as_deref().map(|input| input.as_bytes());
+    let output = match timeout(COMMAND_TIMEOUT, ssh.exec_as(&command.command, input, command.privilege.as_ref(), Some(&command.env))).await {
+        Ok(Ok(output)) => {
+            let logged = if output.stderr.is_empty() {
+                output.stdout.clone()
+            } else {
+                format!("{}\n[stderr]\n{}", output.stdout, output.stderr)
+            };
+            log_and_record!(
+                target,
+                pool,
+                ip_port,
+                &output.exit_code.to_string(),
+                &logged
+            );
+            output
+        }
+        Ok(Err(e)) => {
+            let msg = format!("{} Command execution failed: {}",ip_port, e);
+            log_and_record!(target, pool, ip_port,"ERROR", &msg);
+            error!("{}", msg);
+            return Err(SshFailure::new(FailureClass::Connect, msg));
+        }
+        Err(_) => {
+            let msg = format!("{} Command execution timeout",ip_port.clone());
+            log_and_record!(target, pool, ip_port,"ERROR", &msg);
+            error!("{}", msg);
+            return Err(SshFailure::new(FailureClass::Timeout, msg));
+        }
+    };
+    // let (code,output) = ssh.call(&body.command).await.map_err(|e| ErrorInternalServerError(e))?;
+    const MAX_OUTPUT_SIZE: usize = 1 * 1024 * 1024; // 1MB
+
+    // 检查输出大小
+    let output_size = output.stdout.len() + output.stderr.len();
+    if output_size > MAX_OUTPUT_SIZE {
+        return Err(SshFailure { class: FailureClass::Output, exit_code: Some(output.exit_code), message: format!(
+            "Output exceeds {}MB limit (actual: {}MB)", 
+            MAX_OUTPUT_SIZE / 1024 / 1024,
+            output_size / 1024 / 1024
+        )});
+    }
+
+    ssh.close().await.map_err(|e| SshFailure::new(FailureClass::Connect, format!("Failed to close connection: {}", e)))?;
+    
+    Ok(output)
+}
+
+// 建立连接并完成认证，失败时记录日志；剧本的多个步骤复用同一个连接
+async fn ssh_connect(
+    target: Option<LogTarget>,
+    pool: &PgPool,
+    config: Arc<russh::client::Config>,
+    ip_port: &str,
+    user: String,
+    password: String,
+) -> Result<Session, SshFailure> {
     // let ip_port_clone = ip_port.clone();
     // let mut connect: russh::client::Handle<Client> = timeout(
     //     CONNECTION_TIMEOUT,
@@ -171,22 +358,22 @@ async fn ssh_execute(
     // .map_err(|_| format!("Connection timeout to {}", ip_port_clone))?
     // .map_err(|e| format!("Connection failed: {}", e))?;
     let mut connect: russh::client::Handle<Client> = 
-    match timeout(CONNECTION_TIMEOUT, russh::client::connect(config, ip_port.clone(), Client)).await {
+    match timeout(CONNECTION_TIMEOUT, russh::client::connect(config, ip_port.to_string(), Client)).await {
         Ok(Ok(handle)) => {
-            log_and_record!(job_id, pool,ip_port,"INFO", format!("Connection success to {}",ip_port));
+            log_and_record!(target, pool,ip_port,"INFO", format!("Connection success to {}",ip_port));
             handle
         }
         Ok(Err(e)) => {
             let msg = format!("Connection failed for {}: {}",ip_port, e);
-            log_and_record!(job_id, pool,ip_port,"ERROR", &msg);
+            log_and_record!(target, pool,ip_port,"ERROR", &msg);
             error!("{}", msg);
-            return Err(msg.into());
+            return Err(SshFailure::new(FailureClass::Connect, msg));
         }
         Err(_) => {
             let msg = format!("Connection timeout to {}", ip_port);
-            log_and_record!(job_id, pool, ip_port,"ERROR", &msg);
+            log_and_record!(target, pool, ip_port,"ERROR", &msg);
             error!("{}", msg);
-            return Err(msg.into());
+            return Err(SshFailure::new(FailureClass::Timeout, msg));
         }
     };
 
@@ -204,75 +391,49 @@ async fn ssh_execute(
 // 2. 认证
     match timeout(AUTH_TIMEOUT, connect.authenticate_password(user.clone(), password)).await {
         Ok(Ok(AuthResult::Success)) => {
-            log_and_record!(job_id, pool, ip_port,"INFO", format!("{} Authentication success",ip_port));
+            log_and_record!(target, pool, ip_port,"INFO", format!("{} Authentication success",ip_port));
             info!("Authenticated for user {}", user);
         }
         Ok(Err(e)) => {
             let msg = format!("{} Authentication error: {}",ip_port, e);
-            log_and_record!(job_id, pool, ip_port,"ERROR", &msg);
+            log_and_record!(target, pool, ip_port,"ERROR", &msg);
+            error!("{}", msg);
+            return Err(SshFailure::new(FailureClass::Auth, msg));
+        }
+        Ok(Ok(_)) => {
+            let msg = format!("{} Authentication rejected for user {}",ip_port, user);
+            log_and_record!(target, pool, ip_port,"ERROR", &msg);
             error!("{}", msg);
-            return Err(msg.into());
+            return Err(SshFailure::new(FailureClass::Auth, msg));
         }
-        _ => {
+        Err(_) => {
             let msg = format!("{} Authentication timeout for user {}",ip_port, user);
-            log_and_record!(job_id, pool, ip_port,"ERROR", &msg);
+            log_and_record!(target, pool, ip_port,"ERROR", &msg);
             error!("{}", msg);
-            return Err(msg.into());
+            return Err(SshFailure::new(FailureClass::Timeout, msg));
         }
     }
 
 
 
     info!("Connected to the server");
-    let mut ssh = Session{
+    let ssh = Session{
         session: connect,
     };
     info!("Authentication complete");
+    Ok(ssh)
+}
 
-    // let (_code, output) = timeout(
-    //     COMMAND_TIMEOUT,
-    //     ssh.call(command.as_str())
-    // )
-    // .await
-    // .map_err(|_| "Command execution timeout".to_string())?
-    // .map_err(|e| format!("Command execution failed: {}", e))?;
-    let (code, output) = match timeout(COMMAND_TIMEOUT, ssh.call(command.as_str())).await {
-        Ok(Ok((code, output))) => {
-            log_and_record!(
-                job_id,
-                pool,
-                ip_port,
-                &code.to_string(),
-                &output
-            );
-            (code, output)
-        }
-        Ok(Err(e)) => {
-            let msg = format!("{} Command execution failed: {}",ip_port, e);
-            log_and_record!(job_id, pool, ip_port,"ERROR", &msg);
-            error!("{}", msg);
-            (1, String::new())
-        }
-        Err(_) => {
-            let msg = format!("{} Command execution timeout",ip_port.clone());
-            log_and_record!(job_id, pool, ip_port,"ERROR", &msg);
-            error!("{}", msg);
-            (1, String::new())
-        }
-    };
-    // let (code,output) = ssh.call(&body.command).await.map_err(|e| ErrorInternalServerError(e))?;
-    const MAX_OUTPUT_SIZE: usize = 1 * 1024 * 1024; // 1MB
-
-    // 检查输出大小
-    if output.len() > MAX_OUTPUT_SIZE {
-        return Err(format!(
-            "Output exceeds {}MB limit (actual: {}MB)", 
-            MAX_OUTPUT_SIZE / 1024 / 1024,
-            output.len() / 1024 / 1024
-        ));
-    }
 
-    ssh.close().await.map_err(|e| format!("Failed to close connection: {}", e))?;
-    
-    Ok((code,output))
-}
\ No newline at end of file
+// 这台server实际使用的提权方式和解密后的密码：任务上的设置优先；没有单独的提权密码时使用登录密码
+pub fn host_become(server: &ServiceTerminal, job: Option<&BecomeSpec>) -> Result<Option<Become>, anyhow::Error> {
+    let spec = match job {
+        Some(spec) => spec.clone(),
+        None => match BecomeSpec::from_columns(server.become_method.as_deref(), server.become_user.as_deref())? {
+            Some(spec) => spec,
+            None => return Ok(None),
+        },
+    };
+    let password = passwd_decrypt(server.become_password.clone().unwrap_or_else(|| server.password.clone()))?;
+    Ok(Some(Become { spec, password }))
+}
@@ -0,0 +1,117 @@
+use sqlx::{PgConnection, PgPool};
+use crate::domain::cron_run::{CronRun, RunStatus, RunTrigger};
+use crate::domain::workflow::{CreateCronJobDependency, CronJobDependency};
+
+
+// job_id 的所有上游，触发下游时在持有工作流锁的事务里查询
+pub async fn get_dependencies_db(conn: &mut PgConnection, job_id: i32) -> Result<Vec<CronJobDependency>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        CronJobDependency,
+        "SELECT job_id, upstream_id, trigger_on, created_at FROM cronjob_dependencies WHERE job_id = $1 ORDER BY upstream_id",
+        job_id
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+// 依赖 upstream_id 的所有未归档下游
+pub async fn get_downstream_ids_db(pool: &PgPool, upstream_id: i32) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!(
+        "SELECT d.job_id FROM cronjob_dependencies d JOIN cronjobs c ON c.id = d.job_id WHERE d.upstream_id = $1 AND c.archived_at IS NULL ORDER BY d.job_id",
+        upstream_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.job_id).collect())
+}
+
+
+// 所有依赖边 (job_id, upstream_id)，用于新增依赖时检查环
+pub async fn get_dependency_edges_db(pool: &PgPool) -> Result<Vec<(i32, i32)>, anyhow::Error> {
+    let rows = sqlx::query!("SELECT job_id, upstream_id FROM cronjob_dependencies")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(|row| (row.job_id, row.upstream_id)).collect())
+}
+
+
+// 已存在时更新触发条件
+pub async fn create_dependency_db(pool: &PgPool, job_id: i32, params: CreateCronJobDependency) -> Result<CronJobDependency, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CronJobDependency,
+        r#"
+        INSERT INTO cronjob_dependencies (job_id, upstream_id, trigger_on)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (job_id, upstream_id) DO UPDATE SET trigger_on = EXCLUDED.trigger_on
+        RETURNING job_id, upstream_id, trigger_on, created_at
+        "#,
+        job_id, params.upstream_id, params.trigger_on.unwrap_or_default().as_str()
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row)
+}
+
+
+pub async fn delete_dependency_db(pool: &PgPool, job_id: i32, upstream_id: i32) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM cronjob_dependencies WHERE job_id = $1 AND upstream_id = $2",
+        job_id, upstream_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+
+// 任务在工作流中最近一次运行的状态，工作流起点本身的 workflow_run_id 为空，用 run_id 匹配
+pub async fn get_workflow_job_status_db(conn: &mut PgConnection, workflow_run_id: i32, job_id: i32) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT status FROM cronjob_runs
+        WHERE job_id = $1 AND (run_id = $2 OR workflow_run_id = $2)
+        ORDER BY run_id DESC LIMIT 1
+        "#,
+        job_id, workflow_run_id
+    )
+    .fetch_optional(conn)
+    .await?;
+    Ok(row.map(|row| row.status))
+}
+
+
+// 工作流中每个任务只触发一次，已经触发过返回None
+pub async fn create_downstream_run_db(
+    conn: &mut PgConnection,
+    job_id: i32,
+    workflow_run_id: i32,
+    upstream_run_id: i32,
+    dry_run: bool,
+) -> Result<Option<CronRun>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        CronRun,
+        r#"
+        INSERT INTO cronjob_runs (job_id, trigger_type, status, dry_run, workflow_run_id, upstream_run_id)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (workflow_run_id, job_id) WHERE workflow_run_id IS NOT NULL DO NOTHING
+        RETURNING *
+        "#,
+        job_id, RunTrigger::Dependency.as_str(), RunStatus::Queued.as_str(), dry_run, workflow_run_id, upstream_run_id
+    )
+    .fetch_optional(conn)
+    .await?;
+    Ok(row)
+}
+
+
+// 工作流起点和它触发的所有运行
+pub async fn get_workflow_runs_db(pool: &PgPool, workflow_run_id: i32) -> Result<Vec<CronRun>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        CronRun,
+        "SELECT * FROM cronjob_runs WHERE run_id = $1 OR workflow_run_id = $1 ORDER BY run_id",
+        workflow_run_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
@@ -0,0 +1,70 @@
+use log::{info, warn, error};
+use sqlx::PgPool;
+use sqlx::postgres::PgListener;
+use crate::domain::cron_job::{CronJobChange, CronJobOp};
+use crate::domain::queue::JobMeta;
+use crate::domain::scheduler::JobQueue;
+use crate::repository::cron_job::get_cronjob_by_id_db;
+use crate::scheduler::prepare::{judge_time, reload_job_from_sql};
+use crate::scheduler::runner::{dispatch_queued_runs, spawn_run};
+use crate::scheduler::shutdown::Shutdown;
+
+// 与 migrations/init.sql 中 notify_cronjob_change / notify_cronjob_run 使用的 channel 保持一致
+pub const CRONJOB_CHANNEL: &str = "cronjob_changes";
+pub const CRONJOB_RUN_CHANNEL: &str = "cronjob_runs";
+
+
+// 监听cronjobs的增删改，收到通知后立即同步到队列；轮询reload只作为兜底的一致性检查
+pub async fn listen_job_changes<Q: JobQueue>(pool: &PgPool, heap: Q, save_secs: u64, shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen_all([CRONJOB_CHANNEL, CRONJOB_RUN_CHANNEL]).await?;
+    info!("Listening cronjob changes on channel {}, runs on channel {}", CRONJOB_CHANNEL, CRONJOB_RUN_CHANNEL);
+    loop {
+        match listener.try_recv().await? {
+            Some(notification) if notification.channel() == CRONJOB_RUN_CHANNEL => {
+                match notification.payload().parse::<i32>() {
+                    Ok(run_id) => spawn_run(pool.clone(), heap.clone(), run_id, shutdown.clone()),
+                    Err(e) => warn!("Invalid cronjob run payload {}: {}", notification.payload(), e),
+                }
+            }
+            Some(notification) => {
+                let change = match serde_json::from_str::<CronJobChange>(notification.payload()) {
+                    Ok(change) => change,
+                    Err(e) => {
+                        warn!("Invalid cronjob change payload {}: {}", notification.payload(), e);
+                        continue;
+                    }
+                };
+                if let Err(e) = apply_job_change(pool, &heap, &change).await {
+                    error!("Failed to apply cronjob change {:?}: {:?}", change, e);
+                }
+            }
+            None => {
+                // 连接断开，PgListener会自动重连，但断开期间的通知已经丢失，做一次全量补偿
+                warn!("Cronjob listener connection lost, resync jobs from sql");
+                reload_job_from_sql(pool, heap.clone(), save_secs).await?;
+                dispatch_queued_runs(pool, &heap, &shutdown).await?;
+            }
+        }
+    }
+}
+
+
+pub async fn apply_job_change<Q: JobQueue>(pool: &PgPool, heap: &Q, change: &CronJobChange) -> Result<(), anyhow::Error> {
+    match change.op {
+        CronJobOp::Delete => {
+            heap.del_job_pending(change.id).await?;
+        }
+        CronJobOp::Insert | CronJobOp::Update => {
+            let job = get_cronjob_by_id_db(pool, change.id).await?;
+            heap.set_job_meta(job.id, JobMeta::from_job(&job)).await?;
+            // enabled且在保存时间内的定时任务进入队列，其余从pending移除，等待reload
+            if job.enabled && job.is_scheduled() && judge_time(job.next_execute_at) {
+                heap.add_job(job.id, job.next_execute_at.timestamp_millis()).await?;
+            } else {
+                heap.del_job_pending(job.id).await?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,2 +1,4 @@
-
-pub mod prepare;
\ No newline at end of file
+pub mod prepare;
+pub mod listener;
+pub mod runner;
+pub mod shutdown;
\ No newline at end of file
@@ -1,17 +1,17 @@
+use std::collections::HashSet;
 use std::env;
 use futures::future::join_all;
 use chrono::{DateTime, Duration, Utc};
-use cron_parser::parse;
-use log::{info,debug};
+use log::{info,debug,warn};
 use sqlx::PgPool;
-use crate::domain::scheduler::JobScheduler;
-use crate::repository::ssh::batch_server_ssh_back;
-use crate::{domain::ssh_configuration::Message, repository::ssh::{single_server_ssh_back}};
+use crate::domain::scheduler::JobQueue;
 use crate::repository::server::*;
-use crate::utils::crypto::*;
-use crate::domain::server::ServiceTerminal;
-use bytes::Bytes;
-use crate::domain::cron_job::CronJob;
+use crate::domain::server::{require_servers, ServiceTerminal};
+use crate::domain::cron_job::{CronJob, MisfirePolicy, ScheduleType};
+use crate::repository::cron_job::get_cronjob_by_id_db;
+use crate::domain::cron_log::CreateCronLog;
+use crate::domain::queue::{JobMeta, ResyncReport};
+use crate::repository::cron_log::create_cron_log_db;
 use dotenvy::dotenv;
 
 
@@ -51,10 +51,23 @@ pub fn judge_time(time:DateTime<Utc>) -> bool{
 
 // 这里面不用管 enable，任务执行后的善后处理，如果enable关闭 任务不会执行，除非在执行后的同时关闭了enable出现了竞态，概率较小
 // 用于初始化，计算了每个任务的下次时间 并且进行更新
-pub async fn reload_single_job(pool: &PgPool,job_id: i32,heap: JobScheduler) -> Result<(), anyhow::Error>{
-    let job_expression = sqlx::query!("SELECT cron_expression FROM cronjobs where id = $1",job_id).fetch_one(pool).await?;
-    let job_expression = job_expression.cron_expression;
-    let next_time = parse(&job_expression, &Utc::now())?;
+pub async fn reload_single_job<Q: JobQueue>(pool: &PgPool,job_id: i32,heap: Q) -> Result<(), anyhow::Error>{
+    let job = get_cronjob_by_id_db(pool, job_id).await?;
+    match job.schedule_type.parse()? {
+        ScheduleType::Dependency => return Ok(()), // dependency任务不进入队列
+        ScheduleType::Once => return archive_once_job(pool, job_id).await,
+        ScheduleType::Cron | ScheduleType::Interval => {}
+    }
+    let next_time = if job.catchup_remaining > 0 {
+        // 还有misfire补跑的轮次，立即再执行一次
+        info!("job {} catch up, {} runs remaining",job_id,job.catchup_remaining);
+        let _ = sqlx::query!("UPDATE cronjobs SET catchup_remaining = catchup_remaining - 1 WHERE id = $1",job_id).execute(pool).await?;
+        Utc::now()
+    } else {
+        // interval任务从这次执行结束开始计算
+        job.next_schedule_time(Utc::now())?
+            .ok_or_else(|| anyhow::anyhow!("job {} has no next execute time", job_id))?
+    };
     let _ = sqlx::query!("UPDATE cronjobs SET next_execute_at = $1 WHERE id = $2",next_time,job_id).execute(pool).await?;
     // 任务执行成功后，自动更新自己的下次执行时间
     info!("Reloaded job {} next execute time",job_id);
@@ -65,6 +78,13 @@ pub async fn reload_single_job(pool: &PgPool,job_id: i32,heap: JobScheduler) ->
 }
 
 
+// once任务执行过(或错过后按misfire策略跳过)后归档，停用并且不再出现在任务列表中
+pub async fn archive_once_job(pool: &PgPool, job_id: i32) -> Result<(), anyhow::Error> {
+    let _ = sqlx::query!("UPDATE cronjobs SET enabled = false, archived_at = now() WHERE id = $1",job_id).execute(pool).await?;
+    info!("once job {} archived", job_id);
+    record_scheduler_log(pool, job_id, None, "ARCHIVED", "once job finished, archived".to_string()).await
+}
+
 
 // pub async fn init_job_from_sql(pool: &PgPool,heap: JobScheduler) -> Result<(), anyhow::Error>{
 //     let cronjob_id_expression_list = sqlx::query!("SELECT id,cron_expression,enabled FROM cronjobs").fetch_all(pool).await?;
@@ -95,24 +115,55 @@ pub async fn reload_single_job(pool: &PgPool,job_id: i32,heap: JobScheduler) ->
 // }
 
 
+// 把任务优先级、所属group和group的并发上限同步到队列，acquire时使用
+// group的修改没有NOTIFY，由定时reload兜底
+pub async fn sync_queue_meta<Q: JobQueue>(pool: &PgPool, heap: &Q) -> Result<(), anyhow::Error> {
+    let jobs = sqlx::query!("SELECT id, priority, group_id FROM cronjobs WHERE schedule_type <> 'dependency'")
+        .fetch_all(pool)
+        .await?;
+    for job in jobs {
+        heap.set_job_meta(job.id, JobMeta { priority: job.priority, group_id: job.group_id }).await?;
+    }
+    let groups = sqlx::query!("SELECT group_id, max_concurrency FROM groups").fetch_all(pool).await?;
+    for group in groups {
+        heap.set_group_limit(group.group_id, group.max_concurrency).await?;
+    }
+    Ok(())
+}
+
+
 // 初始化操作
-pub async fn init_job_from_sql(pool: &PgPool, heap: JobScheduler) -> Result<(), anyhow::Error> {
-    let cronjob_id_expression_list = sqlx::query!("SELECT id,cron_expression,enabled FROM cronjobs")
+pub async fn init_job_from_sql<Q: JobQueue>(pool: &PgPool, heap: Q) -> Result<(), anyhow::Error> {
+    sync_queue_meta(pool, &heap).await?;
+    let job_list = sqlx::query_as!(CronJob,"SELECT * FROM cronjobs WHERE enabled = true AND archived_at IS NULL AND schedule_type <> 'dependency'")
         .fetch_all(pool)
         .await?;
-    let job_list: Vec<(i32, String)> = cronjob_id_expression_list
-        .into_iter()
-        .filter(|row| row.enabled)
-        .map(|row| (row.id, row.cron_expression))
-        .collect();
+    // 滚动发布时旧worker还在执行的任务留在processing里，由它执行结束后推进，这里不重复入队
+    let processing: HashSet<i32> = heap.list_jobs().await?.processing.iter().map(|entry| entry.job_id).collect();
+    let now = Utc::now();
     let tasks: Vec<_> = job_list
         .into_iter()
-        .map(|(job_id, _job_expression)| {
+        .filter(|job| !processing.contains(&job.id))
+        .map(|job| {
             let pool = pool.clone();  // clone 引用计数
             let heap = heap.clone();
             async move {
-                debug!("job {} reloaded from sql", job_id);
-                reload_single_job(&pool, job_id, heap).await
+                debug!("job {} reloaded from sql", job.id);
+                let grace = Duration::seconds(job.misfire_grace_secs as i64);
+                if job.next_execute_at >= now && job.schedule_type != ScheduleType::Cron.as_str() {
+                    // once/interval的下次执行时间是创建或上次执行结束时定下的，不重新计算
+                    if judge_time(job.next_execute_at) {
+                        heap.add_job(job.id, job.next_execute_at.timestamp_millis()).await?;
+                    }
+                    Ok(())
+                } else if job.next_execute_at >= now {
+                    reload_single_job(&pool, job.id, heap).await
+                } else if job.next_execute_at + grace >= now {
+                    // 在宽限时间内，按原计划补上这一次
+                    heap.add_job(job.id, job.next_execute_at.timestamp_millis()).await
+                } else {
+                    misfire_job(&pool, job, now, heap).await
+                }
             }
         })
         .collect();
@@ -124,6 +175,40 @@ pub async fn init_job_from_sql(pool: &PgPool, heap: JobScheduler) -> Result<(),
 }
 
 
+// 调度器自己做出的决定(misfire、重叠跳过等)也写入任务日志，server_ip记为scheduler
+pub async fn record_scheduler_log(pool: &PgPool, job_id: i32, run_id: Option<i32>, status: &str, output: String) -> Result<(), anyhow::Error> {
+    let job_log = CreateCronLog::new(job_id, run_id, "scheduler".to_string(), status.to_string(), Some(output));
+    create_cron_log_db(pool, job_log).await?;
+    Ok(())
+}
+
+
+// worker停机期间错过的轮次，按任务的misfire策略处理，并把决定写入任务日志
+pub async fn misfire_job<Q: JobQueue>(pool: &PgPool, job: CronJob, now: DateTime<Utc>, heap: Q) -> Result<(), anyhow::Error> {
+    let policy: MisfirePolicy = job.misfire_policy.parse()?;
+    let missed = job.missed_ticks(now)?;
+    let runs = policy.runs(missed.len(), job.misfire_limit);
+    let last = missed.last().copied().unwrap_or(job.next_execute_at);
+    let output = format!(
+        "missed {} runs from {} to {}, policy {}: run {}, skip {}",
+        missed.len(), job.next_execute_at, last, policy.as_str(), runs, missed.len() - runs
+    );
+    warn!("job {} {}", job.id, output);
+    record_scheduler_log(pool, job.id, None, "MISFIRE", output).await?;
+
+    if runs == 0 {
+        let _ = sqlx::query!("UPDATE cronjobs SET catchup_remaining = 0 WHERE id = $1",job.id).execute(pool).await?;
+        return reload_single_job(pool, job.id, heap).await;
+    }
+    // 立即执行一次，剩余的次数在每次执行完后由reload_single_job继续补跑
+    let _ = sqlx::query!(
+        "UPDATE cronjobs SET catchup_remaining = $1, next_execute_at = $2 WHERE id = $3",
+        (runs - 1) as i32, now, job.id
+    ).execute(pool).await?;
+    heap.add_job(job.id, now.timestamp_millis()).await
+}
+
+
 
 // pub async fn reload_job_from_sql(pool: &PgPool,heap: JobScheduler) -> Result<(), anyhow::Error>{
 // let cronjob_id_expression_list = sqlx::query!("SELECT id,cron_expression,enabled FROM cronjobs").fetch_all(pool).await?;
@@ -139,12 +224,15 @@ pub async fn init_job_from_sql(pool: &PgPool, heap: JobScheduler) -> Result<(),
 // }
 
 // 定时 reload。redis存近3min的任务，每1min循环一次数据库
-pub async fn reload_job_from_sql(pool: &PgPool,heap: JobScheduler,save_secs: u64) -> Result<(), anyhow::Error>{
+pub async fn reload_job_from_sql<Q: JobQueue>(pool: &PgPool,heap: Q,save_secs: u64) -> Result<(), anyhow::Error>{
+    sync_queue_meta(pool, &heap).await?;
     let save_time = Utc::now() + Duration::seconds(save_secs as i64);
     let due_job = sqlx::query!(
         r#"
     SELECT id,next_execute_at  FROM cronjobs
     WHERE enabled = true
+    AND archived_at IS NULL
+    AND schedule_type <> 'dependency'
     AND next_execute_at <= $1
     "#,
         save_time
@@ -171,50 +259,56 @@ pub async fn reload_job_from_sql(pool: &PgPool,heap: JobScheduler,save_secs: u64
 
 
 
-
-
-
-pub async fn batch_job_execute(
-    job_id: Option<i32>,
-    pool: &PgPool,
-    msg: CronJob
-) -> Result<tokio::sync::mpsc::Receiver<Result<Bytes, std::io::Error>>, anyhow::Error> {
-    if !msg.enabled{
-        return Err(anyhow::anyhow!("Batch job is not enabled"))
+// 管理接口触发的全量同步：只移除已停用、归档、删除或不再需要调度的任务，补上缺失的，已在队列中的不改执行时间，processing不动
+pub async fn resync_job_from_sql<Q: JobQueue>(pool: &PgPool, heap: Q, save_secs: u64) -> Result<ResyncReport, anyhow::Error> {
+    sync_queue_meta(pool, &heap).await?;
+    let now = Utc::now();
+    let save_time = now + Duration::seconds(save_secs as i64);
+    let jobs = sqlx::query_as!(CronJob,"SELECT * FROM cronjobs WHERE enabled = true AND archived_at IS NULL AND schedule_type <> 'dependency'")
+        .fetch_all(pool)
+        .await?;
+    let scheduled: HashSet<i32> = jobs.iter().map(|job| job.id).collect();
+    let snapshot = heap.list_jobs().await?;
+    let mut report = ResyncReport::default();
+    for entry in &snapshot.pending {
+        if !scheduled.contains(&entry.job_id) {
+            heap.del_job_pending(entry.job_id).await?;
+            report.removed += 1;
+        }
+    }
+    let queued: HashSet<i32> = snapshot.pending.iter().chain(&snapshot.processing).map(|entry| entry.job_id).collect();
+    for job in jobs {
+        if job.next_execute_at > save_time || queued.contains(&job.id) {
+            continue;
+        }
+        let grace = Duration::seconds(job.misfire_grace_secs as i64);
+        if job.next_execute_at + grace >= now {
+            // 同步期间worker可能已经加回队列，以队列中的为准
+            if heap.add_job_nx(job.id, job.next_execute_at.timestamp_millis()).await? {
+                report.added += 1;
+            }
+        } else {
+            // 错过太久的和启动时一样按misfire策略处理
+            misfire_job(pool, job, now, heap.clone()).await?;
+            report.added += 1;
+        }
     }
-    let command = msg.command;
-    
-    // 如果 group_id 不存在，直接返回错误
-    let group_id = msg.group_id.ok_or_else(|| anyhow::anyhow!("group_id is required"))?;
-    
-    let server_list: Vec<ServiceTerminal> = get_server_by_group_id_db(pool, group_id).await
-        .map_err(|e| anyhow::anyhow!("Failed to get server by group_id: {}", e))?;
-    
-    let ssh_user = server_list[0].ssh_user.clone();
-    let password = passwd_decrypt(server_list[0].password.clone())
-        .map_err(|e| anyhow::anyhow!("Failed to change password: {}", e))?;
-    
-    let port = server_list[0].port.to_string();
-    let server_list: Vec<String> = server_list.into_iter().map(|e| e.ip.clone()).collect();
-
-    let msg = Message::new(ssh_user, password, port, None, Some(server_list));
-    let rx = batch_server_ssh_back(job_id, pool,msg, command).await.map_err(|e| anyhow::anyhow!("Failed to get rx: {}", e))?;
-    
-    Ok(rx)
+    info!("Resync jobs from sql, {} added, {} removed", report.added, report.removed);
+    Ok(report)
 }
 
 
 
-pub async fn single_job_execute(job_id:Option<i32>,pool: &PgPool,msg: CronJob) -> Result<(u32,String), anyhow::Error>{
-    if !msg.enabled{
-        return Err(anyhow::anyhow!("Single job is not enabled"))
-    }
-    let command = msg.command;
-    let server_id = msg.server_id.ok_or_else(|| anyhow::anyhow!("server_id is required"))?;
-    let server = get_server_by_id_db(pool, server_id).await
-    .map_err(|e| anyhow::anyhow!("Failed to get server by group_id: {}", e))?;
-    let msg = Message::new(server.ssh_user, server.password.clone(),server.port.to_string(), Some(server.ip),None);
-    let (code,output) = single_server_ssh_back(job_id ,pool,msg, command.clone()).await.map_err(|e| anyhow::anyhow!("Failed to get output: {}", e))?;
-
-    Ok((code,output))
-}
\ No newline at end of file
+
+// 任务的目标server：group任务为组内所有未归档的server，否则为单个server，已归档的不执行
+pub async fn job_servers(pool: &PgPool, msg: &CronJob) -> Result<Vec<ServiceTerminal>, anyhow::Error> {
+    let servers = match (msg.group_id, msg.server_id) {
+        (Some(group_id), _) => get_server_by_group_id_db(pool, group_id).await
+            .map_err(|e| anyhow::anyhow!("Failed to get server by group_id: {}", e))?,
+        (None, Some(server_id)) => vec![get_server_by_id_db(pool, server_id).await
+            .map_err(|e| anyhow::anyhow!("Failed to get server by server_id: {}", e))?]
+            .into_iter().filter(|server| server.archived_at.is_none()).collect(),
+        (None, None) => return Err(anyhow::anyhow!("server_id or group_id is required")),
+    };
+    require_servers(msg.id, servers)
+}
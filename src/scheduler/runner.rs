@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use log::{info, debug, warn, error};
+use sqlx::PgPool;
+use anyhow::Result;
+use chrono::Utc;
+use serde_json::Value;
+use uuid::Uuid;
+use crate::domain::calendar::{blocked_reason, next_allowed_time, CalendarPolicy, CalendarRules};
+use crate::domain::cron_job::{CronJob, OverlapPolicy, ScheduleType};
+use crate::domain::cron_run::{CronRun, LogTarget, RunEnd, RunFollowUp, RunStatus, RunTrigger};
+use crate::domain::playbook::{describe_steps, HostPlaybookResult};
+use crate::domain::retry::RetryPolicy;
+use crate::domain::spread::HostSpread;
+use crate::domain::scheduler::{JobQueue, PROCESSING_TIMEOUT_MS};
+use crate::domain::environment::Environment;
+use crate::domain::ssh_session::{CommandOutput, RemoteCommand, SshFailure};
+use crate::domain::success::SuccessCriteria;
+use crate::domain::server::ServiceTerminal;
+use crate::repository::calendar::{get_group_calendar_rules_db, get_job_calendar_rules_db};
+use crate::repository::command_template::get_command_template_by_id_db;
+use crate::repository::cron_job::get_cronjob_by_id_db;
+use crate::repository::cron_run::*;
+use crate::repository::runbook::get_runbook_by_id_db;
+use crate::repository::dead_letter::create_dead_letter_db;
+use crate::repository::ssh::{servers_playbook_back, servers_ssh_back};
+use crate::repository::playbook::get_playbook_by_id_db;
+use crate::repository::script::get_script_by_id_db;
+use crate::repository::workflow::*;
+use crate::domain::workflow::{downstream_decision, DownstreamDecision, TriggerOn};
+use crate::scheduler::prepare::*;
+use crate::scheduler::shutdown::Shutdown;
+use crate::utils::crypto::passwd_decrypt;
+
+// 任务锁的过期时间，执行期间每 1/3 时间续期一次，worker挂掉后锁自动过期
+const OVERLAP_LOCK_TTL_MS: i64 = 30_000;
+// queue策略下，上一轮没结束时新一轮延后多久再尝试
+const OVERLAP_QUEUE_DELAY_MS: i64 = 1_000;
+const INTERRUPTED_ERROR: &str = "interrupted by worker shutdown, requeued";
+
+// 按重叠策略获取任务锁的结果
+enum JobLock {
+    Free,          // allow策略，不加锁
+    Held(String),  // 持有锁，值为token
+    Busy,          // 上一轮还在执行
+}
+
+// 重试结束后的执行结果
+struct JobOutcome {
+    total: usize,                 // 目标server数
+    failures: Vec<HostFailure>,   // 最终仍然失败的server
+}
+
+// 最终仍然失败的server，保留最后一次执行的输出写入死信
+struct HostFailure {
+    server: String,
+    failure: SshFailure,
+    output: Option<CommandOutput>, // 连接失败、超时等没有输出
+}
+
+impl HostFailure {
+    fn describe(&self) -> String {
+        match &self.output {
+            Some(output) => format!(
+                "[{}] exit code {}\nstdout:\n{}\nstderr:\n{}",
+                self.server, output.exit_code, output.stdout, output.stderr
+            ),
+            None => format!("[{}] {}: {}", self.server, self.failure.class.as_str(), self.failure),
+        }
+    }
+}
+
+
+// 定时触发：队列里到期的任务，执行期间续期processing的死线；出错时也移出processing并推进到下一次执行时间
+pub async fn process_job<Q: JobQueue>(pool: &PgPool, heap: &Q, job_id: i32, shutdown: &Shutdown) -> Result<(),anyhow::Error> {
+    let result = tokio::select! {
+        result = schedule_job(pool, heap, job_id, shutdown) => result,
+        _ = hold_job(heap, job_id) => unreachable!("hold_job never returns"),
+    };
+    if result.is_err() {
+        if let Err(e) = heap.del_job(job_id).await {
+            error!("job {} failed to remove from processing: {:?}", job_id, e);
+        }
+        // 不推进的话next_execute_at停在过去，每次reload都会重新入队出错
+        if let Err(e) = reload_single_job(pool, job_id, heap.clone()).await {
+            error!("job {} failed to reschedule after error: {:?}", job_id, e);
+        }
+    }
+    result
+}
+
+async fn schedule_job<Q: JobQueue>(pool: &PgPool, heap: &Q, job_id: i32, shutdown: &Shutdown) -> Result<()> {
+    info!("job {} start execute", job_id);
+    let msg = get_cronjob_by_id_db(pool, job_id).await?;
+    if !msg.enabled || !msg.is_scheduled() {
+        // 入队后被停用、归档或改为dependency任务，等待重新启用时由NOTIFY/reload加回
+        info!("job {} is not enabled or not scheduled, skipped", job_id);
+        heap.del_job(job_id).await?;
+        return Ok(());
+    }
+    let calendars = get_job_calendar_rules_db(pool, &msg).await?;
+    if let Some(reason) = blocked_reason(&calendars, Utc::now())? {
+        return calendar_job(pool, heap, &msg, &calendars, reason).await;
+    }
+    let policy: OverlapPolicy = msg.overlap_policy.parse()?;
+    let lock = lock_job(heap, job_id, policy).await?;
+    if let JobLock::Busy = lock {
+        return overlap_job(pool, heap, job_id, policy).await;
+    }
+    let run = create_scheduled_run_db(pool, job_id, msg.next_execute_at).await?;
+    let end = tokio::select! {
+        end = run_job(pool, heap, msg.clone(), &run, &lock) => match end {
+            Ok(end) => end,
+            Err(e) => {
+                finish_run(pool, &run, RunStatus::Failed, Some(e.to_string())).await?;
+                return Err(e);
+            }
+        },
+        _ = shutdown.aborted() => {
+            // worker停机超时，这一轮记为取消，任务立即放回pending由其他worker执行
+            interrupt_run(pool, &run).await?;
+            finish_run_db(pool, run.run_id, RunStatus::Cancelled, Some(INTERRUPTED_ERROR.to_string())).await?;
+            if let JobLock::Held(token) = &lock {
+                heap.release_lock(job_id, token).await?;
+            }
+            heap.retry_job(job_id, Utc::now().timestamp_millis()).await?;
+            return Ok(());
+        }
+    };
+    match end.follow_up(msg.disables_on_failure()) {
+        RunFollowUp::Handover => {}
+        RunFollowUp::Disable => {
+            let _ = sqlx::query!("UPDATE cronjobs SET enabled = $1 WHERE id=$2",false,job_id).execute(pool).await?;
+            error!("job {} all retry failed, the job has been disabled by disable_on_failure",job_id);
+            record_scheduler_log(pool, job_id, Some(run.run_id), "DISABLED", "all retry failed, job disabled".to_string()).await?;
+            heap.del_job(job_id).await?;
+        }
+        RunFollowUp::Reschedule => {
+            heap.del_job(job_id).await?;// 任务完成 从processing移除
+            reload_single_job(pool, job_id, heap.clone()).await?;
+        }
+    }
+    Ok(())
+}
+
+
+// 手动触发：认领API入队的运行，与定时运行走同一条执行路径，但不改动队列和next_execute_at
+pub async fn process_run<Q: JobQueue>(pool: &PgPool, heap: &Q, run_id: i32, shutdown: &Shutdown) -> Result<()> {
+    let Some(run) = claim_run_db(pool, run_id).await? else {
+        debug!("run {} already claimed by another worker", run_id);
+        return Ok(());
+    };
+    info!("job {} manual run {} start", run.job_id, run_id);
+    let result = tokio::select! {
+        result = manual_run(pool, heap, &run) => result,
+        _ = shutdown.aborted() => {
+            // worker停机超时，运行放回queued由其他worker重新认领；持有的任务锁等TTL过期
+            interrupt_run(pool, &run).await?;
+            requeue_run_db(pool, run_id).await?;
+            return Ok(());
+        }
+    };
+    if let Err(e) = &result {
+        finish_run(pool, &run, RunStatus::Failed, Some(e.to_string())).await?;
+    }
+    result
+}
+
+async fn manual_run<Q: JobQueue>(pool: &PgPool, heap: &Q, run: &CronRun) -> Result<()> {
+    // 手动运行不受enabled限制，停用的任务也可以手动执行；依赖触发的运行和定时一样需要enabled
+    let msg = get_cronjob_by_id_db(pool, run.job_id).await?;
+    if !msg.enabled && run.trigger_type == RunTrigger::Dependency.as_str() {
+        info!("job {} is not enabled, dependency run {} skipped", msg.id, run.run_id);
+        finish_run_db(pool, run.run_id, RunStatus::Skipped, Some("job is not enabled".to_string())).await?;
+        return Ok(());
+    }
+    // 依赖触发的运行和定时一样受任务日历限制；手动运行只受group的封网限制
+    let calendars = match (run.trigger_type == RunTrigger::Manual.as_str(), msg.group_id) {
+        (false, _) => get_job_calendar_rules_db(pool, &msg).await?,
+        (true, Some(group_id)) => get_group_calendar_rules_db(pool, group_id).await?.into_iter().collect(),
+        (true, None) => Vec::new(),
+    };
+    if let (false, Some(reason)) = (run.dry_run, blocked_reason(&calendars, Utc::now())?) {
+        info!("job {} run {} skipped: {}", msg.id, run.run_id, reason);
+        record_scheduler_log(pool, msg.id, Some(run.run_id), "SKIPPED", reason.clone()).await?;
+        finish_run_db(pool, run.run_id, RunStatus::Skipped, Some(reason)).await?;
+        return Ok(());
+    }
+    if run.dry_run {
+        run_job(pool, heap, msg, run, &JobLock::Free).await?;
+        return Ok(());
+    }
+    let policy: OverlapPolicy = msg.overlap_policy.parse()?;
+    let lock = loop {
+        match lock_job(heap, msg.id, policy).await? {
+            // 手动运行不在队列里，queue策略就地等待上一轮结束
+            JobLock::Busy if policy == OverlapPolicy::Queue => {
+                tokio::time::sleep(tokio::time::Duration::from_millis(OVERLAP_QUEUE_DELAY_MS as u64)).await;
+            }
+            lock => break lock,
+        }
+    };
+    if let JobLock::Busy = lock {
+        info!("job {} previous run still running, manual run {} skipped", msg.id, run.run_id);
+        record_scheduler_log(pool, msg.id, Some(run.run_id), "SKIPPED", "previous run still running".to_string()).await?;
+        finish_run_db(pool, run.run_id, RunStatus::Skipped, Some("previous run still running".to_string())).await?;
+        return Ok(());
+    }
+    run_job(pool, heap, msg, run, &lock).await?;
+    Ok(())
+}
+
+
+// 收到通知之前已经入队、或通知丢失的手动运行，逐个派发，认领由claim_run_db保证只执行一次
+pub async fn dispatch_queued_runs<Q: JobQueue>(pool: &PgPool, heap: &Q, shutdown: &Shutdown) -> Result<()> {
+    if shutdown.is_draining() {
+        return Ok(());
+    }
+    for run_id in get_queued_run_ids_db(pool).await? {
+        spawn_run(pool.clone(), heap.clone(), run_id, shutdown.clone());
+    }
+    Ok(())
+}
+
+// 停机中的worker不再认领，留给其他worker
+pub fn spawn_run<Q: JobQueue>(pool: PgPool, heap: Q, run_id: i32, shutdown: Shutdown) {
+    if shutdown.is_draining() {
+        debug!("worker is shutting down, run {} left for other workers", run_id);
+        return;
+    }
+    let guard = shutdown.track();
+    tokio::spawn(async move {
+        if let Err(e) = process_run(&pool, &heap, run_id, &shutdown).await {
+            error!("Failed to process run {}: {:?}", run_id, e);
+        }
+        drop(guard);
+    });
+}
+
+// 停机超时被中断的运行，执行到一半的ssh会话随future一起drop关闭
+async fn interrupt_run(pool: &PgPool, run: &CronRun) -> Result<()> {
+    warn!("job {} run {} interrupted by worker shutdown", run.job_id, run.run_id);
+    record_scheduler_log(pool, run.job_id, Some(run.run_id), "INTERRUPTED", INTERRUPTED_ERROR.to_string()).await
+}
+
+
+async fn lock_job<Q: JobQueue>(heap: &Q, job_id: i32, policy: OverlapPolicy) -> Result<JobLock> {
+    if policy == OverlapPolicy::Allow {
+        return Ok(JobLock::Free);
+    }
+    // 分布式锁以job id为key，所有worker共用
+    let token = Uuid::new_v4().to_string();
+    let locked = if policy == OverlapPolicy::Replace {
+        heap.force_lock(job_id, &token, OVERLAP_LOCK_TTL_MS).await?;
+        true
+    } else {
+        heap.acquire_lock(job_id, &token, OVERLAP_LOCK_TTL_MS).await?
+    };
+    Ok(if locked { JobLock::Held(token) } else { JobLock::Busy })
+}
+
+
+// 执行一次运行并记录运行状态
+async fn run_job<Q: JobQueue>(pool: &PgPool, heap: &Q, msg: CronJob, run: &CronRun, lock: &JobLock) -> Result<RunEnd> {
+    let job_id = msg.id;
+    // 只有定时运行失败会停用任务，见process_job
+    let disables_job = run.trigger_type == RunTrigger::Schedule.as_str() && msg.disables_on_failure();
+    let result = match lock {
+        JobLock::Held(token) => {
+            let result = tokio::select! {
+                result = execute_job(pool, msg, run) => result,
+                _ = hold_lock(heap, job_id, token) => {
+                    // 锁被新一轮(replace)抢走，drop掉执行中的future，ssh连接随之关闭
+                    warn!("job {} run {} replaced by a newer run, cancelled", job_id, run.run_id);
+                    record_scheduler_log(pool, job_id, Some(run.run_id), "CANCELLED", "replaced by a newer run".to_string()).await?;
+                    finish_run_db(pool, run.run_id, RunStatus::Cancelled, Some("replaced by a newer run".to_string())).await?;
+                    return Ok(RunEnd::Cancelled);
+                }
+            };
+            heap.release_lock(job_id, token).await?;
+            result
+        }
+        _ => execute_job(pool, msg, run).await,
+    };
+    // 执行前出错(没有server、模板渲染失败、找不到脚本或剧本、成功规则有误、secret解密失败等)，和重试耗尽一样处理
+    let (outcome, criteria) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            let error = e.to_string();
+            error!("job {} run {} {}", job_id, run.run_id, error);
+            return fail_run(pool, run, error, None, disables_job).await;
+        }
+    };
+    if outcome.failures.is_empty() {
+        finish_run(pool, run, RunStatus::Success, None).await?;
+        return Ok(RunEnd::Succeeded);
+    }
+    let error = format!(
+        "{}/{} servers failed: {}",
+        outcome.failures.len(),
+        outcome.total,
+        outcome.failures.iter().map(|f| format!("{} {}: {}", f.server, f.failure.class.as_str(), f.failure)).collect::<Vec<_>>().join("; ")
+    );
+    // 失败的server占比在min_success_ratio允许的范围内，整个运行仍算成功，失败信息保留在error中
+    if criteria.is_satisfied(outcome.total - outcome.failures.len(), outcome.total) {
+        warn!("job {} run {} {}", job_id, run.run_id, error);
+        finish_run(pool, run, RunStatus::Success, Some(error)).await?;
+        return Ok(RunEnd::Succeeded);
+    }
+    error!("job {} run {} {}", job_id, run.run_id, error);
+    let output = outcome.failures.iter().map(HostFailure::describe).collect::<Vec<_>>().join("\n");
+    fail_run(pool, run, error, Some(output), disables_job).await
+}
+
+// 只有定时运行的失败进死信，手动和依赖触发的运行失败由触发方查看运行记录；死信写入失败不影响运行状态的记录
+async fn fail_run(pool: &PgPool, run: &CronRun, error: String, output: Option<String>, disables_job: bool) -> Result<RunEnd> {
+    if run.trigger_type == RunTrigger::Schedule.as_str()
+        && let Err(e) = create_dead_letter_db(pool, run.job_id, run.run_id, error.clone(), output, disables_job).await
+    {
+        error!("job {} run {} failed to create dead letter: {:?}", run.job_id, run.run_id, e);
+    }
+    finish_run(pool, run, RunStatus::Failed, Some(error)).await?;
+    Ok(RunEnd::Failed)
+}
+
+// 记录运行的最终状态，然后按依赖触发下游
+async fn finish_run(pool: &PgPool, run: &CronRun, status: RunStatus, error: Option<String>) -> Result<()> {
+    finish_run_db(pool, run.run_id, status, error).await?;
+    if let Err(e) = trigger_downstream(pool, run).await {
+        error!("job {} run {} failed to trigger downstream: {:?}", run.job_id, run.run_id, e);
+    }
+    Ok(())
+}
+
+// 下游的所有上游都在本工作流中结束后才决定是否运行，下游运行以queued入队，由worker认领执行
+async fn trigger_downstream(pool: &PgPool, run: &CronRun) -> Result<()> {
+    let downstream_ids = get_downstream_ids_db(pool, run.job_id).await?;
+    if downstream_ids.is_empty() {
+        return Ok(());
+    }
+    let workflow_run_id = run.workflow_run_id.unwrap_or(run.run_id);
+    // 多个上游同时结束时串行判断，后结束的一方一定能看到先结束的状态
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)").bind(workflow_run_id as i64).execute(&mut *tx).await?;
+    for downstream_id in downstream_ids {
+        let mut conditions = Vec::new();
+        for dependency in get_dependencies_db(&mut tx, downstream_id).await? {
+            let status = get_workflow_job_status_db(&mut tx, workflow_run_id, dependency.upstream_id).await?;
+            conditions.push((dependency.trigger_on.parse::<TriggerOn>()?, status));
+        }
+        match downstream_decision(&conditions) {
+            DownstreamDecision::Run => {
+                if let Some(downstream) = create_downstream_run_db(&mut tx, downstream_id, workflow_run_id, run.run_id, run.dry_run).await? {
+                    info!("job {} run {} triggered downstream job {} run {}", run.job_id, run.run_id, downstream_id, downstream.run_id);
+                }
+            }
+            DownstreamDecision::Skip => debug!("job {} run {} downstream job {} condition not met", run.job_id, run.run_id, downstream_id),
+            DownstreamDecision::Wait => debug!("job {} run {} downstream job {} waiting for other upstreams", run.job_id, run.run_id, downstream_id),
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+// 在目标server上执行，按任务的成功规则判断每台server，失败的server按重试策略只重试失败的部分
+async fn execute_job(pool: &PgPool, msg: CronJob, run: &CronRun) -> Result<(JobOutcome, SuccessCriteria)> {
+    let criteria = SuccessCriteria::from_job(&msg)?;
+    // 只有定时运行按jitter/spread错开启动，手动和依赖触发的运行立即执行
+    let spread = if run.trigger_type == RunTrigger::Schedule.as_str() { HostSpread::from_job(&msg) } else { HostSpread::default() };
+    let servers = job_servers(pool, &msg).await?;
+    // 手动运行指定了command时只执行这条命令，不执行剧本或脚本
+    let playbook = match (msg.playbook_id, &run.command) {
+        (Some(playbook_id), None) => Some(get_playbook_by_id_db(pool, playbook_id).await?),
+        _ => None,
+    };
+    // 脚本内容作为stdin发送，每台server执行同一条解释器命令
+    let mut commands = match (&playbook, msg.script_id, &run.command) {
+        (Some(playbook), _, _) => vec![RemoteCommand::new(format!("playbook {}: {}", playbook.name, describe_steps(&playbook.steps))); servers.len()],
+        (None, Some(script_id), None) => {
+            let script = get_script_by_id_db(pool, script_id).await?;
+            let command = script.invocation(msg.script_args.as_deref())?;
+            vec![RemoteCommand { input: Some(Arc::new(script.body)), ..RemoteCommand::new(command) }; servers.len()]
+        }
+        _ => job_commands(pool, &msg, run, &servers).await?,
+    };
+    // 任务上的环境变量覆盖模板里的
+    let env = job_environment(&msg)?;
+    for command in commands.iter_mut() {
+        command.env = std::mem::take(&mut command.env).merge(env.clone());
+    }
+    if run.dry_run {
+        dry_run_job(pool, &msg, run.run_id, spread, &servers, &commands).await?;
+        return Ok((JobOutcome { total: 0, failures: Vec::new() }, criteria));
+    }
+    let policy = RetryPolicy::from_job(&msg)?;
+    let privilege = msg.become_spec()?;
+    let target = Some(LogTarget::new(msg.id, Some(run.run_id)));
+    let total = servers.len();
+    // 重试已经有自己的等待，只在第一次执行时错开
+    let mut delays = spread.delays(msg.id, &servers.iter().map(|s| s.ip.as_str()).collect::<Vec<_>>());
+    let mut hosts: Vec<(ServiceTerminal, RemoteCommand)> = servers.into_iter().zip(commands).collect();
+    let mut failures = Vec::new(); // 不再重试的失败
+    let mut attempt = 0;
+    loop {
+        let (servers, commands): (Vec<_>, Vec<_>) = hosts.iter().cloned().unzip();
+        // 剧本每台server一个连接执行所有步骤，步骤的输出已经写入日志，按任务的成功规则判断合并后的结果
+        let results = match &playbook {
+            Some(playbook) => servers_playbook_back(target, pool, servers, std::mem::take(&mut delays), &playbook.steps, privilege.clone(), env.clone()).await
+                .into_iter().map(HostPlaybookResult::into_host_result).collect(),
+            None => servers_ssh_back(target, pool, servers, std::mem::take(&mut delays), commands, privilege.clone()).await,
+        };
+        let mut retry = Vec::new();
+        for (host, result) in hosts.into_iter().zip(results) {
+            if let Some(failure) = criteria.check(&result) {
+                if attempt < policy.max_retries && policy.should_retry(&failure) {
+                    retry.push(host);
+                } else {
+                    failures.push(HostFailure { server: result.server, failure, output: result.result.ok() });
+                }
+            }
+        }
+        if retry.is_empty() {
+            return Ok((JobOutcome { total, failures }, criteria));
+        }
+        attempt += 1;
+        let delay = policy.delay(attempt);
+        let output = format!(
+            "retry {}/{} in {}ms on {}",
+            attempt, policy.max_retries, delay.as_millis(),
+            retry.iter().map(|(s, _)| s.ip.as_str()).collect::<Vec<_>>().join(", ")
+        );
+        info!("job {} run {} {}", msg.id, run.run_id, output);
+        record_scheduler_log(pool, msg.id, Some(run.run_id), "RETRY", output).await?;
+        tokio::time::sleep(delay).await;
+        hosts = retry;
+    }
+}
+
+// 每台server实际执行的命令：手动运行指定的command优先，其次按主机渲染任务引用的模板、命令库里的命令，否则使用任务的command
+// 模板参数按 运行请求 > 任务定义 > 主机标签 > 默认值 的顺序取值，模板里的环境变量和工作目录同样按主机渲染；命令库的修改在下一次运行时生效
+async fn job_commands(pool: &PgPool, msg: &CronJob, run: &CronRun, servers: &[ServiceTerminal]) -> Result<Vec<RemoteCommand>> {
+    if let Some(command) = &run.command {
+        return Ok(vec![RemoteCommand::new(command.clone()); servers.len()]);
+    }
+    if let Some(runbook_id) = msg.runbook_id {
+        let runbook = get_runbook_by_id_db(pool, runbook_id).await?;
+        return Ok(vec![RemoteCommand::new(runbook.body); servers.len()]);
+    }
+    let Some(template_id) = msg.template_id else {
+        return Ok(vec![RemoteCommand::new(msg.command.clone()); servers.len()]);
+    };
+    let template = get_command_template_by_id_db(pool, template_id).await?;
+    let run_values = run.template_params.as_ref().and_then(Value::as_object).cloned().unwrap_or_default();
+    let job_values = msg.template_params.as_object().cloned().unwrap_or_default();
+    servers.iter().map(|server| {
+        let values = [&run_values, &job_values];
+        let render = || -> Result<RemoteCommand> {
+            let command = template.render(&values, Some(server))?;
+            Ok(RemoteCommand { env: template.render_env(&values, Some(server))?, ..RemoteCommand::new(command) })
+        };
+        render().map_err(|e| anyhow::anyhow!("template {} on {}: {}", template_id, server.ip, e))
+    }).collect()
+}
+
+// 任务上的环境变量和工作目录，secret的值在这里解密，只在执行时使用
+fn job_environment(msg: &CronJob) -> Result<Environment> {
+    let vars: HashMap<String, String> = serde_json::from_value(msg.env.clone())?;
+    let secrets: HashMap<String, String> = serde_json::from_value(msg.secret_env.clone())?;
+    let mut env = Environment::new(vars, msg.workdir.clone());
+    env.add_secrets(secrets.into_iter().map(|(name, value)| Ok((name, passwd_decrypt(value)?))).collect::<Result<_>>()?);
+    Ok(env)
+}
+
+// 只解析出目标server并记录每台将要执行的命令，不建立ssh连接
+async fn dry_run_job(pool: &PgPool, msg: &CronJob, run_id: i32, spread: HostSpread, servers: &[ServiceTerminal], commands: &[RemoteCommand]) -> Result<()> {
+    let ips: Vec<&str> = servers.iter().map(|s| s.ip.as_str()).collect();
+    let delays = spread.delays(msg.id, &ips);
+    let targets: Vec<String> = ips.iter().zip(commands).zip(delays).map(|((ip, command), delay)| {
+        if delay.is_zero() { format!("`{}` on {}", command.command, ip) } else { format!("`{}` on {} (+{}ms)", command.command, ip, delay.as_millis()) }
+    }).collect();
+    let output = format!("dry run: {}", targets.join(", "));
+    info!("job {} run {} {}", msg.id, run_id, output);
+    record_scheduler_log(pool, msg.id, Some(run_id), "DRY_RUN", output).await
+}
+
+// 执行期间定时续期processing的死线，避免reload把还在执行的任务当作超时放回pending
+// 任务被移回pending(retry_job)后续期不再生效，直到执行结束都不返回
+async fn hold_job<Q: JobQueue>(heap: &Q, job_id: i32) {
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_millis((PROCESSING_TIMEOUT_MS / 3) as u64));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(e) = heap.renew_job(job_id).await {
+            warn!("job {} renew processing deadline failed: {:?}", job_id, e);
+        }
+    }
+}
+
+// 持有锁期间定时续期，锁不再属于自己时返回
+async fn hold_lock<Q: JobQueue>(heap: &Q, job_id: i32, token: &str) {
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_millis((OVERLAP_LOCK_TTL_MS / 3) as u64));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match heap.renew_lock(job_id, token, OVERLAP_LOCK_TTL_MS).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => warn!("job {} renew lock failed: {:?}", job_id, e),
+        }
+    }
+}
+
+// 执行时间不在日历允许的范围内，按任务的日历策略跳过或延后到下一个允许的时间，决定写入任务日志
+async fn calendar_job<Q: JobQueue>(pool: &PgPool, heap: &Q, msg: &CronJob, calendars: &[CalendarRules], reason: String) -> Result<()> {
+    let policy: CalendarPolicy = msg.calendar_policy.parse()?;
+    // once任务跳过后不会再有下一次，不管策略都延后
+    let once = msg.schedule_type.parse::<ScheduleType>()? == ScheduleType::Once;
+    if policy == CalendarPolicy::Delay || once {
+        if let Some(next_time) = next_allowed_time(calendars, Utc::now())? {
+            info!("job {} {}, delayed to {}", msg.id, reason, next_time);
+            record_scheduler_log(pool, msg.id, None, "DELAYED", format!("{}, delayed to {}", reason, next_time)).await?;
+            // 同步更新next_execute_at，避免reload按原时间再次加回队列
+            let _ = sqlx::query!("UPDATE cronjobs SET next_execute_at = $1 WHERE id = $2", next_time, msg.id).execute(pool).await?;
+            heap.retry_job(msg.id, next_time.timestamp_millis()).await?;
+            return Ok(());
+        }
+        warn!("job {} {}, no allowed time found, skipped", msg.id, reason);
+    }
+    if once {
+        // 没有执行过，只停用不归档，改run_at或重新启用后还能执行
+        record_scheduler_log(pool, msg.id, None, "SKIPPED", format!("{}, no allowed time found, disabled", reason)).await?;
+        let _ = sqlx::query!("UPDATE cronjobs SET enabled = false WHERE id = $1", msg.id).execute(pool).await?;
+        heap.del_job(msg.id).await?;
+        return Ok(());
+    }
+    info!("job {} {}, skipped", msg.id, reason);
+    record_scheduler_log(pool, msg.id, None, "SKIPPED", reason).await?;
+    heap.del_job(msg.id).await?;
+    reload_single_job(pool, msg.id, heap.clone()).await
+}
+
+// 上一轮还在执行，按重叠策略处理新一轮
+async fn overlap_job<Q: JobQueue>(pool: &PgPool, heap: &Q, job_id: i32, policy: OverlapPolicy) -> Result<()> {
+    match policy {
+        OverlapPolicy::Queue => {
+            debug!("job {} previous run still running, queued", job_id);
+            let retry_at = Utc::now().timestamp_millis() + OVERLAP_QUEUE_DELAY_MS;
+            heap.retry_job(job_id, retry_at).await?;
+        }
+        _ => {
+            info!("job {} previous run still running, skipped", job_id);
+            record_scheduler_log(pool, job_id, None, "SKIPPED", "previous run still running".to_string()).await?;
+            heap.del_job(job_id).await?;
+            reload_single_job(pool, job_id, heap.clone()).await?;
+        }
+    }
+    Ok(())
+}
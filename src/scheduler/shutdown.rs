@@ -0,0 +1,102 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+// worker停机的阶段，只会向后推进
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShutdownPhase {
+    Running,
+    Draining,  // 不再取新任务，等待执行中的运行结束
+    Aborting,  // 超过drain时间，中断剩余的运行并放回队列
+}
+
+/// worker的停机状态和执行中的运行数，clone后分发给取任务的循环、监听和每个运行
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    phase: Arc<watch::Sender<ShutdownPhase>>,
+    in_flight: Arc<watch::Sender<usize>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            phase: Arc::new(watch::Sender::new(ShutdownPhase::Running)),
+            in_flight: Arc::new(watch::Sender::new(0)),
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.phase.borrow() >= ShutdownPhase::Draining
+    }
+
+    pub fn drain(&self) {
+        self.phase.send_if_modified(|phase| advance(phase, ShutdownPhase::Draining));
+    }
+
+    pub fn abort(&self) {
+        self.phase.send_if_modified(|phase| advance(phase, ShutdownPhase::Aborting));
+    }
+
+    /// 进入Aborting阶段时返回，执行中的运行据此中断
+    pub async fn aborted(&self) {
+        let mut phase = self.phase.subscribe();
+        let _ = phase.wait_for(|phase| *phase == ShutdownPhase::Aborting).await;
+    }
+
+    /// 登记一个执行中的运行，guard drop时注销
+    pub fn track(&self) -> InFlightGuard {
+        self.in_flight.send_modify(|count| *count += 1);
+        InFlightGuard { in_flight: Arc::clone(&self.in_flight) }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        *self.in_flight.borrow()
+    }
+
+    /// 等待所有运行结束，超时返回false
+    pub async fn wait_idle(&self, timeout: Duration) -> bool {
+        let mut in_flight = self.in_flight.subscribe();
+        tokio::time::timeout(timeout, in_flight.wait_for(|count| *count == 0)).await.is_ok()
+    }
+}
+
+fn advance(phase: &mut ShutdownPhase, next: ShutdownPhase) -> bool {
+    if *phase >= next {
+        return false;
+    }
+    *phase = next;
+    true
+}
+
+pub struct InFlightGuard {
+    in_flight: Arc<watch::Sender<usize>>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.send_modify(|count| *count -= 1);
+    }
+}
+
+/// 等待SIGTERM(滚动发布、systemctl stop)或Ctrl-C，返回收到的信号名
+pub async fn shutdown_signal() -> Result<&'static str, anyhow::Error> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|_| "Ctrl-C").map_err(Into::into),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        Ok("Ctrl-C")
+    }
+}
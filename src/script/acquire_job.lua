@@ -2,31 +2,76 @@
 
 -- KEYS[1]: pending_queue (等待执行的任务 ZSet)
 -- KEYS[2]: processing_queue (正在执行的任务 ZSet，用于容错)
+-- KEYS[3]: priority_hash (job_id -> 优先级，缺省为 0)
+-- KEYS[4]: job_group_hash (job_id -> group_id)
+-- KEYS[5]: group_limit_hash (group_id -> 同时执行的任务数上限)
 -- ARGV[1]: current_ts (当前时间戳，毫秒)
 -- ARGV[2]: timeout_ms (任务超时时间，比如 30000ms)
+-- ARGV[3]: scan_limit (最多从多少个到期任务中挑选)
 
 local pending_key = KEYS[1]
 local processing_key = KEYS[2]
+local priority_key = KEYS[3]
+local job_group_key = KEYS[4]
+local group_limit_key = KEYS[5]
 local current_ts = tonumber(ARGV[1])
 local timeout_ms = tonumber(ARGV[2])
+local scan_limit = tonumber(ARGV[3])
 
--- 1. 查询 Pending 队列中，分数小于等于当前时间的第一个任务
+-- 1. 查询 Pending 队列中，分数小于等于当前时间的任务，按到期时间排序
 -- ZRANGEBYSCORE key min max LIMIT offset count
-local jobs = redis.call('ZRANGEBYSCORE', pending_key, '-inf', current_ts, 'LIMIT', 0, 1)
+local jobs = redis.call('ZRANGEBYSCORE', pending_key, '-inf', current_ts, 'LIMIT', 0, scan_limit)
 
-if #jobs > 0 then
-    local job_id = jobs[1]
+if #jobs == 0 then
+    -- 没任务，返回空
+    return nil
+end
 
-    -- 2. 计算超时死线 (Deadline)
-    local deadline = current_ts + timeout_ms
+-- 2. 统计每个 group 正在执行的任务数，只在设置了上限时需要
+-- 超过死线的记录属于已经退出的 worker，不占 group 的名额
+local running = {}
+if redis.call('HLEN', group_limit_key) > 0 then
+    local processing = redis.call('ZRANGEBYSCORE', processing_key, '(' .. current_ts, '+inf')
+    for _, id in ipairs(processing) do
+        local group_id = redis.call('HGET', job_group_key, id)
+        if group_id then
+            running[group_id] = (running[group_id] or 0) + 1
+        end
+    end
+end
 
-    -- 3. 原子移动：先从 Pending 删掉，再加到 Processing
-    redis.call('ZREM', pending_key, job_id)
-    redis.call('ZADD', processing_key, deadline, job_id)
+-- 3. 跳过 group 已满的任务，剩下的取优先级最高的，同优先级取最早到期的
+local job_id = nil
+local best_priority = nil
+for _, id in ipairs(jobs) do
+    local allowed = true
+    local group_id = redis.call('HGET', job_group_key, id)
+    if group_id then
+        local limit = redis.call('HGET', group_limit_key, group_id)
+        if limit and (running[group_id] or 0) >= tonumber(limit) then
+            allowed = false
+        end
+    end
+    if allowed then
+        local priority = tonumber(redis.call('HGET', priority_key, id) or '0')
+        if best_priority == nil or priority > best_priority then
+            job_id = id
+            best_priority = priority
+        end
+    end
+end
 
-    -- 4. 返回抢到的 Job ID
-    return job_id
-else
-    -- 没任务，返回空
+if job_id == nil then
+    -- 到期的任务所在 group 都已满
     return nil
 end
+
+-- 4. 计算超时死线 (Deadline)
+local deadline = current_ts + timeout_ms
+
+-- 5. 原子移动：先从 Pending 删掉，再加到 Processing
+redis.call('ZREM', pending_key, job_id)
+redis.call('ZADD', processing_key, deadline, job_id)
+
+-- 6. 返回抢到的 Job ID
+return job_id
@@ -0,0 +1,11 @@
+-- release_lock.lua
+
+-- KEYS[1]: lock_key (任务锁，值为持有者 token)
+-- ARGV[1]: token
+
+-- 只释放自己持有的锁，避免误删别的 worker 的锁
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+else
+    return 0
+end
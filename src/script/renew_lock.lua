@@ -0,0 +1,12 @@
+-- renew_lock.lua
+
+-- KEYS[1]: lock_key (任务锁，值为持有者 token)
+-- ARGV[1]: token
+-- ARGV[2]: ttl_ms (续期时长，毫秒)
+
+-- 只有锁仍然属于自己时才续期，被 replace 抢走后返回 0
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+else
+    return 0
+end